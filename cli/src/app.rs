@@ -1,10 +1,16 @@
 use super::event::{Event, Events};
+use super::minutecat::ack::{current_user, Ack};
+use super::minutecat::command::{AckCommand, Command};
 use super::minutecat::error::Error;
 use super::minutecat::interface::Interface;
-use super::minutecat::trigger::TriggerType;
+use super::minutecat::metrics::{render, MetricsServer};
+use super::minutecat::silence::format_local_time;
+use super::minutecat::task::{ClockTimeSource, TimeSource};
+use super::minutecat::trigger::{TriggerMatch, TriggerType};
 use super::tab::TabManager;
 use chrono::prelude::DateTime;
 use chrono::Local;
+use std::fmt;
 use std::time::{Duration, UNIX_EPOCH};
 use termion::event::Key;
 use tui::{
@@ -16,6 +22,21 @@ use tui::{
     Frame, Terminal,
 };
 
+/// the color of a severity in tabs and info
+pub fn severity_color(trigger_type: TriggerType) -> Color {
+    match trigger_type {
+        TriggerType::NoEvent => Color::White,
+        TriggerType::Debug => Color::Gray,
+        TriggerType::Success => Color::Green,
+        TriggerType::Info => Color::Cyan,
+        TriggerType::Notice => Color::Blue,
+        TriggerType::Warning => Color::Yellow,
+        TriggerType::Error => Color::Red,
+        TriggerType::Critical => Color::LightRed,
+        TriggerType::Fatal => Color::Magenta,
+    }
+}
+
 pub struct App<B>
 where
     B: Backend,
@@ -24,6 +45,7 @@ where
     pub interface: Interface,
     terminal: Terminal<B>,
     events: Events,
+    metrics: Option<MetricsServer>,
 }
 
 impl<B> App<B>
@@ -36,23 +58,26 @@ where
             interface,
             terminal,
             events: Events::new(),
+            metrics: None,
         }
     }
 
     pub async fn update_logs(interface: &mut Interface, tabs: &mut TabManager, force: bool) {
-        let iter = interface.logset.slice_mut().iter_mut();
-
-        for (index, log) in &mut iter.enumerate() {
+        // pick up acks and silences of other processes
+        if let Err(err) = interface.logset.load_state() {
+            Self::report_error(tabs, &err);
+        }
+        for index in 0..interface.logset.len() {
             // update logs
             // TODO handle errors better!
-
-            let res = if force {
-                log.force_update(&mut vec![&mut tabs.state[index]]).await
-            } else {
-                log.update(&mut vec![&mut tabs.state[index]]).await
-            };
+            let res = interface
+                .logset
+                .update_log(index, &mut vec![&mut tabs.state[index]], force)
+                .await;
             match res {
-                Ok(_) => {}
+                Ok(_) => {
+                    tabs.state[index].trigger_type = interface.logset.logs[index].status.severity
+                }
                 Err(err) => {
                     tabs.state[index]
                         .slices
@@ -60,10 +85,71 @@ where
                 }
             }
         }
+        // send digests of this cycle
+        interface.logset.flush();
+        // e.g. webhooks that could not be delivered
+        for err in interface.logset.take_errors() {
+            Self::report_error(tabs, &err);
+        }
+        if let Err(err) = interface.logset.save_state() {
+            Self::report_error(tabs, &err);
+        }
+    }
+
+    /// shows an error that is not caused by a single log in every tab
+    fn report_error(tabs: &mut TabManager, err: &dyn fmt::Display) {
+        for tab in &mut tabs.state {
+            tab.slices.insert("Error".into(), format!("{}", err));
+        }
+    }
+
+    /// acks every firing trigger of the current tab
+    /// or removes the acks if all of them are acked
+    pub fn toggle_ack(interface: &mut Interface, tabs: &mut TabManager) {
+        let index = tabs.index;
+        let (log, tab) = match (
+            interface.logset.logs.get_mut(index),
+            tabs.state.get_mut(index),
+        ) {
+            (Some(log), Some(tab)) => (log, tab),
+            _ => return,
+        };
+
+        let active = tab.active();
+        let ack = if active.iter().all(|t| tab.acks.contains_key(t)) {
+            None
+        } else {
+            Some(Ack::new(&current_user(), ClockTimeSource.get_time_ms()))
+        };
+        for trigger in active {
+            if let Err(err) = AckCommand::new(&trigger, ack.clone()).execute(log) {
+                tab.slices.insert("Error".into(), format!("{}", err));
+                continue;
+            }
+            match &ack {
+                Some(ack) => tab.acks.insert(trigger, ack.clone()),
+                _ => tab.acks.remove(&trigger),
+            };
+        }
+
+        // keep the ack even if minutecat exits before the next update
+        if let Err(err) = interface.logset.save_state() {
+            Self::report_error(tabs, &err);
+        }
+    }
+
+    fn update_metrics(&self) {
+        if let Some(metrics) = &self.metrics {
+            metrics.update(render(&self.interface.logset));
+        }
     }
 
     pub async fn init(&mut self) -> Result<(), Error> {
+        if let Some(listen) = &self.interface.logset.metrics {
+            self.metrics = Some(MetricsServer::start(listen)?);
+        }
         Self::update_logs(&mut self.interface, &mut self.tabs, true).await;
+        self.update_metrics();
 
         Ok(())
     }
@@ -71,6 +157,7 @@ where
     pub async fn update(&mut self) -> Result<bool, Error> {
         // do forever
         Self::update_logs(&mut self.interface, &mut self.tabs, false).await;
+        self.update_metrics();
 
         async {
             let next_event = match self.events.next() {
@@ -89,6 +176,8 @@ where
                     Key::Down => self.tabs.up(),
                     Key::PageUp | Key::Char('>') => self.tabs.next_offset(),
                     Key::PageDown | Key::Char('<') => self.tabs.prev_offset(),
+                    Key::Char('m') => self.tabs.toggle_matches(),
+                    Key::Char('a') => Self::toggle_ack(&mut self.interface, &mut self.tabs),
                     _ => {}
                 }
             }
@@ -111,12 +200,7 @@ where
             .map(|(i, t)| {
                 let tab = &tab_manager.state[tab_manager.tab_offset + i];
 
-                let color = match tab.trigger_type {
-                    TriggerType::Success => Color::Green,
-                    TriggerType::Warning => Color::Yellow,
-                    TriggerType::Error => Color::Red,
-                    _ => Color::White,
-                };
+                let color = severity_color(tab.trigger_type);
                 Spans::from(vec![Span::styled(&t.name, Style::default().fg(color))])
             })
             .collect();
@@ -153,13 +237,37 @@ where
         let d = UNIX_EPOCH + Duration::from_millis(log.next_time as u64);
         let datetime = DateTime::<Local>::from(d);
 
+        let (title, text) = if tab_manager.show_matches {
+            // list every match ordered by position
+            let mut matches: Vec<(&String, &TriggerMatch)> = log
+                .matches
+                .iter()
+                .flat_map(|(name, matches)| matches.iter().map(move |m| (name, m)))
+                .collect();
+            matches.sort_by_key(|(name, m)| (m.start, *name));
+
+            let lines: Vec<String> = matches
+                .iter()
+                .map(|(name, m)| format!("{}: {} {}", m.line, name, m.text))
+                .collect();
+            (
+                format!("{} Matches ({})", &log.name, matches.len()),
+                Text::raw(lines.join("\n")),
+            )
+        } else {
+            (
+                format!(
+                    "{} Next: {}",
+                    &log.name,
+                    datetime.format("%Y-%m-%d %H:%M:%S")
+                ),
+                Text::raw(log.text.as_str()),
+            )
+        };
+
         // render content
-        let content = Paragraph::new(Text::raw(&log.text))
-            .block(Block::default().borders(Borders::ALL).title(format!(
-                "{} Next: {}",
-                &log.name,
-                datetime.format("%Y-%m-%d %H:%M:%S")
-            )))
+        let content = Paragraph::new(text)
+            .block(Block::default().borders(Borders::ALL).title(title))
             .wrap(Wrap { trim: true })
             .alignment(Alignment::Left)
             .scroll(tab_manager.scroll);
@@ -175,14 +283,25 @@ where
             .iter()
             .enumerate()
             .map(|(_i, t)| {
-                let color = match tab.trigger_type {
-                    TriggerType::Success => Color::Green,
-                    TriggerType::Warning => Color::Yellow,
-                    TriggerType::Error => Color::Red,
-                    _ => Color::White,
+                // resolved triggers are dimmed
+                if tab.resolved.contains(t.0) {
+                    return Spans::from(vec![Span::styled(
+                        format!("{}={} (resolved)", t.0, t.1),
+                        Style::default().fg(Color::DarkGray),
+                    )]);
+                }
+                let color = severity_color(tab.trigger_type);
+                let silenced = if tab.silenced.contains(t.0) {
+                    " (silenced)"
+                } else {
+                    ""
+                };
+                let ack = match tab.acks.get(t.0) {
+                    Some(ack) => format!(" (acked by {} {})", ack.by, format_local_time(ack.at)),
+                    _ => "".into(),
                 };
                 Spans::from(vec![Span::styled(
-                    format!("{}={}", t.0, t.1),
+                    format!("{}={}{}{}", t.0, t.1, silenced, ack),
                     Style::default().fg(color),
                 )])
             })
@@ -206,10 +325,11 @@ where
     }
 
     pub fn render_help(f: &mut Frame<B>, _tab_manager: &TabManager, chunk: &Rect) {
-        let content = Paragraph::new("Left/Right/Up/Down | Q: Exit | PAGE UP/DOWN")
-            .block(Block::default().borders(Borders::ALL).title("Help"))
-            .wrap(Wrap { trim: true })
-            .alignment(Alignment::Left);
+        let content =
+            Paragraph::new("Left/Right/Up/Down | Q: Exit | PAGE UP/DOWN | M: Matches | A: Ack")
+                .block(Block::default().borders(Borders::ALL).title("Help"))
+                .wrap(Wrap { trim: true })
+                .alignment(Alignment::Left);
         f.render_widget(content, *chunk);
     }
 
@@ -1,12 +1,22 @@
+use super::minutecat::ack::Ack;
 use super::minutecat::logfile::{Event, EventHandler};
+use super::minutecat::policy::Transition;
 use super::minutecat::task::TimeMs;
-use super::minutecat::trigger::TriggerType;
-use std::collections::HashMap;
+use super::minutecat::trigger::{TriggerMatch, TriggerType};
+use std::collections::{HashMap, HashSet};
 
 #[derive(Clone)]
 pub struct TabState {
+    /// the severity of the log's status
     pub trigger_type: TriggerType,
     pub slices: HashMap<String, String>,
+    pub matches: HashMap<String, Vec<TriggerMatch>>,
+    /// triggers that stopped firing, their slices are kept
+    pub resolved: HashSet<String>,
+    /// firing triggers that match a silence or maintenance window
+    pub silenced: HashSet<String>,
+    /// acks of the triggers
+    pub acks: HashMap<String, Ack>,
     pub text: String,
     pub name: String,
     pub next_time: TimeMs,
@@ -19,6 +29,10 @@ impl TabState {
             name: "".into(),
             trigger_type: TriggerType::NoEvent,
             slices: HashMap::new(),
+            matches: HashMap::new(),
+            resolved: HashSet::new(),
+            silenced: HashSet::new(),
+            acks: HashMap::new(),
             next_time: 0,
         }
     }
@@ -30,6 +44,7 @@ pub struct TabManager {
     pub index: usize,
     pub scroll: (u16, u16),
     pub tab_offset: usize,
+    pub show_matches: bool,
 }
 
 impl TabManager {
@@ -40,6 +55,7 @@ impl TabManager {
             max,
             scroll: (0, 0),
             tab_offset: 0,
+            show_matches: false,
         }
     }
 
@@ -73,6 +89,11 @@ impl TabManager {
         }
     }
 
+    pub fn toggle_matches(&mut self) {
+        self.scroll = (0, 0);
+        self.show_matches = !self.show_matches;
+    }
+
     pub fn next_offset(&mut self) {
         if self.tab_offset >= self.max - 1 {
             self.tab_offset = 0;
@@ -90,17 +111,50 @@ impl TabManager {
     }
 }
 
+impl TabState {
+    /// names of the triggers that currently fire
+    pub fn active(&self) -> Vec<String> {
+        self.slices
+            .keys()
+            .filter(|k| !self.resolved.contains(*k) && self.matches.contains_key(*k))
+            .cloned()
+            .collect()
+    }
+}
+
 impl EventHandler for TabState {
     fn on_event(&mut self, event: &Event) {
         if let Some(trigger) = event.trigger {
+            match &event.ack {
+                Some(ack) => self.acks.insert(trigger.name().into(), ack.clone()),
+                _ => self.acks.remove(trigger.name()),
+            };
             if event.did_trigger {
-                self.slices.insert(
-                    trigger.name().into(),
-                    trigger.slice(event.text).unwrap_or("").into(),
-                );
-                self.trigger_type = trigger.get_type();
-            } else if self.slices.contains_key(trigger.name()) {
-                self.slices.remove(trigger.name());
+                // prefer named captures over the raw slice
+                let slice = if event.captures.is_empty() {
+                    event.slice.to_string()
+                } else {
+                    let mut captures: Vec<String> = event
+                        .captures
+                        .iter()
+                        .map(|(k, v)| format!("{}:{}", k, v))
+                        .collect();
+                    captures.sort();
+                    captures.join(" ")
+                };
+                self.slices.insert(trigger.name().into(), slice);
+                self.matches
+                    .insert(trigger.name().into(), event.matches.clone());
+                self.resolved.remove(trigger.name());
+                if event.silenced {
+                    self.silenced.insert(trigger.name().into());
+                } else {
+                    self.silenced.remove(trigger.name());
+                }
+            } else if event.transition == Transition::Resolved
+                && self.slices.contains_key(trigger.name())
+            {
+                self.resolved.insert(trigger.name().into());
             }
         }
         if event.did_trigger || self.name.is_empty() {
@@ -0,0 +1,68 @@
+use super::serde::{Deserialize, Serialize};
+use super::task::TimeMs;
+use std::env;
+
+/// the name of the user running minutecat
+pub fn current_user() -> String {
+    env::var("USER")
+        .or_else(|_| env::var("USERNAME"))
+        .unwrap_or_else(|_| "unknown".into())
+}
+
+/// An ack marks an active problem as being handled.
+/// Notifications for the trigger stop until it stops firing
+/// or the ack expires.
+/// Acks are stored per trigger in the state file of the logset.
+/// Only active triggers can be acked
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Ack {
+    /// who acked the problem
+    pub by: String,
+    /// when the problem was acked in ms since the epoch
+    pub at: TimeMs,
+    /// end of the ack in ms since the epoch
+    #[serde(default)]
+    pub expires: Option<TimeMs>,
+    #[serde(default)]
+    pub comment: String,
+}
+
+impl Ack {
+    pub fn new(by: &str, at: TimeMs) -> Self {
+        Self {
+            by: by.into(),
+            at,
+            expires: None,
+            comment: "".into(),
+        }
+    }
+
+    pub fn with_expires(mut self, expires: TimeMs) -> Self {
+        self.expires = Some(expires);
+        self
+    }
+
+    pub fn with_comment(mut self, comment: &str) -> Self {
+        self.comment = comment.into();
+        self
+    }
+
+    pub fn is_active(&self, now: TimeMs) -> bool {
+        self.expires.is_none_or(|expires| now < expires)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_expire() {
+        let ack = Ack::new("alice", 0);
+        assert!(ack.is_active(u128::MAX));
+
+        let ack = ack.with_expires(1000);
+        assert!(ack.is_active(999));
+        assert!(!ack.is_active(1000));
+    }
+}
@@ -1,6 +1,9 @@
+use super::ack::Ack;
 use super::error::Error;
 use super::logfile::Logfile;
 use super::logset::LogSet;
+use super::policy::{TriggerPolicy, TriggerState};
+use super::silence::{MaintenanceWindow, Silence};
 use super::source::{DataSourceTypes, FileDataSource, HttpDataSource};
 use super::task::{ClockTimeSource, Task, TimeSourceTypes};
 use super::trigger::{RegexTrigger, TriggerType, TriggerTypes};
@@ -141,6 +144,7 @@ pub struct AddRegexTriggerCommand {
     trigger_type: TriggerType,
     regex: String,
     invert: bool,
+    per_line: bool,
     can_undo: bool,
 }
 
@@ -158,20 +162,32 @@ impl AddRegexTriggerCommand {
             trigger_type,
             regex: regex.into(),
             invert,
+            per_line: false,
             can_undo: false,
         }
     }
+
+    pub fn with_per_line(mut self, per_line: bool) -> Self {
+        self.per_line = per_line;
+        self
+    }
 }
 
 impl Command<Logfile> for AddRegexTriggerCommand {
     fn execute(&mut self, log: &mut Logfile) -> Result<(), Error> {
-        log.push(TriggerTypes::Regex(RegexTrigger::new(
-            &self.name,
-            &self.desc,
-            self.trigger_type,
-            &self.regex,
-            self.invert,
-        )));
+        if log.trigger_names().contains(&self.name.as_str()) {
+            return Err(Error::DuplicateTrigger(self.name.clone()));
+        }
+        log.push(TriggerTypes::Regex(
+            RegexTrigger::new(
+                &self.name,
+                &self.desc,
+                self.trigger_type,
+                &self.regex,
+                self.invert,
+            )
+            .with_per_line(self.per_line),
+        ));
         self.can_undo = true;
         Ok(())
     }
@@ -214,6 +230,210 @@ impl Command<Logfile> for RemoveTriggerCommand {
     }
 }
 
+pub struct SetPolicyCommand {
+    trigger: String,
+    policy: TriggerPolicy,
+    previous: Option<TriggerPolicy>,
+    can_undo: bool,
+}
+
+impl SetPolicyCommand {
+    pub fn new(trigger: &str, policy: TriggerPolicy) -> Self {
+        Self {
+            trigger: trigger.into(),
+            policy,
+            previous: None,
+            can_undo: false,
+        }
+    }
+}
+
+impl Command<Logfile> for SetPolicyCommand {
+    fn execute(&mut self, log: &mut Logfile) -> Result<(), Error> {
+        self.previous = log
+            .policies
+            .insert(self.trigger.clone(), self.policy.clone());
+        self.can_undo = true;
+        Ok(())
+    }
+
+    fn undo(&mut self, log: &mut Logfile) -> Result<(), Error> {
+        if self.can_undo {
+            match self.previous.take() {
+                Some(previous) => log.policies.insert(self.trigger.clone(), previous),
+                _ => log.policies.remove(&self.trigger),
+            };
+            self.can_undo = false;
+        }
+        Ok(())
+    }
+}
+
+/// acks a trigger or removes its ack.
+/// Only active triggers can be acked
+pub struct AckCommand {
+    trigger: String,
+    ack: Option<Ack>,
+    previous: Option<Ack>,
+    can_undo: bool,
+}
+
+impl AckCommand {
+    pub fn new(trigger: &str, ack: Option<Ack>) -> Self {
+        Self {
+            trigger: trigger.into(),
+            ack,
+            previous: None,
+            can_undo: false,
+        }
+    }
+}
+
+impl Command<Logfile> for AckCommand {
+    fn execute(&mut self, log: &mut Logfile) -> Result<(), Error> {
+        if self.ack.is_some() && !TriggerState::load(&mut log.extra, &self.trigger).active {
+            return Err(Error::InactiveTrigger(self.trigger.clone()));
+        }
+        self.previous = match &self.ack {
+            Some(ack) => log.acks.insert(self.trigger.clone(), ack.clone()),
+            _ => log.acks.remove(&self.trigger),
+        };
+        self.can_undo = true;
+        Ok(())
+    }
+
+    fn undo(&mut self, log: &mut Logfile) -> Result<(), Error> {
+        if self.can_undo {
+            match self.previous.take() {
+                Some(previous) => log.acks.insert(self.trigger.clone(), previous),
+                _ => log.acks.remove(&self.trigger),
+            };
+            self.can_undo = false;
+        }
+        Ok(())
+    }
+}
+
+pub struct AddSilenceCommand {
+    silence: Silence,
+    can_undo: bool,
+}
+
+impl AddSilenceCommand {
+    pub fn new(silence: Silence) -> Self {
+        Self {
+            silence,
+            can_undo: false,
+        }
+    }
+}
+
+impl Command<LogSet> for AddSilenceCommand {
+    fn execute(&mut self, logset: &mut LogSet) -> Result<(), Error> {
+        logset.silences.silences.push(self.silence.clone());
+        self.can_undo = true;
+        Ok(())
+    }
+
+    fn undo(&mut self, logset: &mut LogSet) -> Result<(), Error> {
+        if self.can_undo {
+            logset.silences.silences.pop();
+            self.can_undo = false;
+        }
+        Ok(())
+    }
+}
+
+pub struct DeleteSilenceCommand {
+    pub index: usize,
+    pub removed: Option<Silence>,
+}
+
+impl DeleteSilenceCommand {
+    pub fn new(index: usize) -> Self {
+        Self {
+            index,
+            removed: None,
+        }
+    }
+}
+
+impl Command<LogSet> for DeleteSilenceCommand {
+    fn execute(&mut self, logset: &mut LogSet) -> Result<(), Error> {
+        if self.index < logset.silences.silences.len() {
+            self.removed = Some(logset.silences.silences.remove(self.index));
+        }
+        Ok(())
+    }
+
+    fn undo(&mut self, logset: &mut LogSet) -> Result<(), Error> {
+        if let Some(silence) = self.removed.take() {
+            logset.silences.silences.insert(self.index, silence);
+        }
+        Ok(())
+    }
+}
+
+pub struct AddMaintenanceCommand {
+    window: MaintenanceWindow,
+    can_undo: bool,
+}
+
+impl AddMaintenanceCommand {
+    pub fn new(window: MaintenanceWindow) -> Self {
+        Self {
+            window,
+            can_undo: false,
+        }
+    }
+}
+
+impl Command<LogSet> for AddMaintenanceCommand {
+    fn execute(&mut self, logset: &mut LogSet) -> Result<(), Error> {
+        logset.silences.maintenance.push(self.window.clone());
+        self.can_undo = true;
+        Ok(())
+    }
+
+    fn undo(&mut self, logset: &mut LogSet) -> Result<(), Error> {
+        if self.can_undo {
+            logset.silences.maintenance.pop();
+            self.can_undo = false;
+        }
+        Ok(())
+    }
+}
+
+pub struct DeleteMaintenanceCommand {
+    pub index: usize,
+    pub removed: Option<MaintenanceWindow>,
+}
+
+impl DeleteMaintenanceCommand {
+    pub fn new(index: usize) -> Self {
+        Self {
+            index,
+            removed: None,
+        }
+    }
+}
+
+impl Command<LogSet> for DeleteMaintenanceCommand {
+    fn execute(&mut self, logset: &mut LogSet) -> Result<(), Error> {
+        if self.index < logset.silences.maintenance.len() {
+            self.removed = Some(logset.silences.maintenance.remove(self.index));
+        }
+        Ok(())
+    }
+
+    fn undo(&mut self, logset: &mut LogSet) -> Result<(), Error> {
+        if let Some(window) = self.removed.take() {
+            logset.silences.maintenance.insert(self.index, window);
+        }
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -278,6 +498,11 @@ mod tests {
 
         assert_eq!(l.len(), 1);
 
+        // names have to be unique
+        let mut dup = AddRegexTriggerCommand::new("name", "desc", TriggerType::Error, "", false);
+        assert!(dup.execute(&mut l).is_err());
+        assert_eq!(l.len(), 1);
+
         cmd.undo(&mut l).unwrap();
         assert_eq!(l.len(), 0);
     }
@@ -304,4 +529,86 @@ mod tests {
         delcmd.undo(&mut l).unwrap();
         assert_eq!(l.len(), 1);
     }
+
+    #[test]
+    fn it_should_set_policy() {
+        let mut log = Logfile::new(
+            "name",
+            DataSourceTypes::InMemory(InMemoryDataSource::new(vec![])),
+            Task::new(false, 0, TimeSourceTypes::Clock(ClockTimeSource)),
+        );
+
+        let mut cmd1 = SetPolicyCommand::new("error", TriggerPolicy::new().with_debounce(2));
+        let mut cmd2 = SetPolicyCommand::new("error", TriggerPolicy::new().with_cooldown("5m"));
+        cmd1.execute(&mut log).unwrap();
+        cmd2.execute(&mut log).unwrap();
+        assert_eq!(log.policies["error"].cooldown, Some("5m".into()));
+
+        cmd2.undo(&mut log).unwrap();
+        assert_eq!(log.policies["error"].debounce, 2);
+
+        cmd1.undo(&mut log).unwrap();
+        assert!(log.policies.is_empty());
+    }
+
+    #[test]
+    fn it_should_add_and_delete_silences() {
+        use crate::silence::SilenceMatcher;
+
+        let mut ls = LogSet::new();
+        let mut add1 = AddSilenceCommand::new(Silence::new(SilenceMatcher::new(), 10, "a"));
+        let mut add2 = AddSilenceCommand::new(Silence::new(SilenceMatcher::new(), 20, "b"));
+        add1.execute(&mut ls).unwrap();
+        add2.execute(&mut ls).unwrap();
+
+        let mut del = DeleteSilenceCommand::new(0);
+        del.execute(&mut ls).unwrap();
+        assert_eq!(ls.silences.silences[0].comment, "b");
+        del.undo(&mut ls).unwrap();
+        assert_eq!(ls.silences.silences[0].comment, "a");
+
+        add2.undo(&mut ls).unwrap();
+        add2.undo(&mut ls).unwrap();
+        assert_eq!(ls.silences.silences.len(), 1);
+
+        let window =
+            MaintenanceWindow::new(SilenceMatcher::new(), vec![], "02:00", "04:00", "").unwrap();
+        let mut add = AddMaintenanceCommand::new(window);
+        add.execute(&mut ls).unwrap();
+        let mut del = DeleteMaintenanceCommand::new(0);
+        del.execute(&mut ls).unwrap();
+        assert!(ls.silences.maintenance.is_empty());
+        del.undo(&mut ls).unwrap();
+        add.undo(&mut ls).unwrap();
+        assert!(ls.silences.maintenance.is_empty());
+    }
+
+    #[test]
+    fn it_should_ack() {
+        let mut log = Logfile::new(
+            "name",
+            DataSourceTypes::InMemory(InMemoryDataSource::new(vec![])),
+            Task::new(false, 0, TimeSourceTypes::Clock(ClockTimeSource)),
+        );
+
+        let mut ack = AckCommand::new("error", Some(Ack::new("alice", 10)));
+        let mut clear = AckCommand::new("error", None);
+        assert!(ack.execute(&mut log).is_err());
+
+        let state = TriggerState {
+            active: true,
+            ..TriggerState::default()
+        };
+        state.store(&mut log.extra, "error").unwrap();
+        ack.execute(&mut log).unwrap();
+        assert_eq!(log.acks["error"].by, "alice");
+
+        clear.execute(&mut log).unwrap();
+        assert!(log.acks.is_empty());
+
+        clear.undo(&mut log).unwrap();
+        assert_eq!(log.acks["error"].by, "alice");
+        ack.undo(&mut log).unwrap();
+        assert!(log.acks.is_empty());
+    }
 }
@@ -8,8 +8,16 @@ pub enum Error {
     Utf8Error(std::str::Utf8Error),
     ParseIntError(std::num::ParseIntError),
     SerdeYamlError(serde_yaml::Error),
+    SerdeJsonError(serde_json::Error),
     ReqwestError(reqwest::Error),
     RegexError(regex::Error),
+    ScriptError(String),
+    PluginError(String),
+    MailError(String),
+    TemplateError(String),
+    DuplicateTrigger(String),
+    InactiveTrigger(String),
+    UpdateErrors(Vec<(String, Error)>),
 }
 
 impl PartialEq for Error {
@@ -28,6 +36,31 @@ impl PartialEq for Error {
 }
 
 impl Error {
+    /// a short name of the kind of error e.g. for metrics
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::InMemoryDataError => "in_memory_data",
+            Self::TimeStringUnknownOperator => "time_string",
+            Self::UndefinedExtraData => "undefined_extra_data",
+            Self::FromStringError => "from_string",
+            Self::GenericError => "generic",
+            Self::IoError(_) => "io",
+            Self::Utf8Error(_) => "utf8",
+            Self::ParseIntError(_) => "parse_int",
+            Self::SerdeYamlError(_) => "yaml",
+            Self::SerdeJsonError(_) => "json",
+            Self::ReqwestError(_) => "http",
+            Self::RegexError(_) => "regex",
+            Self::ScriptError(_) => "script",
+            Self::PluginError(_) => "plugin",
+            Self::MailError(_) => "mail",
+            Self::TemplateError(_) => "template",
+            Self::DuplicateTrigger(_) => "duplicate_trigger",
+            Self::InactiveTrigger(_) => "inactive_trigger",
+            Self::UpdateErrors(_) => "update",
+        }
+    }
+
     fn as_string(&self) -> String {
         match self {
             Self::InMemoryDataError => "InMemoryDataError",
@@ -38,8 +71,22 @@ impl Error {
             Self::Utf8Error(e) => return e.to_string(),
             Self::ParseIntError(e) => return e.to_string(),
             Self::SerdeYamlError(e) => return e.to_string(),
+            Self::SerdeJsonError(e) => return e.to_string(),
             Self::ReqwestError(e) => return e.to_string(),
             Self::RegexError(e) => return e.to_string(),
+            Self::ScriptError(e) => return format!("Script error: {}", e),
+            Self::PluginError(e) => return format!("Plugin error: {}", e),
+            Self::MailError(e) => return format!("Mail error: {}", e),
+            Self::TemplateError(e) => return format!("Template error: {}", e),
+            Self::DuplicateTrigger(e) => return format!("Duplicate trigger name: {}", e),
+            Self::InactiveTrigger(e) => return format!("Trigger is not active: {}", e),
+            Self::UpdateErrors(errors) => {
+                return errors
+                    .iter()
+                    .map(|(name, e)| format!("{}: {}", name, e))
+                    .collect::<Vec<String>>()
+                    .join("\n")
+            }
             _ => "NoString",
         }
         .into()
@@ -76,6 +123,12 @@ impl From<serde_yaml::Error> for Error {
     }
 }
 
+impl From<serde_json::Error> for Error {
+    fn from(error: serde_json::Error) -> Self {
+        Error::SerdeJsonError(error)
+    }
+}
+
 impl From<std::str::Utf8Error> for Error {
     fn from(error: std::str::Utf8Error) -> Self {
         Error::Utf8Error(error)
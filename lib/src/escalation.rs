@@ -0,0 +1,372 @@
+use super::error::Error;
+use super::policy::TriggerState;
+use super::route::glob_match;
+use super::serde::{Deserialize, Serialize};
+use super::task::{Task, TimeMs};
+use super::trigger::TriggerType;
+
+/// A single step of an escalation chain
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct EscalationStep {
+    /// how long the trigger has to be active e.g. 15m
+    pub after: String,
+    /// the name of the logset handler that is notified
+    pub handler: String,
+}
+
+impl EscalationStep {
+    pub fn new(after: &str, handler: &str) -> Self {
+        Self {
+            after: after.into(),
+            handler: handler.into(),
+        }
+    }
+}
+
+/// An escalation chain notifies further handlers of the logset
+/// while a matching trigger stays active without being acked.
+///
+/// Every matcher that is set has to match:
+///   - log: a glob of the log name e.g. prod-*
+///   - trigger: a glob of the trigger name
+///   - severity: the lowest severity
+///
+/// Only the first matching chain is used.
+/// Steps are sent in order once the trigger has been active for their delay.
+/// Acks and silences hold the chain back, a resolved trigger restarts it.
+/// Delays count from the time the trigger was last active without
+/// an ack or silence: once a hold ends the remaining steps wait
+/// for their full delay again.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Escalation {
+    pub steps: Vec<EscalationStep>,
+
+    #[serde(default)]
+    pub log: Option<String>,
+    #[serde(default)]
+    pub trigger: Option<String>,
+    #[serde(default)]
+    pub severity: Option<TriggerType>,
+}
+
+impl Escalation {
+    pub fn new(steps: Vec<EscalationStep>) -> Self {
+        Self {
+            steps,
+            log: None,
+            trigger: None,
+            severity: None,
+        }
+    }
+
+    pub fn with_log(mut self, log: &str) -> Self {
+        self.log = Some(log.into());
+        self
+    }
+
+    pub fn with_trigger(mut self, trigger: &str) -> Self {
+        self.trigger = Some(trigger.into());
+        self
+    }
+
+    pub fn with_severity(mut self, severity: TriggerType) -> Self {
+        self.severity = Some(severity);
+        self
+    }
+
+    pub fn matches(&self, log: &str, trigger: &str, severity: TriggerType) -> bool {
+        self.log.as_ref().is_none_or(|l| glob_match(l, log))
+            && self.trigger.as_ref().is_none_or(|t| glob_match(t, trigger))
+            && self.severity.is_none_or(|s| severity >= s)
+    }
+
+    /// the first matching chain
+    pub fn find<'a>(
+        escalations: &'a [Escalation],
+        log: &str,
+        trigger: &str,
+        severity: TriggerType,
+    ) -> Option<&'a Escalation> {
+        escalations
+            .iter()
+            .find(|e| e.matches(log, trigger, severity))
+    }
+
+    /// the delays of the steps
+    pub fn delays(&self) -> Result<Vec<TimeMs>, Error> {
+        self.steps.iter().map(|s| Task::scan(&s.after)).collect()
+    }
+
+    /// checks that every delay can be read
+    pub fn validate(&self) -> Result<(), Error> {
+        self.delays().map(|_| ())
+    }
+
+    /// returns the handlers of the steps that are due
+    /// and marks them as sent in the trigger state.
+    /// It is called while the trigger is active and not held back.
+    /// The state is left unchanged if a delay is invalid
+    pub fn due(&self, state: &mut TriggerState, now: TimeMs) -> Result<Vec<String>, Error> {
+        let delays = self.delays()?;
+        if state.raised.is_none() {
+            return Ok(vec![]);
+        }
+        let since = now.saturating_sub(*state.unheld.get_or_insert(now));
+
+        let mut due = vec![];
+        for (step, delay) in self.steps.iter().zip(delays).skip(state.escalated) {
+            if since < delay {
+                break;
+            }
+            due.push(step.handler.clone());
+            state.escalated += 1;
+        }
+        Ok(due)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::policy::TriggerPolicy;
+
+    fn chain() -> Escalation {
+        Escalation::new(vec![
+            EscalationStep::new("15m", "pager"),
+            EscalationStep::new("1h", "phone"),
+        ])
+        .with_log("prod-*")
+        .with_severity(TriggerType::Error)
+    }
+
+    #[test]
+    fn it_should_find_chains() {
+        let escalations = vec![chain(), Escalation::new(vec![])];
+        assert_eq!(
+            Escalation::find(&escalations, "prod-api", "panic", TriggerType::Fatal),
+            Some(&escalations[0])
+        );
+        assert_eq!(
+            Escalation::find(&escalations, "prod-api", "slow", TriggerType::Warning),
+            Some(&escalations[1])
+        );
+        assert_eq!(
+            Escalation::find(&escalations[..1], "dev-api", "panic", TriggerType::Fatal),
+            None
+        );
+    }
+
+    #[test]
+    fn it_should_escalate_in_order() {
+        let policy = TriggerPolicy::new();
+        let chain = chain();
+        let mut state = TriggerState::default();
+        let minute = 60 * 1000;
+
+        let mut run = |fired: bool, now: TimeMs| -> Vec<String> {
+            policy.apply(&mut state, fired, now).unwrap();
+            chain.due(&mut state, now).unwrap()
+        };
+        assert!(run(true, 0).is_empty());
+        assert!(run(true, 14 * minute).is_empty());
+        assert_eq!(run(true, 15 * minute), vec!["pager"]);
+        assert!(run(true, 30 * minute).is_empty());
+        assert_eq!(run(true, 60 * minute), vec!["phone"]);
+        assert!(run(true, 120 * minute).is_empty());
+
+        // resolving restarts the chain
+        assert!(run(false, 121 * minute).is_empty());
+        assert!(run(true, 122 * minute).is_empty());
+        assert_eq!(run(true, 200 * minute), vec!["pager", "phone"]);
+    }
+
+    #[test]
+    fn it_should_wait_for_the_delays_after_a_hold() {
+        let policy = TriggerPolicy::new();
+        let chain = chain();
+        let mut state = TriggerState::default();
+        let minute = 60 * 1000;
+
+        assert!(run_due(&policy, &chain, &mut state, 0).is_empty());
+        assert_eq!(state.unheld, Some(0));
+
+        // acked from 10 to 90 minutes, the chain is not checked meanwhile
+        policy.apply(&mut state, true, 10 * minute).unwrap();
+        state.unheld = None;
+        policy.apply(&mut state, true, 89 * minute).unwrap();
+
+        // the steps wait for their delay from the end of the hold
+        assert!(run_due(&policy, &chain, &mut state, 90 * minute).is_empty());
+        assert!(run_due(&policy, &chain, &mut state, 104 * minute).is_empty());
+        assert_eq!(
+            run_due(&policy, &chain, &mut state, 105 * minute),
+            vec!["pager"]
+        );
+        assert!(run_due(&policy, &chain, &mut state, 149 * minute).is_empty());
+        assert_eq!(
+            run_due(&policy, &chain, &mut state, 150 * minute),
+            vec!["phone"]
+        );
+    }
+
+    fn run_due(
+        policy: &TriggerPolicy,
+        chain: &Escalation,
+        state: &mut TriggerState,
+        now: TimeMs,
+    ) -> Vec<String> {
+        policy.apply(state, true, now).unwrap();
+        chain.due(state, now).unwrap()
+    }
+
+    #[test]
+    fn it_should_fail_on_bad_delays() {
+        let chain = Escalation::new(vec![
+            EscalationStep::new("0s", "pager"),
+            EscalationStep::new("10x", "phone"),
+        ]);
+        assert!(chain.validate().is_err());
+
+        let mut state = TriggerState {
+            raised: Some(0),
+            ..TriggerState::default()
+        };
+        assert!(chain.due(&mut state, 0).is_err());
+        assert_eq!(
+            state,
+            TriggerState {
+                raised: Some(0),
+                ..TriggerState::default()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_escalate_unacked_triggers() {
+        use crate::ack::Ack;
+        use crate::handler::{HandlerTypes, ShellHandler};
+        use crate::logfile::Logfile;
+        use crate::logset::LogSet;
+        use crate::route::Route;
+        use crate::source::{DataSourceTypes, InMemoryDataSource};
+        use crate::task::{InMemoryTimeSource, TimeSourceTypes};
+        use crate::{RegexTrigger, TriggerTypes};
+
+        let minute = 60 * 1000;
+        let mut lf = Logfile::new(
+            "prod-api",
+            DataSourceTypes::InMemory(InMemoryDataSource::new(vec!["panic oom".into(); 4])),
+            Task::new(
+                true,
+                10,
+                // raised at 1m, checked again 14, 15 and 60 minutes later
+                TimeSourceTypes::InMemory(InMemoryTimeSource::new(vec![
+                    61 * minute,
+                    61 * minute,
+                    16 * minute,
+                    16 * minute,
+                    15 * minute,
+                    15 * minute,
+                    minute,
+                    minute,
+                    0,
+                ])),
+            ),
+        );
+        for name in ["panic", "oom"] {
+            lf.push(TriggerTypes::Regex(RegexTrigger::new(
+                name,
+                "",
+                TriggerType::Error,
+                name,
+                false,
+            )));
+        }
+        lf.acks.insert("oom".into(), Ack::new("alice", 0));
+
+        let mut logset = LogSet::new();
+        logset.push(lf);
+        for name in ["chat", "pager", "phone"] {
+            logset.handlers.insert(
+                name.to_string(),
+                HandlerTypes::Shell(ShellHandler::new("echo $MINUTECAT_TRIGGER_NAME")),
+            );
+        }
+        logset.routes = vec![Route::new(vec!["chat".into()])];
+        logset.escalations = vec![chain()];
+
+        let mut escalated = vec![];
+        for _ in 0..4 {
+            assert!(logset.update_log(0, &mut vec![], false).await.unwrap());
+            let state = TriggerState::load(&mut logset.logs[0].extra, "panic");
+            escalated.push(state.escalated);
+        }
+        assert_eq!(escalated, vec![0, 0, 1, 2]);
+
+        let outputs = |name: &str| match &logset.handlers[name] {
+            HandlerTypes::Shell(h) => {
+                h.wait();
+                h.results()
+                    .into_iter()
+                    .map(|r| r.stdout)
+                    .collect::<Vec<_>>()
+            }
+            _ => vec![],
+        };
+        assert_eq!(outputs("chat"), vec!["panic\n"]);
+        assert_eq!(outputs("pager"), vec!["panic\n"]);
+        assert_eq!(outputs("phone"), vec!["panic\n"]);
+    }
+
+    #[tokio::test]
+    async fn it_should_skip_bad_chains_in_checks() {
+        use crate::logfile::Logfile;
+        use crate::logset::LogSet;
+        use crate::source::{DataSourceTypes, InMemoryDataSource};
+        use crate::task::{InMemoryTimeSource, TimeSourceTypes};
+        use crate::{RegexTrigger, TriggerTypes};
+
+        let mut lf = Logfile::new(
+            "prod-api",
+            DataSourceTypes::InMemory(InMemoryDataSource::new(vec!["panic".into()])),
+            Task::new(
+                true,
+                10,
+                TimeSourceTypes::InMemory(InMemoryTimeSource::new(vec![11, 11, 0])),
+            ),
+        );
+        lf.push(TriggerTypes::Regex(RegexTrigger::new(
+            "panic",
+            "",
+            TriggerType::Error,
+            "panic",
+            false,
+        )));
+
+        let mut logset = LogSet::new();
+        logset.push(lf);
+        logset.escalations = vec![Escalation::new(vec![EscalationStep::new("10x", "pager")])];
+
+        assert!(logset.update_log(0, &mut vec![], false).await.unwrap());
+        assert!(TriggerState::load(&mut logset.logs[0].extra, "panic").active);
+    }
+
+    #[test]
+    fn it_should_read_escalations_from_yaml() {
+        let escalations: Vec<Escalation> = serde_yaml::from_str(
+            "- log: prod-*\n  severity: error\n  steps:\n    - after: 15m\n      handler: pager\n    - after: 1h\n      handler: phone\n",
+        )
+        .unwrap();
+        assert_eq!(escalations, vec![chain()]);
+    }
+
+    #[test]
+    fn it_should_reject_bad_delays_on_load() {
+        use crate::logset::LogSet;
+
+        let res = LogSet::deserialize(
+            "logs: []\nescalations:\n  - steps:\n      - after: 10x\n        handler: pager\n",
+        );
+        assert!(res.is_err());
+    }
+}
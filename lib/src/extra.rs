@@ -7,7 +7,7 @@ use std::collections::HashMap;
 /// to store extra data
 /// but it works well with the current serde piepeline
 /// Maybe implement a proper system in the future?
-#[derive(Clone, Serialize, Deserialize, Default)]
+#[derive(Debug, PartialEq, Clone, Serialize, Deserialize, Default)]
 pub struct ExtraData {
     data: HashMap<String, String>,
 }
@@ -0,0 +1,136 @@
+use crate::logfile::{Event, EventHandler};
+use crate::policy::Transition;
+use crate::serde::{Deserialize, Serialize};
+use crate::typetag;
+use crate::HistoryHandler;
+use crate::ShellHandler;
+use crate::SmtpHandler;
+use crate::WebhookHandler;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+/// The number of errors a handler keeps until they are taken
+const MAX_ERRORS: usize = 100;
+
+/// Handlers that are configured in the logset or logfile
+#[derive(Clone, Serialize, Deserialize)]
+pub enum HandlerTypes {
+    Shell(ShellHandler),
+    Webhook(WebhookHandler),
+    Smtp(SmtpHandler),
+    History(HistoryHandler),
+    Generic(Box<dyn Handler>),
+}
+
+impl EventHandler for HandlerTypes {
+    fn on_event(&mut self, event: &Event) {
+        match self {
+            Self::Shell(h) => h.on_event(event),
+            Self::Webhook(h) => h.on_event(event),
+            Self::Smtp(h) => h.on_event(event),
+            Self::History(h) => h.on_event(event),
+            Self::Generic(h) => h.on_event(event),
+        }
+    }
+}
+
+#[typetag::serde]
+impl Handler for HandlerTypes {
+    fn flush(&mut self) {
+        match self {
+            Self::Shell(h) => h.flush(),
+            Self::Webhook(h) => h.flush(),
+            Self::Smtp(h) => h.flush(),
+            Self::History(h) => h.flush(),
+            Self::Generic(h) => h.flush(),
+        }
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        match self {
+            Self::Shell(h) => h.take_errors(),
+            Self::Webhook(h) => h.take_errors(),
+            Self::Smtp(h) => h.take_errors(),
+            Self::History(h) => h.take_errors(),
+            Self::Generic(h) => h.take_errors(),
+        }
+    }
+}
+
+pub trait HandlerClone {
+    fn box_clone(&self) -> Box<dyn Handler>;
+}
+
+impl<T> HandlerClone for T
+where
+    T: 'static + Handler + Clone,
+{
+    fn box_clone(&self) -> Box<dyn Handler> {
+        Box::new(self.clone())
+    }
+}
+
+/// true if a notifier should send a notification for the event.
+/// Notifications are sent when the trigger's policy says so
+/// and optionally when a trigger is resolved.
+/// Silenced and acked events never notify
+pub fn should_notify(event: &Event, on_resolved: bool) -> bool {
+    event.trigger.is_some()
+        && !event.silenced
+        && event.ack.is_none()
+        && (event.notify || (on_resolved && event.transition == Transition::Resolved))
+}
+
+/// The errors a handler reported e.g. failed deliveries.
+/// Clones share the errors, so background threads can report them.
+/// Only the last errors are kept
+#[derive(Clone, Default)]
+pub struct HandlerErrors {
+    errors: Arc<Mutex<Vec<String>>>,
+}
+
+impl HandlerErrors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn report(&self, err: impl fmt::Display) {
+        if let Ok(mut errors) = self.errors.lock() {
+            errors.push(err.to_string());
+            let len = errors.len();
+            if len > MAX_ERRORS {
+                errors.drain(..len - MAX_ERRORS);
+            }
+        }
+    }
+
+    /// removes and returns the reported errors, oldest first
+    pub fn take(&self) -> Vec<String> {
+        match self.errors.lock() {
+            Ok(mut errors) => errors.drain(..).collect(),
+            _ => vec![],
+        }
+    }
+}
+
+/// A handler is an event handler that can be
+/// stored in the configuration e.g. a shell command
+/// or a notifier
+#[typetag::serde(tag = "type")]
+pub trait Handler: EventHandler + HandlerClone + Send {
+    /// called after every update cycle.
+    /// Handlers that collect events (e.g. digests) should send them here
+    fn flush(&mut self) {}
+
+    /// removes and returns the errors since the last call.
+    /// Handlers that fail in the background should report them here
+    fn take_errors(&mut self) -> Vec<String> {
+        vec![]
+    }
+}
+
+impl Clone for Box<dyn Handler> {
+    fn clone(&self) -> Box<dyn Handler> {
+        self.box_clone()
+    }
+}
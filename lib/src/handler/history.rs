@@ -0,0 +1,462 @@
+use crate::error::Error;
+use crate::logfile::{Event, EventHandler};
+use crate::policy::Transition;
+use crate::route::glob_match;
+use crate::serde::{Deserialize, Serialize};
+use crate::task::{Task, TimeMs};
+use crate::trigger::TriggerType;
+use crate::typetag;
+use crate::{Handler, HandlerErrors};
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+
+fn default_prune_every() -> String {
+    "1h".into()
+}
+
+/// A single raised or resolved trigger
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    /// time of the check in ms since the epoch
+    pub time: TimeMs,
+    pub log: String,
+    pub trigger: String,
+    pub severity: TriggerType,
+    pub transition: Transition,
+    pub slice: String,
+    #[serde(default)]
+    pub captures: HashMap<String, String>,
+}
+
+/// Selects history entries.
+/// Every filter that is set has to match
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct HistoryQuery {
+    /// entries at or after this time
+    pub since: Option<TimeMs>,
+    /// entries before this time
+    pub until: Option<TimeMs>,
+    /// a glob of the log name
+    pub log: Option<String>,
+    /// a glob of the trigger name
+    pub trigger: Option<String>,
+    /// the lowest severity
+    pub severity: Option<TriggerType>,
+}
+
+impl HistoryQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn matches(&self, entry: &HistoryEntry) -> bool {
+        self.since.is_none_or(|since| entry.time >= since)
+            && self.until.is_none_or(|until| entry.time < until)
+            && self.log.as_ref().is_none_or(|l| glob_match(l, &entry.log))
+            && self
+                .trigger
+                .as_ref()
+                .is_none_or(|t| glob_match(t, &entry.trigger))
+            && self.severity.is_none_or(|s| entry.severity >= s)
+    }
+}
+
+/// A history handler appends an entry to a JSON lines file
+/// whenever a trigger is raised or resolved.
+///
+/// Retention keeps only the newest max_entries entries
+/// and removes entries older than max_age (e.g. 30d).
+/// Since it rewrites the file it is applied on flush
+/// once prune_every (default 1h) passed since the last time
+/// or the file grew beyond max_size bytes.
+/// The time is the check time of the logs' tasks.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct HistoryHandler {
+    path: String,
+
+    #[serde(default)]
+    max_entries: Option<usize>,
+    #[serde(default)]
+    max_age: Option<String>,
+    #[serde(default = "default_prune_every")]
+    prune_every: String,
+    #[serde(default)]
+    max_size: Option<u64>,
+
+    /// the time of the latest event
+    #[serde(skip)]
+    now: TimeMs,
+    /// entries were written since the last flush
+    #[serde(skip)]
+    written: bool,
+    /// when retention was last applied
+    #[serde(skip)]
+    pruned: Option<TimeMs>,
+    #[serde(skip)]
+    errors: HandlerErrors,
+}
+
+impl HistoryHandler {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.into(),
+            max_entries: None,
+            max_age: None,
+            prune_every: default_prune_every(),
+            max_size: None,
+            now: 0,
+            written: false,
+            pruned: None,
+            errors: HandlerErrors::new(),
+        }
+    }
+
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = Some(max_entries);
+        self
+    }
+
+    pub fn with_max_age(mut self, max_age: &str) -> Self {
+        self.max_age = Some(max_age.into());
+        self
+    }
+
+    pub fn with_prune_every(mut self, prune_every: &str) -> Self {
+        self.prune_every = prune_every.into();
+        self
+    }
+
+    pub fn with_max_size(mut self, max_size: u64) -> Self {
+        self.max_size = Some(max_size);
+        self
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// reads every entry of a history file.
+    /// A missing file is an empty history, broken lines are skipped
+    pub fn read(path: &str) -> Result<Vec<HistoryEntry>, Error> {
+        let history = match fs::read_to_string(path) {
+            Ok(history) => history,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(history
+            .lines()
+            .filter_map(|l| serde_json::from_str(l).ok())
+            .collect())
+    }
+
+    /// returns the matching entries of a history file, oldest first
+    pub fn query(path: &str, query: &HistoryQuery) -> Result<Vec<HistoryEntry>, Error> {
+        Ok(Self::read(path)?
+            .into_iter()
+            .filter(|e| query.matches(e))
+            .collect())
+    }
+
+    fn append(&self, entry: &HistoryEntry) -> Result<(), Error> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        writeln!(file, "{}", serde_json::to_string(entry)?)?;
+        Ok(())
+    }
+
+    /// applies the retention limits relative to now
+    pub fn prune(&self, now: TimeMs) -> Result<(), Error> {
+        if self.max_entries.is_none() && self.max_age.is_none() {
+            return Ok(());
+        }
+
+        let mut entries = Self::read(&self.path)?;
+        let len = entries.len();
+        if let Some(max_age) = &self.max_age {
+            let oldest = now.saturating_sub(Task::scan(max_age)?);
+            entries.retain(|e| e.time >= oldest);
+        }
+        if let Some(max_entries) = self.max_entries {
+            let skip = entries.len().saturating_sub(max_entries);
+            entries.drain(..skip);
+        }
+        if entries.len() == len {
+            return Ok(());
+        }
+
+        let mut lines = String::new();
+        for entry in &entries {
+            lines.push_str(&serde_json::to_string(entry)?);
+            lines.push('\n');
+        }
+        fs::write(&self.path, lines)?;
+        Ok(())
+    }
+
+    /// true if retention should be applied on this flush
+    fn should_prune(&self) -> Result<bool, Error> {
+        let every = Task::scan(&self.prune_every)?;
+        if self
+            .pruned
+            .is_none_or(|pruned| self.now.saturating_sub(pruned) >= every)
+        {
+            return Ok(true);
+        }
+        Ok(match self.max_size {
+            Some(max_size) if self.written => fs::metadata(&self.path)?.len() > max_size,
+            _ => false,
+        })
+    }
+
+    fn report(&self, err: Error) {
+        self.errors.report(format!("{}: {}", self.path, err));
+    }
+}
+
+impl EventHandler for HistoryHandler {
+    fn on_event(&mut self, event: &Event) {
+        self.now = self.now.max(event.task.last_time());
+        let trigger = match event.trigger {
+            Some(trigger) => trigger,
+            _ => return,
+        };
+        if event.transition != Transition::Raised && event.transition != Transition::Resolved {
+            return;
+        }
+
+        let entry = HistoryEntry {
+            time: event.task.last_time(),
+            log: event.name.into(),
+            trigger: trigger.name().into(),
+            severity: event.trigger_type,
+            transition: event.transition,
+            slice: event.slice.into(),
+            captures: event.captures.clone(),
+        };
+        match self.append(&entry) {
+            Ok(_) => self.written = true,
+            Err(err) => self.report(err),
+        }
+    }
+}
+
+#[typetag::serde]
+impl Handler for HistoryHandler {
+    /// applies the retention limits if they are due
+    fn flush(&mut self) {
+        if self.max_entries.is_some() || self.max_age.is_some() {
+            match self.should_prune() {
+                Ok(true) => match self.prune(self.now) {
+                    Ok(_) => self.pruned = Some(self.now),
+                    Err(err) => self.report(err),
+                },
+                Ok(false) => {}
+                Err(err) => self.report(err),
+            }
+        }
+        self.written = false;
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        self.errors.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logfile::Logfile;
+    use crate::source::{DataSourceTypes, InMemoryDataSource};
+    use crate::task::{InMemoryTimeSource, TimeSourceTypes};
+    use crate::{RegexTrigger, TriggerTypes};
+
+    /// a history file in a new temp dir, the dir is removed on drop
+    fn path() -> (tempfile::TempDir, String) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.jsonl").to_str().unwrap().into();
+        (dir, path)
+    }
+
+    /// checks the texts at 1000, 2000, ...
+    async fn record(handler: &mut HistoryHandler, texts: Vec<&str>) {
+        let mut data: Vec<String> = texts.iter().map(|t| t.to_string()).collect();
+        data.reverse();
+        // Task::new takes one time and every due update takes two
+        let mut times: Vec<TimeMs> = vec![0];
+        times.extend((1..=texts.len() as TimeMs).flat_map(|i| [i * 1000, i * 1000]));
+        times.reverse();
+
+        let mut lf = Logfile::new(
+            "prod-api",
+            DataSourceTypes::InMemory(InMemoryDataSource::new(data)),
+            Task::new(
+                true,
+                10,
+                TimeSourceTypes::InMemory(InMemoryTimeSource::new(times)),
+            ),
+        );
+        lf.push(TriggerTypes::Regex(RegexTrigger::new(
+            "login",
+            "failed logins",
+            TriggerType::Warning,
+            r"login failed for (?P<user>\w+)",
+            false,
+        )));
+        lf.push(TriggerTypes::Regex(RegexTrigger::new(
+            "panic",
+            "panics",
+            TriggerType::Fatal,
+            "panic",
+            false,
+        )));
+
+        for _ in 0..texts.len() {
+            assert!(lf.update(&mut vec![handler]).await.unwrap());
+            handler.flush();
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_record_fired_and_resolved_events() {
+        let (_dir, path) = path();
+        let mut handler = HistoryHandler::new(&path);
+        record(&mut handler, vec!["login failed for bob", "panic", "ok"]).await;
+
+        let entries = HistoryHandler::read(&path).unwrap();
+        let summary: Vec<(TimeMs, &str, Transition)> = entries
+            .iter()
+            .map(|e| (e.time, e.trigger.as_str(), e.transition))
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (1000, "login", Transition::Raised),
+                (2000, "login", Transition::Resolved),
+                (2000, "panic", Transition::Raised),
+                (3000, "panic", Transition::Resolved),
+            ]
+        );
+        assert_eq!(entries[0].captures["user"], "bob");
+        assert_eq!(entries[0].slice, "login failed for bob");
+        assert_eq!(entries[2].severity, TriggerType::Fatal);
+    }
+
+    #[tokio::test]
+    async fn it_should_query_entries() {
+        let (_dir, path) = path();
+        let mut handler = HistoryHandler::new(&path);
+        record(&mut handler, vec!["login failed for bob", "panic", "ok"]).await;
+
+        let query = |query: HistoryQuery| -> Vec<TimeMs> {
+            HistoryHandler::query(&path, &query)
+                .unwrap()
+                .iter()
+                .map(|e| e.time)
+                .collect()
+        };
+        assert_eq!(query(HistoryQuery::new()).len(), 4);
+        assert_eq!(
+            query(HistoryQuery {
+                since: Some(2000),
+                until: Some(3000),
+                ..HistoryQuery::new()
+            }),
+            vec![2000, 2000]
+        );
+        assert_eq!(
+            query(HistoryQuery {
+                severity: Some(TriggerType::Error),
+                ..HistoryQuery::new()
+            }),
+            vec![2000, 3000]
+        );
+        assert_eq!(
+            query(HistoryQuery {
+                log: Some("prod-*".into()),
+                trigger: Some("log*".into()),
+                ..HistoryQuery::new()
+            }),
+            vec![1000, 2000]
+        );
+        assert!(query(HistoryQuery {
+            log: Some("dev-*".into()),
+            ..HistoryQuery::new()
+        })
+        .is_empty());
+    }
+
+    #[tokio::test]
+    async fn it_should_apply_retention() {
+        let (_dir, path) = path();
+        let mut handler = HistoryHandler::new(&path)
+            .with_max_entries(3)
+            .with_prune_every("1s");
+        record(&mut handler, vec!["panic", "ok", "panic", "ok"]).await;
+        let times: Vec<TimeMs> = HistoryHandler::read(&path)
+            .unwrap()
+            .iter()
+            .map(|e| e.time)
+            .collect();
+        assert_eq!(times, vec![2000, 3000, 4000]);
+
+        let (_dir, path) = super::tests::path();
+        let mut handler = HistoryHandler::new(&path)
+            .with_max_age("2s")
+            .with_prune_every("1s");
+        record(&mut handler, vec!["panic", "ok", "panic", "ok"]).await;
+        let times: Vec<TimeMs> = HistoryHandler::read(&path)
+            .unwrap()
+            .iter()
+            .map(|e| e.time)
+            .collect();
+        assert_eq!(times, vec![2000, 3000, 4000]);
+    }
+
+    #[tokio::test]
+    async fn it_should_skip_ongoing_triggers() {
+        let (_dir, path) = path();
+        let mut handler = HistoryHandler::new(&path);
+        record(&mut handler, vec!["panic", "panic", "ok"]).await;
+
+        let transitions: Vec<Transition> = HistoryHandler::read(&path)
+            .unwrap()
+            .iter()
+            .map(|e| e.transition)
+            .collect();
+        assert_eq!(transitions, vec![Transition::Raised, Transition::Resolved]);
+    }
+
+    #[tokio::test]
+    async fn it_should_prune_when_due_or_too_large() {
+        let count = |path: &str| HistoryHandler::read(path).unwrap().len();
+
+        // pruned on the first flush, then not for an hour
+        let (_dir, path) = path();
+        let mut handler = HistoryHandler::new(&path).with_max_entries(1);
+        record(&mut handler, vec!["panic", "ok", "panic", "ok"]).await;
+        assert_eq!(count(&path), 4);
+
+        // or once the file is too large
+        let (_dir, path) = super::tests::path();
+        let mut handler = HistoryHandler::new(&path)
+            .with_max_entries(1)
+            .with_max_size(1);
+        record(&mut handler, vec!["panic", "ok", "panic", "ok"]).await;
+        assert_eq!(count(&path), 1);
+    }
+
+    #[test]
+    fn it_should_report_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("missing/history.jsonl");
+        let mut handler = HistoryHandler::new(path.to_str().unwrap())
+            .with_max_entries(1)
+            .with_prune_every("1x");
+        handler.flush();
+        assert_eq!(handler.take_errors().len(), 1);
+        assert!(handler.take_errors().is_empty());
+    }
+}
@@ -0,0 +1,11 @@
+mod base;
+mod history;
+mod shell;
+mod smtp;
+mod webhook;
+
+pub use self::base::*;
+pub use self::history::*;
+pub use self::shell::*;
+pub use self::smtp::*;
+pub use self::webhook::*;
@@ -0,0 +1,511 @@
+use crate::error::Error;
+use crate::logfile::{Event, EventHandler};
+use crate::serde::{Deserialize, Serialize};
+use crate::task::{Task, TimeMs};
+use crate::template::DEFAULT_TEMPLATE;
+use crate::typetag;
+use crate::{should_notify, Handler, HandlerErrors};
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::{Read, Write};
+use std::os::unix::process::CommandExt;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// output of a pipe and if the pipe was closed
+type Output = Arc<Mutex<(Vec<u8>, bool)>>;
+
+fn default_max_concurrent() -> usize {
+    4
+}
+
+fn default_timeout() -> String {
+    "30s".into()
+}
+
+fn default_max_results() -> usize {
+    100
+}
+
+/// The outcome of a single command run
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct ShellResult {
+    pub log: String,
+    pub trigger: String,
+    /// time the command was started
+    pub started: TimeMs,
+    /// exit code, None if the command was killed or did not start
+    pub status: Option<i32>,
+    pub timed_out: bool,
+    /// the command was not started because too many commands were running
+    pub skipped: bool,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+/// A shell handler runs a command with sh -c
+/// whenever an event should notify (see TriggerPolicy)
+/// and optionally when a trigger is resolved.
+///
+/// Event fields are passed as environment variables
+/// prefixed with MINUTECAT_ e.g. MINUTECAT_TRIGGER_NAME or
+/// MINUTECAT_CAPTURES_USER. MINUTECAT_MESSAGE is the event rendered
+/// with its template (see Template). The slice is written to stdin.
+///
+/// Commands run in the background. At most max_concurrent commands
+/// run at the same time, events beyond that are skipped.
+/// Commands run in their own process group, the whole group
+/// is killed after the timeout. An invalid timeout is reported
+/// as an error and the command is not run.
+/// The last max_results results are kept and every result
+/// is appended to the output file as a JSON line if one is set.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ShellHandler {
+    command: String,
+
+    #[serde(default)]
+    on_resolved: bool,
+    #[serde(default = "default_max_concurrent")]
+    max_concurrent: usize,
+    #[serde(default = "default_timeout")]
+    timeout: String,
+    #[serde(default = "default_max_results")]
+    max_results: usize,
+    #[serde(default)]
+    output_file: Option<String>,
+
+    #[serde(skip)]
+    running: Arc<AtomicUsize>,
+    #[serde(skip)]
+    results: Arc<Mutex<Vec<ShellResult>>>,
+    #[serde(skip)]
+    errors: HandlerErrors,
+}
+
+impl ShellHandler {
+    pub fn new(command: &str) -> Self {
+        Self {
+            command: command.into(),
+            on_resolved: false,
+            max_concurrent: default_max_concurrent(),
+            timeout: default_timeout(),
+            max_results: default_max_results(),
+            output_file: None,
+            running: Arc::new(AtomicUsize::new(0)),
+            results: Arc::new(Mutex::new(vec![])),
+            errors: HandlerErrors::new(),
+        }
+    }
+
+    pub fn with_on_resolved(mut self, on_resolved: bool) -> Self {
+        self.on_resolved = on_resolved;
+        self
+    }
+
+    pub fn with_max_concurrent(mut self, max_concurrent: usize) -> Self {
+        self.max_concurrent = max_concurrent;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: &str) -> Self {
+        self.timeout = timeout.into();
+        self
+    }
+
+    pub fn with_output_file(mut self, output_file: &str) -> Self {
+        self.output_file = Some(output_file.into());
+        self
+    }
+
+    /// the results of the last commands, oldest first
+    pub fn results(&self) -> Vec<ShellResult> {
+        match self.results.lock() {
+            Ok(results) => results.clone(),
+            _ => vec![],
+        }
+    }
+
+    /// number of commands that are still running
+    pub fn running(&self) -> usize {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    /// blocks until every command finished
+    pub fn wait(&self) {
+        while self.running() > 0 {
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    /// the environment variable name of an event field
+    pub fn env_name(field: &str) -> String {
+        format!(
+            "MINUTECAT_{}",
+            field
+                .chars()
+                .map(|c| if c.is_ascii_alphanumeric() {
+                    c.to_ascii_uppercase()
+                } else {
+                    '_'
+                })
+                .collect::<String>()
+        )
+    }
+
+    fn now() -> TimeMs {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_millis())
+    }
+
+    fn record(
+        results: &Mutex<Vec<ShellResult>>,
+        max_results: usize,
+        output_file: &Option<String>,
+        result: ShellResult,
+    ) {
+        if let Some(path) = output_file {
+            if let (Ok(mut file), Ok(line)) = (
+                OpenOptions::new().create(true).append(true).open(path),
+                serde_json::to_string(&result),
+            ) {
+                let _ = writeln!(file, "{}", line);
+            }
+        }
+        if let Ok(mut results) = results.lock() {
+            results.push(result);
+            let len = results.len();
+            if len > max_results {
+                results.drain(..len - max_results);
+            }
+        }
+    }
+
+    /// reads from a pipe in the background
+    fn reader<R: Read + Send + 'static>(mut input: R) -> Output {
+        let output: Output = Arc::new(Mutex::new((vec![], false)));
+        let shared = output.clone();
+        thread::spawn(move || {
+            let mut chunk = [0; 1024];
+            loop {
+                match input.read(&mut chunk) {
+                    Ok(0) | Err(_) => break,
+                    Ok(n) => {
+                        if let Ok(mut output) = shared.lock() {
+                            output.0.extend_from_slice(&chunk[..n]);
+                        }
+                    }
+                }
+            }
+            if let Ok(mut output) = shared.lock() {
+                output.1 = true;
+            }
+        });
+        output
+    }
+
+    /// kills every process in the group of pid
+    fn kill_group(pid: u32) {
+        let _ = Command::new("kill")
+            .args(["-KILL", "--", &format!("-{}", pid)])
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+    }
+
+    /// runs the command and waits for it to finish or time out
+    fn run(
+        command: &str,
+        env: &HashMap<String, String>,
+        stdin: &str,
+        timeout: Duration,
+        mut result: ShellResult,
+    ) -> Result<ShellResult, Error> {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .envs(env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .process_group(0)
+            .spawn()?;
+
+        if let Some(mut input) = child.stdin.take() {
+            // the command may not read stdin at all, do not block on it
+            let stdin = stdin.to_string();
+            thread::spawn(move || {
+                let _ = input.write_all(stdin.as_bytes());
+            });
+        }
+
+        // read output while the command runs so it can not block on full pipes
+        let stdout = child.stdout.take().map(Self::reader);
+        let stderr = child.stderr.take().map(Self::reader);
+
+        let start = Instant::now();
+        let status = loop {
+            if let Some(status) = child.try_wait()? {
+                break Some(status);
+            }
+            if start.elapsed() > timeout {
+                // sh may have started children of its own
+                Self::kill_group(child.id());
+                let _ = child.kill();
+                let _ = child.wait();
+                break None;
+            }
+            thread::sleep(Duration::from_millis(5));
+        };
+
+        // children of the command may keep the pipes open,
+        // do not wait for them past the timeout
+        let done = |output: &Option<Output>| {
+            output
+                .as_ref()
+                .is_none_or(|o| o.lock().map_or(true, |o| o.1))
+        };
+        while !(done(&stdout) && done(&stderr)) && start.elapsed() <= timeout {
+            thread::sleep(Duration::from_millis(5));
+        }
+        let text = |output: Option<Output>| {
+            output.map_or(String::new(), |o| {
+                o.lock()
+                    .map_or(String::new(), |o| String::from_utf8_lossy(&o.0).into())
+            })
+        };
+
+        result.status = status.and_then(|s| s.code());
+        result.timed_out = status.is_none();
+        result.stdout = text(stdout);
+        result.stderr = text(stderr);
+        Ok(result)
+    }
+}
+
+impl EventHandler for ShellHandler {
+    fn on_event(&mut self, event: &Event) {
+        if !should_notify(event, self.on_resolved) {
+            return;
+        }
+
+        let result = ShellResult {
+            log: event.name.into(),
+            trigger: event.trigger.map_or("", |t| t.name()).into(),
+            started: Self::now(),
+            status: None,
+            timed_out: false,
+            skipped: false,
+            stdout: "".into(),
+            stderr: "".into(),
+        };
+
+        if self.running() >= self.max_concurrent {
+            Self::record(
+                &self.results,
+                self.max_results,
+                &self.output_file,
+                ShellResult {
+                    skipped: true,
+                    ..result
+                },
+            );
+            return;
+        }
+
+        let timeout = match Task::scan(&self.timeout) {
+            Ok(timeout) => Duration::from_millis(timeout as u64),
+            Err(err) => {
+                self.errors
+                    .report(format!("invalid timeout {}: {}", self.timeout, err));
+                return;
+            }
+        };
+
+        let mut env: HashMap<String, String> = event
+            .fields()
+            .into_iter()
+            .map(|(k, v)| (Self::env_name(&k), v))
+            .collect();
+        env.insert(Self::env_name("message"), event.message(DEFAULT_TEMPLATE));
+        let stdin = event.slice.to_string();
+        let command = self.command.clone();
+        let running = self.running.clone();
+        let results = self.results.clone();
+        let max_results = self.max_results;
+        let output_file = self.output_file.clone();
+
+        running.fetch_add(1, Ordering::SeqCst);
+        thread::spawn(move || {
+            let result = match Self::run(&command, &env, &stdin, timeout, result.clone()) {
+                Ok(result) => result,
+                Err(err) => ShellResult {
+                    stderr: err.to_string(),
+                    ..result
+                },
+            };
+            Self::record(&results, max_results, &output_file, result);
+            running.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+}
+
+#[typetag::serde]
+impl Handler for ShellHandler {
+    fn take_errors(&mut self) -> Vec<String> {
+        self.errors.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logfile::Logfile;
+    use crate::source::{DataSourceTypes, InMemoryDataSource};
+    use crate::task::{InMemoryTimeSource, TimeSourceTypes};
+    use crate::{RegexTrigger, TriggerType, TriggerTypes};
+
+    fn logfile(texts: Vec<&str>) -> Logfile {
+        let mut data: Vec<String> = texts.iter().map(|t| t.to_string()).collect();
+        data.reverse();
+        let mut lf = Logfile::new(
+            "app",
+            DataSourceTypes::InMemory(InMemoryDataSource::new(data)),
+            Task::new(
+                true,
+                10,
+                TimeSourceTypes::InMemory(InMemoryTimeSource::new(vec![])),
+            ),
+        );
+        lf.push(TriggerTypes::Regex(RegexTrigger::new(
+            "login",
+            "failed logins",
+            TriggerType::Error,
+            r"login failed for (?P<user>\w+)",
+            false,
+        )));
+        lf
+    }
+
+    #[tokio::test]
+    async fn it_should_run_commands_with_event_fields() {
+        let mut lf = logfile(vec!["login failed for bob"]);
+        let mut handler = ShellHandler::new(
+            "echo $MINUTECAT_NAME $MINUTECAT_TRIGGER_NAME $MINUTECAT_SEVERITY $MINUTECAT_CAPTURES_USER; cat",
+        );
+        lf.force_update(&mut vec![&mut handler]).await.unwrap();
+        handler.wait();
+
+        let results = handler.results();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].status, Some(0));
+        assert_eq!(results[0].trigger, "login");
+        assert_eq!(
+            results[0].stdout,
+            "app login Error bob\nlogin failed for bob"
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_pass_the_rendered_template() {
+        let mut lf = logfile(vec!["login failed for bob"]);
+        lf.template = Some("{{ captures.user | upper }} on {{ name }}".into());
+        let mut handler = ShellHandler::new("echo $MINUTECAT_MESSAGE");
+        lf.force_update(&mut vec![&mut handler]).await.unwrap();
+        handler.wait();
+
+        assert_eq!(handler.results()[0].stdout, "BOB on app\n");
+    }
+
+    #[tokio::test]
+    async fn it_should_only_run_on_notify_and_resolved() {
+        let mut lf = logfile(vec![
+            "login failed for bob",
+            "login failed for bob",
+            "all good",
+        ]);
+        let mut handler = ShellHandler::new("echo $MINUTECAT_TRANSITION").with_on_resolved(true);
+        for _ in 0..3 {
+            lf.force_update(&mut vec![&mut handler]).await.unwrap();
+            handler.wait();
+        }
+
+        let outputs: Vec<String> = handler.results().into_iter().map(|r| r.stdout).collect();
+        assert_eq!(outputs, vec!["Raised\n", "Resolved\n"]);
+    }
+
+    #[tokio::test]
+    async fn it_should_time_out_and_record_failures() {
+        let path = std::env::temp_dir().join("minutecat_shell_test.jsonl");
+        let _ = std::fs::remove_file(&path);
+
+        let mut lf = logfile(vec!["login failed for bob"]);
+        let mut handler = ShellHandler::new("sleep 5")
+            .with_timeout("50ms")
+            .with_output_file(path.to_str().unwrap());
+        lf.force_update(&mut vec![&mut handler]).await.unwrap();
+        handler.wait();
+
+        let results = handler.results();
+        assert!(results[0].timed_out);
+        assert_eq!(results[0].status, None);
+
+        let recorded: ShellResult =
+            serde_json::from_str(std::fs::read_to_string(&path).unwrap().trim()).unwrap();
+        assert_eq!(recorded, results[0]);
+
+        let mut lf = logfile(vec!["login failed for bob"]);
+        let mut handler = ShellHandler::new("exit 3");
+        lf.force_update(&mut vec![&mut handler]).await.unwrap();
+        handler.wait();
+        assert_eq!(handler.results()[0].status, Some(3));
+    }
+
+    #[tokio::test]
+    async fn it_should_limit_concurrency() {
+        let mut lf = logfile(vec!["login failed for bob"]);
+        let mut handler = ShellHandler::new("sleep 0.2").with_max_concurrent(0);
+        lf.force_update(&mut vec![&mut handler]).await.unwrap();
+        handler.wait();
+
+        let results = handler.results();
+        assert!(results[0].skipped);
+        assert_eq!(results[0].status, None);
+    }
+
+    #[tokio::test]
+    async fn it_should_kill_the_process_group() {
+        let mut lf = logfile(vec!["login failed for bob"]);
+        let mut handler = ShellHandler::new("sleep 5 & echo $!; wait").with_timeout("100ms");
+        lf.force_update(&mut vec![&mut handler]).await.unwrap();
+        handler.wait();
+
+        let result = &handler.results()[0];
+        assert!(result.timed_out);
+        // the background sleep is gone (or a zombie) as well
+        let stat = format!("/proc/{}/stat", result.stdout.trim());
+        let start = Instant::now();
+        let alive = || {
+            std::fs::read_to_string(&stat)
+                .is_ok_and(|s| !s.rsplit(')').next().unwrap_or("").starts_with(" Z"))
+        };
+        while alive() && start.elapsed() < Duration::from_secs(2) {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert!(!alive());
+    }
+
+    #[tokio::test]
+    async fn it_should_report_invalid_timeouts() {
+        let mut lf = logfile(vec!["login failed for bob"]);
+        let mut handler = ShellHandler::new("echo hi").with_timeout("soon");
+        lf.force_update(&mut vec![&mut handler]).await.unwrap();
+        handler.wait();
+
+        assert!(handler.results().is_empty());
+        assert_eq!(handler.take_errors().len(), 1);
+    }
+}
@@ -0,0 +1,488 @@
+use crate::error::Error;
+use crate::lettre::message::header::ContentType;
+use crate::lettre::transport::smtp::authentication::Credentials;
+use crate::lettre::transport::smtp::client::{Tls, TlsParameters};
+use crate::lettre::{Message, SmtpTransport, Transport};
+use crate::logfile::{Event, EventHandler};
+use crate::serde::{Deserialize, Serialize};
+use crate::task::{Task, TimeMs};
+use crate::trigger::TriggerType;
+use crate::typetag;
+use crate::{should_notify, Handler, HandlerErrors};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+fn default_port() -> u16 {
+    587
+}
+
+/// The number of unsent entries that are kept for the next digest
+const MAX_KEPT: usize = 1000;
+
+/// The default text of an event in a digest
+const MAIL_TEMPLATE: &str = "[{{ severity }}] {{ name }}/{{ trigger.name }}: \
+{{ trigger.description }} ({{ transition }}){% if slice %}\n{{ slice }}{% endif %}";
+
+/// How the connection to the smtp server is secured
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize, Copy, Clone, Default)]
+pub enum SmtpSecurity {
+    /// no encryption
+    Plain,
+    /// upgrade the connection with STARTTLS
+    #[default]
+    StartTls,
+}
+
+/// A single event waiting for the next digest
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct MailEntry {
+    pub severity: TriggerType,
+    pub text: String,
+}
+
+/// An smtp handler collects events that should notify
+/// (see TriggerPolicy) and sends them as a single digest mail
+/// when the handler is flushed, usually after every update cycle.
+/// Every event is rendered with its template (see Template).
+/// If a window (e.g. 5m) is set the digest is sent once the first
+/// collected event is older than the window. An invalid window
+/// is reported as an error and does not hold the digest back.
+/// The time is the check time of the logs' tasks.
+///
+/// Mails are sent in the background. The entries of mails that
+/// could not be sent are kept and sent again on the next flush.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SmtpHandler {
+    host: String,
+    from: String,
+    to: Vec<String>,
+
+    #[serde(default = "default_port")]
+    port: u16,
+    #[serde(default)]
+    security: SmtpSecurity,
+    #[serde(default)]
+    username: Option<String>,
+    #[serde(default)]
+    password: Option<String>,
+    #[serde(default)]
+    on_resolved: bool,
+    #[serde(default)]
+    window: Option<String>,
+
+    #[serde(skip)]
+    entries: Vec<MailEntry>,
+    /// when the first entry of the digest was collected
+    #[serde(skip)]
+    first: Option<TimeMs>,
+    /// the time of the latest event
+    #[serde(skip)]
+    now: TimeMs,
+    /// entries of mails that could not be sent
+    #[serde(skip)]
+    failed: Arc<Mutex<Vec<MailEntry>>>,
+    #[serde(skip)]
+    pending: Arc<AtomicUsize>,
+    #[serde(skip)]
+    errors: HandlerErrors,
+}
+
+impl SmtpHandler {
+    pub fn new(host: &str, port: u16, from: &str, to: Vec<String>) -> Self {
+        Self {
+            host: host.into(),
+            from: from.into(),
+            to,
+            port,
+            security: SmtpSecurity::StartTls,
+            username: None,
+            password: None,
+            on_resolved: false,
+            window: None,
+            entries: vec![],
+            first: None,
+            now: 0,
+            failed: Arc::new(Mutex::new(vec![])),
+            pending: Arc::new(AtomicUsize::new(0)),
+            errors: HandlerErrors::new(),
+        }
+    }
+
+    pub fn with_security(mut self, security: SmtpSecurity) -> Self {
+        self.security = security;
+        self
+    }
+
+    pub fn with_credentials(mut self, username: &str, password: &str) -> Self {
+        self.username = Some(username.into());
+        self.password = Some(password.into());
+        self
+    }
+
+    pub fn with_on_resolved(mut self, on_resolved: bool) -> Self {
+        self.on_resolved = on_resolved;
+        self
+    }
+
+    pub fn with_window(mut self, window: &str) -> Self {
+        self.window = Some(window.into());
+        self
+    }
+
+    /// the events waiting for the next digest
+    pub fn entries(&self) -> &[MailEntry] {
+        &self.entries
+    }
+
+    /// number of mails that are still being sent
+    pub fn pending(&self) -> usize {
+        self.pending.load(Ordering::SeqCst)
+    }
+
+    /// blocks until every mail was sent
+    pub fn wait(&self) {
+        while self.pending() > 0 {
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    fn error<E: std::fmt::Display>(error: E) -> Error {
+        Error::MailError(error.to_string())
+    }
+
+    fn entry(event: &Event) -> MailEntry {
+        MailEntry {
+            severity: event.trigger_type,
+            text: event.message(MAIL_TEMPLATE),
+        }
+    }
+
+    /// builds the digest mail of the entries
+    pub fn message(&self, entries: &[MailEntry]) -> Result<Message, Error> {
+        let severity = entries.iter().map(|e| e.severity).max().unwrap_or_default();
+        let subject = format!(
+            "[minutecat] {} event(s), highest {}",
+            entries.len(),
+            severity
+        );
+        let body: Vec<&str> = entries.iter().map(|e| e.text.as_str()).collect();
+
+        let mut builder = Message::builder()
+            .from(self.from.parse().map_err(Self::error)?)
+            .subject(subject)
+            .header(ContentType::TEXT_PLAIN);
+        for to in &self.to {
+            builder = builder.to(to.parse().map_err(Self::error)?);
+        }
+        builder.body(body.join("\n\n")).map_err(Self::error)
+    }
+
+    fn transport(&self) -> Result<SmtpTransport, Error> {
+        let mut builder = SmtpTransport::builder_dangerous(&self.host).port(self.port);
+        if self.security == SmtpSecurity::StartTls {
+            let parameters = TlsParameters::new(self.host.clone()).map_err(Self::error)?;
+            builder = builder.tls(Tls::Required(parameters));
+        }
+        if let (Some(username), Some(password)) = (&self.username, &self.password) {
+            builder = builder.credentials(Credentials::new(username.clone(), password.clone()));
+        }
+        Ok(builder.build())
+    }
+
+    fn send(&self, entries: &[MailEntry]) -> Result<(), Error> {
+        let message = self.message(entries)?;
+        self.transport()?.send(&message).map_err(Self::error)?;
+        Ok(())
+    }
+
+    /// keeps the entries of a mail that could not be sent.
+    /// The oldest entries are dropped if too many are kept
+    fn keep(&self, mut entries: Vec<MailEntry>) {
+        if let Ok(mut failed) = self.failed.lock() {
+            entries.append(&mut failed);
+            if entries.len() > MAX_KEPT {
+                let dropped = entries.len() - MAX_KEPT;
+                self.errors
+                    .report(format!("dropped {} unsent digest entries", dropped));
+                entries.drain(..dropped);
+            }
+            *failed = entries;
+        }
+    }
+
+    /// the entries of mails that could not be sent
+    fn take_failed(&self) -> Vec<MailEntry> {
+        match self.failed.lock() {
+            Ok(mut failed) => std::mem::take(&mut *failed),
+            _ => vec![],
+        }
+    }
+
+    /// true if the digest should be sent.
+    /// An invalid window is reported and the digest is sent right away
+    fn window_elapsed(&self) -> bool {
+        let window = match &self.window {
+            Some(window) => window,
+            _ => return true,
+        };
+        let window = match Task::scan(window) {
+            Ok(window) => window,
+            Err(err) => {
+                self.errors
+                    .report(format!("invalid window {}: {}", window, err));
+                return true;
+            }
+        };
+        self.first
+            .is_some_and(|first| self.now.saturating_sub(first) >= window)
+    }
+}
+
+impl EventHandler for SmtpHandler {
+    fn on_event(&mut self, event: &Event) {
+        self.now = self.now.max(event.task.last_time());
+        if !should_notify(event, self.on_resolved) {
+            return;
+        }
+        if self.entries.is_empty() {
+            self.first = Some(event.task.last_time());
+        }
+        self.entries.push(Self::entry(event));
+    }
+}
+
+#[typetag::serde]
+impl Handler for SmtpHandler {
+    /// sends the digest if the window elapsed
+    /// or mails that could not be sent are kept
+    fn flush(&mut self) {
+        let mut entries = self.take_failed();
+        if entries.is_empty() && (self.entries.is_empty() || !self.window_elapsed()) {
+            return;
+        }
+
+        entries.append(&mut self.entries);
+        self.first = None;
+
+        let handler = self.clone();
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        thread::spawn(move || {
+            if let Err(err) = handler.send(&entries) {
+                handler.errors.report(err);
+                handler.keep(entries);
+            }
+            handler.pending.fetch_sub(1, Ordering::SeqCst);
+        });
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        self.errors.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logfile::Logfile;
+    use crate::source::{DataSourceTypes, InMemoryDataSource};
+    use crate::task::{InMemoryTimeSource, TimeSourceTypes};
+    use crate::{RegexTrigger, TriggerTypes};
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+
+    /// a local smtp sink that accepts one mail per connection
+    /// and sends its data to the returned receiver
+    fn sink(mails: usize) -> (u16, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for _ in 0..mails {
+                let (stream, _) = listener.accept().unwrap();
+                let mut writer = stream.try_clone().unwrap();
+                let mut reader = BufReader::new(stream);
+                writer.write_all(b"220 localhost\r\n").unwrap();
+
+                let mut data = String::new();
+                let mut in_data = false;
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).unwrap() == 0 {
+                        break;
+                    }
+                    if in_data {
+                        if line == ".\r\n" {
+                            in_data = false;
+                            writer.write_all(b"250 queued\r\n").unwrap();
+                        } else {
+                            data.push_str(&line);
+                        }
+                        continue;
+                    }
+                    let command = line.to_uppercase();
+                    if command.starts_with("DATA") {
+                        in_data = true;
+                        writer.write_all(b"354 go ahead\r\n").unwrap();
+                    } else if command.starts_with("QUIT") {
+                        writer.write_all(b"221 bye\r\n").unwrap();
+                        break;
+                    } else {
+                        writer.write_all(b"250 ok\r\n").unwrap();
+                    }
+                }
+                tx.send(data).unwrap();
+            }
+        });
+        (port, rx)
+    }
+
+    fn logfile(texts: Vec<&str>) -> Logfile {
+        logfile_at(texts, 0)
+    }
+
+    /// a logfile that is checked at the given time
+    fn logfile_at(texts: Vec<&str>, time: TimeMs) -> Logfile {
+        let mut data: Vec<String> = texts.iter().map(|t| t.to_string()).collect();
+        data.reverse();
+        let mut lf = Logfile::new(
+            "prod-api",
+            DataSourceTypes::InMemory(InMemoryDataSource::new(data)),
+            Task::new(
+                true,
+                10,
+                TimeSourceTypes::InMemory(InMemoryTimeSource::new(vec![time])),
+            ),
+        );
+        lf.push(TriggerTypes::Regex(RegexTrigger::new(
+            "login",
+            "failed logins",
+            TriggerType::Warning,
+            "login failed",
+            false,
+        )));
+        lf.push(TriggerTypes::Regex(RegexTrigger::new(
+            "panic",
+            "panics",
+            TriggerType::Fatal,
+            "panic",
+            false,
+        )));
+        lf
+    }
+
+    #[tokio::test]
+    async fn it_should_send_one_digest_per_cycle() {
+        let (port, rx) = sink(1);
+        let mut handler = SmtpHandler::new(
+            "127.0.0.1",
+            port,
+            "minutecat@example.com",
+            vec!["ops@example.com".into()],
+        )
+        .with_security(SmtpSecurity::Plain);
+
+        let mut lf = logfile(vec!["login failed\npanic"]);
+        lf.force_update(&mut vec![&mut handler]).await.unwrap();
+        assert_eq!(handler.entries().len(), 2);
+
+        handler.flush();
+        assert!(handler.entries().is_empty());
+        handler.wait();
+
+        let mail = rx.recv().unwrap();
+        assert!(mail.contains("Subject: [minutecat] 2 event(s), highest Fatal"));
+        assert!(mail.contains("To: ops@example.com"));
+        assert!(mail.contains("[Warning] prod-api/login: failed logins (Raised)"));
+        assert!(mail.contains("[Fatal] prod-api/panic: panics (Raised)"));
+    }
+
+    #[tokio::test]
+    async fn it_should_wait_for_the_window() {
+        let (port, rx) = sink(1);
+        let mut handler = SmtpHandler::new(
+            "127.0.0.1",
+            port,
+            "minutecat@example.com",
+            vec!["ops@example.com".into()],
+        )
+        .with_security(SmtpSecurity::Plain)
+        .with_window("1h");
+
+        let mut lf = logfile_at(vec!["login failed"], 0);
+        lf.force_update(&mut vec![&mut handler]).await.unwrap();
+        handler.flush();
+        assert_eq!(handler.entries().len(), 1);
+        assert_eq!(handler.pending(), 0);
+
+        // a later check of any log ends the window
+        let mut lf = logfile_at(vec![""], 3_600_000);
+        lf.force_update(&mut vec![&mut handler]).await.unwrap();
+        handler.flush();
+        assert!(handler.entries().is_empty());
+        handler.wait();
+        assert!(rx.recv().unwrap().contains("1 event(s)"));
+    }
+
+    #[tokio::test]
+    async fn it_should_report_invalid_windows() {
+        let (port, rx) = sink(1);
+        let mut handler = SmtpHandler::new(
+            "127.0.0.1",
+            port,
+            "minutecat@example.com",
+            vec!["ops@example.com".into()],
+        )
+        .with_security(SmtpSecurity::Plain)
+        .with_window("soon");
+
+        let mut lf = logfile(vec!["login failed"]);
+        lf.force_update(&mut vec![&mut handler]).await.unwrap();
+        handler.flush();
+        handler.wait();
+        assert_eq!(handler.take_errors().len(), 1);
+        assert!(rx.recv().unwrap().contains("1 event(s)"));
+    }
+
+    #[tokio::test]
+    async fn it_should_keep_unsent_mails() {
+        // nothing listens on the port
+        let closed = TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let mut handler = SmtpHandler::new(
+            "127.0.0.1",
+            closed,
+            "minutecat@example.com",
+            vec!["ops@example.com".into()],
+        )
+        .with_security(SmtpSecurity::Plain);
+
+        let mut lf = logfile(vec!["login failed"]);
+        lf.force_update(&mut vec![&mut handler]).await.unwrap();
+        handler.flush();
+        handler.wait();
+        assert_eq!(handler.take_errors().len(), 1);
+
+        // the next flush sends the kept mail
+        let (port, rx) = sink(1);
+        handler.port = port;
+        handler.flush();
+        handler.wait();
+        assert!(handler.take_errors().is_empty());
+        assert!(rx
+            .recv()
+            .unwrap()
+            .contains("[Warning] prod-api/login: failed logins (Raised)"));
+    }
+
+    #[test]
+    fn it_should_reject_bad_addresses() {
+        let handler = SmtpHandler::new("127.0.0.1", 25, "not an address", vec![]);
+        assert!(matches!(handler.message(&[]), Err(Error::MailError(_))));
+    }
+}
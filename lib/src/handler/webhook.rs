@@ -0,0 +1,657 @@
+use crate::error::Error;
+use crate::logfile::{Event, EventHandler};
+use crate::reqwest;
+use crate::serde::{Deserialize, Serialize};
+use crate::serde_json::{json, Value};
+use crate::task::Task;
+use crate::template::{Template, DEFAULT_TEMPLATE};
+use crate::typetag;
+use crate::{should_notify, Handler, HandlerErrors};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// jobs that may wait for the delivery worker
+const MAX_JOBS: usize = 100;
+
+fn default_retries() -> usize {
+    3
+}
+
+fn default_backoff() -> String {
+    "1s".into()
+}
+
+/// The default message of the Slack preset
+const SLACK_TEMPLATE: &str = "*[{{ severity }}]* {{ name }}/{{ trigger.name }}: \
+{{ trigger.description }} ({{ transition }}){% if slice %}\n```{{ slice }}```{% endif %}";
+
+/// The payload format of a webhook.
+/// Both presets use the template of the event if one is configured.
+/// A body template of the handler replaces the preset
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize, Copy, Clone, Default)]
+pub enum WebhookPreset {
+    /// a JSON object with every event field
+    #[default]
+    Generic,
+    /// a text message for Slack or Mattermost incoming webhooks
+    Slack,
+}
+
+impl WebhookPreset {
+    pub fn payload(&self, event: &Event) -> Value {
+        let trigger = event.trigger.map_or("", |t| t.name());
+        let description = event.trigger.map_or("", |t| t.description());
+        match self {
+            Self::Generic => json!({
+                "log": event.name,
+                "trigger": trigger,
+                "description": description,
+                "severity": event.trigger_type.to_string(),
+                "transition": format!("{:?}", event.transition),
+                "slice": event.slice,
+                "captures": event.captures,
+                "next_time": event.task.next_time().to_string(),
+                "message": event.message(DEFAULT_TEMPLATE),
+            }),
+            Self::Slack => json!({ "text": event.message(SLACK_TEMPLATE) }),
+        }
+    }
+}
+
+/// A delivery that is waiting in the queue
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+struct Delivery {
+    url: String,
+    payload: Value,
+}
+
+/// A job of the delivery worker
+enum Job {
+    Deliver(Delivery),
+    /// sends the deliveries of the queue file
+    Drain,
+}
+
+/// A webhook handler POSTs a JSON payload to a url
+/// whenever an event should notify (see TriggerPolicy)
+/// and optionally when a trigger is resolved.
+///
+/// Deliveries run one after another on a background worker
+/// and are retried with exponential backoff (backoff, 2*backoff, ...).
+/// Deliveries that still fail, or do not fit into the worker's
+/// backlog, are appended to the queue file and sent again on every flush.
+/// Queued lines that can not be read are moved to `<queue_file>.dead`.
+///
+/// The body may be a template of the whole JSON payload
+/// with the fields of the event and the rendered message e.g.
+/// `{"text": "{{ name }}: {{ message | json }}", "level": "{{ severity | lower }}"}`.
+/// Use the json filter for values inside of JSON strings
+#[derive(Clone, Serialize, Deserialize)]
+pub struct WebhookHandler {
+    url: String,
+
+    #[serde(default)]
+    preset: WebhookPreset,
+    #[serde(default)]
+    on_resolved: bool,
+    #[serde(default = "default_retries")]
+    retries: usize,
+    #[serde(default = "default_backoff")]
+    backoff: String,
+    #[serde(default)]
+    queue_file: Option<String>,
+    #[serde(default)]
+    body: Option<String>,
+
+    #[serde(skip)]
+    pending: Arc<AtomicUsize>,
+    #[serde(skip)]
+    errors: HandlerErrors,
+    /// set while a drain of the queue file waits or runs
+    #[serde(skip)]
+    draining: Arc<AtomicBool>,
+    /// the jobs of the delivery worker, started with the first job
+    #[serde(skip)]
+    worker: Arc<Mutex<Option<SyncSender<Job>>>>,
+}
+
+impl WebhookHandler {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.into(),
+            preset: WebhookPreset::Generic,
+            on_resolved: false,
+            retries: default_retries(),
+            backoff: default_backoff(),
+            queue_file: None,
+            body: None,
+            pending: Arc::new(AtomicUsize::new(0)),
+            errors: HandlerErrors::new(),
+            draining: Arc::new(AtomicBool::new(false)),
+            worker: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub fn with_preset(mut self, preset: WebhookPreset) -> Self {
+        self.preset = preset;
+        self
+    }
+
+    pub fn with_on_resolved(mut self, on_resolved: bool) -> Self {
+        self.on_resolved = on_resolved;
+        self
+    }
+
+    pub fn with_retries(mut self, retries: usize, backoff: &str) -> Self {
+        self.retries = retries;
+        self.backoff = backoff.into();
+        self
+    }
+
+    pub fn with_queue_file(mut self, queue_file: &str) -> Self {
+        self.queue_file = Some(queue_file.into());
+        self
+    }
+
+    pub fn with_body(mut self, body: &str) -> Self {
+        self.body = Some(body.into());
+        self
+    }
+
+    /// the JSON payload of an event.
+    /// The body template if configured, otherwise the preset
+    pub fn payload(&self, event: &Event) -> Result<Value, Error> {
+        let body = match &self.body {
+            Some(body) => body,
+            None => return Ok(self.preset.payload(event)),
+        };
+        let mut fields = event.fields();
+        fields.insert("message".into(), event.message(DEFAULT_TEMPLATE));
+        let rendered = Template::parse(body)?.render(&fields);
+        Ok(serde_json::from_str(&rendered)?)
+    }
+
+    /// number of jobs that are waiting or running
+    pub fn pending(&self) -> usize {
+        self.pending.load(Ordering::SeqCst)
+    }
+
+    /// blocks until every job finished
+    pub fn wait(&self) {
+        while self.pending() > 0 {
+            thread::sleep(Duration::from_millis(5));
+        }
+    }
+
+    fn post(client: &reqwest::blocking::Client, delivery: &Delivery) -> Result<(), Error> {
+        client
+            .post(&delivery.url)
+            .json(&delivery.payload)
+            .send()?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    /// posts with retries
+    fn deliver(
+        client: &reqwest::blocking::Client,
+        delivery: &Delivery,
+        retries: usize,
+        backoff: Duration,
+    ) -> Result<(), Error> {
+        let mut attempt = 0;
+        loop {
+            match Self::post(client, delivery) {
+                Ok(_) => return Ok(()),
+                Err(err) if attempt >= retries => return Err(err),
+                Err(_) => {
+                    thread::sleep(backoff * 2u32.saturating_pow(attempt as u32));
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    /// sends every queued delivery once and keeps the ones that failed.
+    /// Broken lines are moved to the dead letter file
+    fn drain(
+        client: &reqwest::blocking::Client,
+        path: &str,
+        errors: &HandlerErrors,
+    ) -> Result<(), Error> {
+        let queued = match fs::read_to_string(path) {
+            Ok(queued) => queued,
+            _ => return Ok(()),
+        };
+
+        let mut failed = vec![];
+        let mut last_err = None;
+        for line in queued.lines().filter(|l| !l.is_empty()) {
+            let delivery: Delivery = match serde_json::from_str(line) {
+                Ok(delivery) => delivery,
+                Err(err) => {
+                    errors.report(format!(
+                        "broken queue entry moved to {}.dead: {}",
+                        path, err
+                    ));
+                    Self::append(&format!("{}.dead", path), line)?;
+                    continue;
+                }
+            };
+            if let Err(err) = Self::post(client, &delivery) {
+                failed.push(line);
+                last_err = Some(err);
+            }
+        }
+
+        if failed.is_empty() {
+            fs::remove_file(path)?;
+        } else {
+            fs::write(path, format!("{}\n", failed.join("\n")))?;
+        }
+        if let Some(err) = last_err {
+            errors.report(format!(
+                "{} queued deliveries failed, last error: {}",
+                failed.len(),
+                err
+            ));
+        }
+        Ok(())
+    }
+
+    fn append(path: &str, line: &str) -> Result<(), Error> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", line)?;
+        Ok(())
+    }
+
+    fn client() -> Result<reqwest::blocking::Client, Error> {
+        Ok(reqwest::blocking::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()?)
+    }
+
+    /// delivers with retries and keeps failed deliveries in the queue file
+    fn send(
+        client: &reqwest::blocking::Client,
+        delivery: &Delivery,
+        retries: usize,
+        backoff: Duration,
+        queue_file: Option<&str>,
+    ) -> Result<(), Error> {
+        let result = Self::deliver(client, delivery, retries, backoff);
+        if let (Err(_), Some(path)) = (&result, queue_file) {
+            Self::append(path, &serde_json::to_string(delivery)?)?;
+        }
+        result
+    }
+
+    /// starts the delivery worker.
+    /// It stops once every clone of the handler is dropped
+    fn start(&self) -> Result<SyncSender<Job>, Error> {
+        let backoff = Duration::from_millis(Task::scan(&self.backoff)? as u64);
+        let retries = self.retries;
+        let queue_file = self.queue_file.clone();
+        let errors = self.errors.clone();
+        let pending = self.pending.clone();
+        let draining = self.draining.clone();
+
+        let (sender, receiver) = mpsc::sync_channel(MAX_JOBS);
+        thread::spawn(move || {
+            // the blocking client may not be created in an async context
+            let client = Self::client().map_err(|err| errors.report(err)).ok();
+            for job in receiver {
+                let result = match (&client, job) {
+                    (Some(client), Job::Deliver(delivery)) => {
+                        Self::send(client, &delivery, retries, backoff, queue_file.as_deref())
+                    }
+                    (client, Job::Drain) => {
+                        let result = match (client, &queue_file) {
+                            (Some(client), Some(path)) => Self::drain(client, path, &errors),
+                            _ => Ok(()),
+                        };
+                        draining.store(false, Ordering::SeqCst);
+                        result
+                    }
+                    // the client error was reported when the worker started
+                    (None, Job::Deliver(_)) => Ok(()),
+                };
+                if let Err(err) = result {
+                    errors.report(err);
+                }
+                pending.fetch_sub(1, Ordering::SeqCst);
+            }
+        });
+        Ok(sender)
+    }
+
+    /// the sender of the worker, starts the worker if needed
+    fn sender(&self) -> Result<SyncSender<Job>, Error> {
+        let mut worker = self.worker.lock().map_err(|_| Error::GenericError)?;
+        match &*worker {
+            Some(sender) => Ok(sender.clone()),
+            None => Ok(worker.insert(self.start()?).clone()),
+        }
+    }
+
+    /// hands a job to the worker.
+    /// Deliveries that do not fit are kept in the queue file,
+    /// errors are reported
+    fn enqueue(&self, job: Job) {
+        let sender = match self.sender() {
+            Ok(sender) => sender,
+            Err(err) => {
+                if let Job::Drain = job {
+                    self.draining.store(false, Ordering::SeqCst);
+                }
+                self.errors.report(err);
+                return;
+            }
+        };
+
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        let (job, reason) = match sender.try_send(job) {
+            Ok(_) => return,
+            Err(TrySendError::Full(job)) => (job, "too many pending deliveries"),
+            Err(TrySendError::Disconnected(job)) => {
+                // start a new worker with the next job
+                if let Ok(mut worker) = self.worker.lock() {
+                    *worker = None;
+                }
+                (job, "the delivery worker stopped")
+            }
+        };
+        self.pending.fetch_sub(1, Ordering::SeqCst);
+
+        let delivery = match job {
+            Job::Deliver(delivery) => delivery,
+            Job::Drain => {
+                self.draining.store(false, Ordering::SeqCst);
+                return;
+            }
+        };
+        let kept = match &self.queue_file {
+            Some(path) => {
+                serde_json::to_string(&delivery).is_ok_and(|line| Self::append(path, &line).is_ok())
+            }
+            None => false,
+        };
+        self.errors.report(format!(
+            "{}, delivery {}",
+            reason,
+            if kept { "queued" } else { "dropped" }
+        ));
+    }
+}
+
+impl EventHandler for WebhookHandler {
+    fn on_event(&mut self, event: &Event) {
+        if !should_notify(event, self.on_resolved) {
+            return;
+        }
+
+        let payload = match self.payload(event) {
+            Ok(payload) => payload,
+            Err(err) => {
+                self.errors.report(format!("invalid body: {}", err));
+                return;
+            }
+        };
+        let delivery = Delivery {
+            url: self.url.clone(),
+            payload,
+        };
+        self.enqueue(Job::Deliver(delivery));
+    }
+}
+
+#[typetag::serde]
+impl Handler for WebhookHandler {
+    /// sends the queued deliveries in the background
+    /// unless a drain is still waiting or running
+    fn flush(&mut self) {
+        let queued = match &self.queue_file {
+            Some(path) => Path::new(path).exists(),
+            None => false,
+        };
+        if !queued || self.draining.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        self.enqueue(Job::Drain);
+    }
+
+    fn take_errors(&mut self) -> Vec<String> {
+        self.errors.take()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logfile::Logfile;
+    use crate::source::{DataSourceTypes, InMemoryDataSource};
+    use crate::task::{InMemoryTimeSource, TimeSourceTypes};
+    use crate::{RegexTrigger, TriggerType, TriggerTypes};
+    use std::io::{BufRead, BufReader, Read};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+
+    /// a local http stand-in that answers with the given status codes
+    /// and sends every request body to the returned receiver
+    fn server(statuses: Vec<u16>) -> (String, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let (tx, rx) = mpsc::channel();
+        thread::spawn(move || {
+            for status in statuses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut len = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if let Some(value) = line.to_lowercase().strip_prefix("content-length:") {
+                        len = value.trim().parse().unwrap();
+                    }
+                    if line == "\r\n" {
+                        break;
+                    }
+                }
+                let mut body = vec![0; len];
+                reader.read_exact(&mut body).unwrap();
+                tx.send(String::from_utf8(body).unwrap()).unwrap();
+
+                let mut stream = reader.into_inner();
+                write!(
+                    stream,
+                    "HTTP/1.1 {} X\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                    status
+                )
+                .unwrap();
+            }
+        });
+        (url, rx)
+    }
+
+    fn logfile() -> Logfile {
+        let mut lf = Logfile::new(
+            "prod-api",
+            DataSourceTypes::InMemory(InMemoryDataSource::new(vec!["login failed for bob".into()])),
+            Task::new(
+                true,
+                10,
+                TimeSourceTypes::InMemory(InMemoryTimeSource::new(vec![])),
+            ),
+        );
+        lf.push(TriggerTypes::Regex(RegexTrigger::new(
+            "login",
+            "failed logins",
+            TriggerType::Error,
+            r"login failed for (?P<user>\w+)",
+            false,
+        )));
+        lf
+    }
+
+    #[tokio::test]
+    async fn it_should_post_generic_payloads() {
+        let (url, rx) = server(vec![200]);
+        let mut handler = WebhookHandler::new(&url);
+        logfile()
+            .force_update(&mut vec![&mut handler])
+            .await
+            .unwrap();
+        handler.wait();
+
+        let body: Value = serde_json::from_str(&rx.recv().unwrap()).unwrap();
+        assert_eq!(body["log"], "prod-api");
+        assert_eq!(body["trigger"], "login");
+        assert_eq!(body["severity"], "Error");
+        assert_eq!(body["transition"], "Raised");
+        assert_eq!(body["captures"]["user"], "bob");
+        assert_eq!(
+            body["message"],
+            "[Error] prod-api/login: failed logins (Raised)"
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_post_slack_payloads_with_retries() {
+        let (url, rx) = server(vec![500, 200]);
+        let mut handler = WebhookHandler::new(&url)
+            .with_preset(WebhookPreset::Slack)
+            .with_retries(2, "10ms");
+        logfile()
+            .force_update(&mut vec![&mut handler])
+            .await
+            .unwrap();
+        handler.wait();
+
+        let first: Value = serde_json::from_str(&rx.recv().unwrap()).unwrap();
+        let second: Value = serde_json::from_str(&rx.recv().unwrap()).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(
+            second["text"],
+            "*[Error]* prod-api/login: failed logins (Raised)\n```login failed for bob```"
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_queue_failed_deliveries() {
+        let dir = tempfile::tempdir().unwrap();
+        let queue = dir.path().join("queue.jsonl");
+        let queue = queue.to_str().unwrap();
+
+        // nothing listens on the url
+        let closed = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}/hook", listener.local_addr().unwrap())
+        };
+        let mut handler = WebhookHandler::new(&closed)
+            .with_retries(1, "10ms")
+            .with_queue_file(queue);
+        logfile()
+            .force_update(&mut vec![&mut handler])
+            .await
+            .unwrap();
+        handler.wait();
+        assert_eq!(fs::read_to_string(queue).unwrap().lines().count(), 1);
+
+        // the queued delivery is sent on the next flush
+        let (url, rx) = server(vec![200, 200]);
+        let contents = fs::read_to_string(queue).unwrap().replace(&closed, &url);
+        fs::write(queue, contents).unwrap();
+
+        let mut handler = WebhookHandler::new(&url).with_queue_file(queue);
+        logfile()
+            .force_update(&mut vec![&mut handler])
+            .await
+            .unwrap();
+        handler.wait();
+        assert!(rx.recv().is_ok());
+        assert_eq!(fs::read_to_string(queue).unwrap().lines().count(), 1);
+
+        handler.flush();
+        handler.wait();
+        assert!(rx.recv().is_ok());
+        assert!(fs::metadata(queue).is_err());
+    }
+
+    #[tokio::test]
+    async fn it_should_render_body_templates() {
+        let (url, rx) = server(vec![200]);
+        let mut handler = WebhookHandler::new(&url)
+            .with_body(r#"{"text": "{{ message | json }}", "user": "{{ captures.user }}"}"#);
+        logfile()
+            .force_update(&mut vec![&mut handler])
+            .await
+            .unwrap();
+        handler.wait();
+
+        let body: Value = serde_json::from_str(&rx.recv().unwrap()).unwrap();
+        assert_eq!(
+            body,
+            json!({
+                "text": "[Error] prod-api/login: failed logins (Raised)",
+                "user": "bob",
+            })
+        );
+
+        // broken bodies are reported instead of sent
+        let mut handler = WebhookHandler::new(&url).with_body("{{ name }}");
+        logfile()
+            .force_update(&mut vec![&mut handler])
+            .await
+            .unwrap();
+        handler.wait();
+        assert_eq!(handler.take_errors().len(), 1);
+        assert!(handler.take_errors().is_empty());
+    }
+
+    #[test]
+    fn it_should_drain_the_queue_on_flush() {
+        let dir = tempfile::tempdir().unwrap();
+        let queue = dir.path().join("queue.jsonl");
+        let queue = queue.to_str().unwrap();
+        let dead = format!("{}.dead", queue);
+
+        let (url, rx) = server(vec![200]);
+        let delivery = Delivery {
+            url,
+            payload: json!({ "text": "queued" }),
+        };
+        let contents = format!("not json\n{}\n", serde_json::to_string(&delivery).unwrap());
+        fs::write(queue, contents).unwrap();
+
+        let mut handler = WebhookHandler::new("http://127.0.0.1:1/hook").with_queue_file(queue);
+        handler.flush();
+        handler.wait();
+
+        // the broken line does not block the queue
+        assert_eq!(rx.recv().unwrap(), r#"{"text":"queued"}"#);
+        assert!(fs::metadata(queue).is_err());
+        assert_eq!(fs::read_to_string(&dead).unwrap(), "not json\n");
+        assert_eq!(handler.take_errors().len(), 1);
+
+        // nothing is left to send
+        handler.flush();
+        assert_eq!(handler.pending(), 0);
+
+        // deliveries that fail again are kept and reported
+        let delivery = Delivery {
+            url: "http://127.0.0.1:1/hook".into(),
+            payload: json!({ "text": "queued" }),
+        };
+        fs::write(queue, serde_json::to_string(&delivery).unwrap()).unwrap();
+        handler.flush();
+        handler.wait();
+        assert_eq!(fs::read_to_string(queue).unwrap().lines().count(), 1);
+        assert_eq!(handler.take_errors().len(), 1);
+    }
+}
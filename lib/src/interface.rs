@@ -1,8 +1,16 @@
+use super::ack::{current_user, Ack};
 use super::clap::{Args, Parser, Subcommand};
 use super::command::*;
 use super::dirs;
 use super::error::Error;
+use super::handler::{HandlerTypes, HistoryHandler, HistoryQuery};
 use super::logset::LogSet;
+use super::policy::TriggerPolicy;
+use super::silence::{
+    format_local_time, parse_local_time, MaintenanceWindow, Silence, SilenceMatcher,
+};
+use super::state::State;
+use super::task::{ClockTimeSource, Task, TimeMs, TimeSource};
 use super::trigger::{Trigger, TriggerType};
 use std::env;
 use std::path::PathBuf;
@@ -45,6 +53,24 @@ pub enum SubCommand {
     ListTrigger(ListTrigger),
 
     DeleteTrigger(DeleteTrigger),
+
+    SetPolicy(SetPolicy),
+
+    RenderTemplate(RenderTemplate),
+
+    AddSilence(AddSilence),
+
+    AddMaintenance(AddMaintenance),
+
+    ListSilences(ListSilences),
+
+    DeleteSilence(DeleteSilence),
+
+    DeleteMaintenance(DeleteMaintenance),
+
+    Ack(AckTrigger),
+
+    History(History),
 }
 
 #[derive(Args)]
@@ -73,6 +99,9 @@ pub struct AddReTrigger {
     pub regex: String,
     #[clap(long)]
     pub invert: bool,
+    /// match every line on its own
+    #[clap(long)]
+    pub per_line: bool,
 }
 
 #[derive(Args)]
@@ -86,6 +115,140 @@ pub struct DeleteTrigger {
     pub trigger_index: usize,
 }
 
+#[derive(Args)]
+pub struct SetPolicy {
+    pub log_index: usize,
+    /// name of the trigger
+    pub trigger: String,
+    /// suppress notifications for this long after a notification e.g. 10m
+    #[clap(long)]
+    pub cooldown: Option<String>,
+    /// number of consecutive checks the trigger has to fire
+    #[clap(long, default_value = "0")]
+    pub debounce: usize,
+    /// notify again after this long while the trigger keeps firing
+    #[clap(long)]
+    pub renotify_every: Option<String>,
+}
+
+#[derive(Args)]
+pub struct RenderTemplate {
+    pub log_index: usize,
+    /// name of the trigger
+    pub trigger: String,
+    /// sample text the trigger is checked against
+    pub text: String,
+    /// render this template instead of the configured one
+    #[clap(long)]
+    pub template: Option<String>,
+}
+
+#[derive(Args)]
+pub struct SilenceArgs {
+    /// glob of the log name e.g. prod-*
+    #[clap(long)]
+    pub log: Option<String>,
+    /// glob of the trigger name
+    #[clap(long)]
+    pub trigger: Option<String>,
+    /// lowest severity e.g. error also silences fatal events
+    #[clap(long)]
+    pub severity: Option<TriggerType>,
+    #[clap(long, default_value = "")]
+    pub comment: String,
+}
+
+impl SilenceArgs {
+    fn matcher(&self) -> SilenceMatcher {
+        SilenceMatcher {
+            log: self.log.clone(),
+            trigger: self.trigger.clone(),
+            severity: self.severity,
+        }
+    }
+}
+
+#[derive(Args)]
+pub struct AddSilence {
+    #[clap(flatten)]
+    pub args: SilenceArgs,
+    /// silence for this long e.g. 2h
+    #[clap(long, conflicts_with = "until", required_unless_present = "until")]
+    pub duration: Option<String>,
+    /// silence until this local time e.g. "2024-09-01 14:30"
+    #[clap(long)]
+    pub until: Option<String>,
+}
+
+#[derive(Args)]
+pub struct AddMaintenance {
+    #[clap(flatten)]
+    pub args: SilenceArgs,
+    /// start time e.g. 02:00
+    pub start: String,
+    /// end time e.g. 04:00
+    pub end: String,
+    /// days the window starts on e.g. sat,sun. Every day if not set
+    #[clap(long, use_value_delimiter = true)]
+    pub days: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct ListSilences;
+
+#[derive(Args)]
+pub struct DeleteSilence {
+    pub index: usize,
+}
+
+#[derive(Args)]
+pub struct DeleteMaintenance {
+    pub index: usize,
+}
+
+#[derive(Args)]
+pub struct AckTrigger {
+    pub log_index: usize,
+    /// name of the trigger
+    pub trigger: String,
+    /// who handles the problem, defaults to the current user
+    #[clap(long)]
+    pub by: Option<String>,
+    /// the ack ends after this long e.g. 2h
+    #[clap(long)]
+    pub expires: Option<String>,
+    #[clap(long, default_value = "")]
+    pub comment: String,
+    /// remove the ack
+    #[clap(long)]
+    pub clear: bool,
+}
+
+#[derive(Args)]
+pub struct History {
+    /// entries since a local time e.g. "2024-09-01 14:30" or a time ago e.g. 1h
+    #[clap(long)]
+    pub since: Option<String>,
+    /// entries before a local time or a time ago
+    #[clap(long)]
+    pub until: Option<String>,
+    /// glob of the log name
+    #[clap(long)]
+    pub log: Option<String>,
+    /// glob of the trigger name
+    #[clap(long)]
+    pub trigger: Option<String>,
+    /// the lowest severity
+    #[clap(long)]
+    pub severity: Option<TriggerType>,
+    /// the history file, required if the history handlers use several files
+    #[clap(long)]
+    pub file: Option<String>,
+    /// print JSON lines
+    #[clap(long)]
+    pub json: bool,
+}
+
 // TODO allow user to move config path?
 pub fn config_path() -> PathBuf {
     let default = dirs::home_dir()
@@ -108,7 +271,8 @@ pub fn init_logset() -> Result<(LogSet, String), Error> {
         .expect("could not find configuration directory!");
 
     init_cfg_dir()?;
-    let logset = LogSet::from_path(cfg_path)?;
+    let mut logset = LogSet::from_path(cfg_path)?;
+    logset.state_path = Some(State::path(cfg_path));
 
     Ok((logset, cfg_path.into()))
 }
@@ -116,6 +280,7 @@ pub fn init_logset() -> Result<(LogSet, String), Error> {
 pub fn command_line() -> Result<Interface, Error> {
     let options = Opts::parse();
     let (mut logset, cfg_path) = init_logset()?;
+    logset.load_state()?;
 
     let exit = match &options.subcmd {
         Some(subcmd) => match &subcmd {
@@ -125,11 +290,21 @@ pub fn command_line() -> Result<Interface, Error> {
             SubCommand::AddReTrigger(re) => add_re_trigger(re, &mut logset)?,
             SubCommand::ListTrigger(lt) => list_trigger(lt, &mut logset)?,
             SubCommand::DeleteTrigger(dt) => delete_trigger(dt, &mut logset)?,
+            SubCommand::SetPolicy(sp) => set_policy(sp, &mut logset)?,
+            SubCommand::RenderTemplate(rt) => render_template(rt, &mut logset)?,
+            SubCommand::AddSilence(si) => add_silence(si, &mut logset)?,
+            SubCommand::AddMaintenance(mw) => add_maintenance(mw, &mut logset)?,
+            SubCommand::ListSilences(ls) => list_silences(ls, &mut logset)?,
+            SubCommand::DeleteSilence(ds) => delete_silence(ds, &mut logset)?,
+            SubCommand::DeleteMaintenance(dm) => delete_maintenance(dm, &mut logset)?,
+            SubCommand::Ack(ack) => ack_trigger(ack, &mut logset)?,
+            SubCommand::History(history) => history_cmd(history, &mut logset)?,
         },
         _ => false,
     };
 
     logset.to_file(&cfg_path)?;
+    logset.save_state()?;
     if exit {
         std::process::exit(0);
     }
@@ -193,7 +368,8 @@ pub fn add_re_trigger(re: &AddReTrigger, logset: &mut LogSet) -> Result<bool, Er
         }
 
         let mut cmd =
-            AddRegexTriggerCommand::new(&re.name, &re.desc, re.trigger_type, &re.regex, re.invert);
+            AddRegexTriggerCommand::new(&re.name, &re.desc, re.trigger_type, &re.regex, re.invert)
+                .with_per_line(re.per_line);
         cmd.execute(log)?;
     }
     Ok(true)
@@ -223,3 +399,229 @@ pub fn delete_trigger(dt: &DeleteTrigger, logset: &mut LogSet) -> Result<bool, E
     }
     Ok(true)
 }
+
+pub fn set_policy(sp: &SetPolicy, logset: &mut LogSet) -> Result<bool, Error> {
+    if sp.log_index >= logset.len() {
+        println!("Index out of bounds!");
+    } else {
+        let log = &mut logset.logs[sp.log_index];
+
+        let policy = TriggerPolicy {
+            cooldown: sp.cooldown.clone(),
+            debounce: sp.debounce,
+            renotify_every: sp.renotify_every.clone(),
+        };
+        let mut cmd = SetPolicyCommand::new(&sp.trigger, policy);
+        cmd.execute(log)?;
+    }
+    Ok(true)
+}
+
+pub fn render_template(rt: &RenderTemplate, logset: &mut LogSet) -> Result<bool, Error> {
+    if rt.log_index >= logset.len() {
+        println!("Index out of bounds!");
+    } else {
+        let log = &logset.logs[rt.log_index];
+
+        match log.render_template(&rt.trigger, &rt.text, rt.template.as_deref())? {
+            Some(message) => println!("{}", message),
+            _ => println!("Unknown trigger!"),
+        }
+    }
+    Ok(true)
+}
+
+pub fn add_silence(si: &AddSilence, logset: &mut LogSet) -> Result<bool, Error> {
+    let now = ClockTimeSource.get_time_ms();
+    let ends = match (&si.duration, &si.until) {
+        (Some(duration), None) => now + Task::scan(duration)?,
+        (None, Some(until)) => parse_local_time(until)?,
+        (Some(_), Some(_)) => {
+            println!("Use either --duration or --until, not both!");
+            return Err(Error::GenericError);
+        }
+        (None, None) => {
+            println!("Either --duration or --until is required!");
+            return Err(Error::GenericError);
+        }
+    };
+
+    logset.silences.prune(now);
+    let mut cmd = AddSilenceCommand::new(Silence::new(si.args.matcher(), ends, &si.args.comment));
+    cmd.execute(logset)?;
+    Ok(true)
+}
+
+pub fn add_maintenance(mw: &AddMaintenance, logset: &mut LogSet) -> Result<bool, Error> {
+    let window = MaintenanceWindow::new(
+        mw.args.matcher(),
+        mw.days.clone(),
+        &mw.start,
+        &mw.end,
+        &mw.args.comment,
+    )?;
+    let mut cmd = AddMaintenanceCommand::new(window);
+    cmd.execute(logset)?;
+    Ok(true)
+}
+
+pub fn list_silences(_ls: &ListSilences, logset: &mut LogSet) -> Result<bool, Error> {
+    for (i, silence) in logset.silences.silences.iter().enumerate() {
+        println!(
+            "silence {}: {} until {} - {}",
+            i,
+            silence.matcher,
+            format_local_time(silence.ends),
+            silence.comment
+        );
+    }
+    for (i, window) in logset.silences.maintenance.iter().enumerate() {
+        let days = if window.days.is_empty() {
+            "daily".into()
+        } else {
+            window.days.join(",")
+        };
+        println!(
+            "maintenance {}: {} {} {}-{} - {}",
+            i, window.matcher, days, window.start, window.end, window.comment
+        );
+    }
+    Ok(true)
+}
+
+pub fn delete_silence(ds: &DeleteSilence, logset: &mut LogSet) -> Result<bool, Error> {
+    let mut cmd = DeleteSilenceCommand::new(ds.index);
+    cmd.execute(logset)?;
+    Ok(true)
+}
+
+pub fn delete_maintenance(dm: &DeleteMaintenance, logset: &mut LogSet) -> Result<bool, Error> {
+    let mut cmd = DeleteMaintenanceCommand::new(dm.index);
+    cmd.execute(logset)?;
+    Ok(true)
+}
+
+pub fn ack_trigger(at: &AckTrigger, logset: &mut LogSet) -> Result<bool, Error> {
+    if at.log_index >= logset.len() {
+        println!("Index out of bounds!");
+    } else {
+        let log = &mut logset.logs[at.log_index];
+
+        let ack = if at.clear {
+            None
+        } else {
+            let now = ClockTimeSource.get_time_ms();
+            let by = at.by.clone().unwrap_or_else(current_user);
+            let mut ack = Ack::new(&by, now).with_comment(&at.comment);
+            if let Some(expires) = &at.expires {
+                ack = ack.with_expires(now + Task::scan(expires)?);
+            }
+            Some(ack)
+        };
+        let mut cmd = AckCommand::new(&at.trigger, ack);
+        cmd.execute(log)?;
+    }
+    Ok(true)
+}
+
+/// the sorted files of the history handlers of the logset and its logs
+fn history_files(logset: &LogSet) -> Vec<String> {
+    let mut files: Vec<String> = logset
+        .handlers
+        .values()
+        .chain(logset.logs.iter().flat_map(|l| l.handlers.values()))
+        .filter_map(|h| match h {
+            HandlerTypes::History(h) => Some(h.path().to_string()),
+            _ => None,
+        })
+        .collect();
+    files.sort();
+    files.dedup();
+    files
+}
+
+/// parses a local time or a time ago
+fn history_time(time: &Option<String>, now: TimeMs) -> Result<Option<TimeMs>, Error> {
+    match time {
+        Some(time) => match parse_local_time(time) {
+            Ok(time) => Ok(Some(time)),
+            _ => Ok(Some(now.saturating_sub(Task::scan(time)?))),
+        },
+        _ => Ok(None),
+    }
+}
+
+pub fn history_cmd(history: &History, logset: &mut LogSet) -> Result<bool, Error> {
+    let file = match &history.file {
+        Some(file) => file.clone(),
+        _ => match &history_files(logset)[..] {
+            [file] => file.clone(),
+            [] => {
+                println!("No history handler configured!");
+                return Ok(true);
+            }
+            files => {
+                println!(
+                    "Several history files configured, pick one with --file: {}",
+                    files.join(", ")
+                );
+                return Ok(true);
+            }
+        },
+    };
+
+    let now = ClockTimeSource.get_time_ms();
+    let query = HistoryQuery {
+        since: history_time(&history.since, now)?,
+        until: history_time(&history.until, now)?,
+        log: history.log.clone(),
+        trigger: history.trigger.clone(),
+        severity: history.severity,
+    };
+
+    for entry in HistoryHandler::query(&file, &query)? {
+        if history.json {
+            println!("{}", serde_json::to_string(&entry)?);
+        } else {
+            println!(
+                "{} [{}] {}/{} {:?}: {}",
+                format_local_time(entry.time),
+                entry.severity,
+                entry.log,
+                entry.trigger,
+                entry.transition,
+                entry.slice.replace('\n', " ")
+            );
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_require_either_duration_or_until() {
+        let parse =
+            |args: &[&str]| Opts::try_parse_from([&["minutecat", "add-silence"], args].concat());
+        assert!(parse(&["--duration", "2h"]).is_ok());
+        assert!(parse(&["--until", "2024-09-01 14:30"]).is_ok());
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["--duration", "2h", "--until", "2024-09-01 14:30"]).is_err());
+    }
+
+    #[test]
+    fn it_should_list_history_files_in_order() {
+        use crate::HistoryHandler;
+
+        let mut logset = LogSet::new();
+        for (name, path) in [("b", "b.jsonl"), ("a", "a.jsonl"), ("c", "b.jsonl")] {
+            logset.handlers.insert(
+                name.into(),
+                HandlerTypes::History(HistoryHandler::new(path)),
+            );
+        }
+        assert_eq!(history_files(&logset), vec!["a.jsonl", "b.jsonl"]);
+    }
+}
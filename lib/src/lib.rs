@@ -1,29 +1,57 @@
 extern crate async_trait;
+extern crate chrono;
 extern crate clap;
 extern crate dirs;
+extern crate lettre;
 extern crate regex;
 extern crate reqwest;
+extern crate rhai;
 extern crate serde;
+extern crate serde_json;
 extern crate serde_yaml;
 extern crate tokio;
 extern crate typetag;
+extern crate wasmi;
 
+pub mod ack;
 pub mod command;
 pub mod error;
+pub mod escalation;
 pub mod extra;
+pub mod handler;
 pub mod interface;
 pub mod logfile;
 pub mod logset;
+pub mod metrics;
+pub mod plugin;
+pub mod policy;
+pub mod record;
+pub mod route;
+pub mod silence;
 pub mod source;
+pub mod state;
+pub mod status;
 pub mod task;
+pub mod template;
 pub mod trigger;
 
+pub use ack::*;
 pub use command::*;
 pub use error::*;
+pub use escalation::*;
 pub use extra::*;
+pub use handler::*;
 pub use interface::*;
 pub use logfile::*;
 pub use logset::*;
+pub use metrics::*;
+pub use plugin::*;
+pub use policy::*;
+pub use record::*;
+pub use route::*;
+pub use silence::*;
 pub use source::*;
+pub use status::*;
 pub use task::*;
+pub use template::*;
 pub use trigger::*;
@@ -1,10 +1,21 @@
+use super::ack::Ack;
 use super::error::Error;
+use super::escalation::Escalation;
 use super::extra::ExtraData;
+use super::handler::HandlerTypes;
+use super::metrics::LogMetrics;
+use super::policy::{Transition, TriggerPolicy, TriggerState};
+use super::record::RecordMode;
 use super::serde::{Deserialize, Serialize};
+use super::silence::Silences;
 use super::source::{DataSource, DataSourceTypes};
+use super::status::LogStatus;
 use super::task::Task;
-use super::trigger::{Trigger, TriggerTypes};
+use super::template::{hostname, Template, DEFAULT_TEMPLATE};
+use super::trigger::{Trigger, TriggerContext, TriggerMatch, TriggerType, TriggerTypes};
+use std::collections::HashMap;
 use std::fmt;
+use std::time::Instant;
 
 /// An event handler callback
 /// that is notified whenever a text trigger is true
@@ -15,11 +26,91 @@ pub trait EventHandler {
 
 pub struct Event<'a> {
     pub did_trigger: bool,
+    /// true if handlers should send a notification
+    /// based on the trigger's policy
+    pub notify: bool,
+    /// how the state of the trigger changed since the previous check
+    pub transition: Transition,
+    /// true if a silence or maintenance window matches.
+    /// Notifiers skip silenced events
+    pub silenced: bool,
+    /// the ack of the trigger if someone handles the problem.
+    /// Notifiers skip acked events
+    pub ack: Option<Ack>,
+    /// names of the logset handlers the event escalates to on this check
+    pub escalate: Vec<String>,
     pub trigger: Option<&'a dyn Trigger>,
+    /// the type reported by the trigger for this text
+    pub trigger_type: TriggerType,
+    /// the slice that fired the trigger
+    /// expanded to whole records if records are configured
+    pub slice: &'a str,
+    /// every match of the trigger in the text
+    pub matches: Vec<TriggerMatch>,
+    /// named capture groups of the first match
+    pub captures: HashMap<String, String>,
     pub task: &'a Task,
     pub extra: &'a mut ExtraData,
     pub text: &'a str,
     pub name: &'a str,
+    /// the message template of the trigger or log
+    pub template: Option<&'a str>,
+}
+
+impl<'a> Event<'a> {
+    /// returns the fields of the event by name
+    /// e.g. name, trigger.name, severity or captures.user
+    pub fn fields(&self) -> HashMap<String, String> {
+        let mut fields = HashMap::new();
+        fields.insert("name".into(), self.name.into());
+        if let Some(trigger) = self.trigger {
+            fields.insert("trigger.name".into(), trigger.name().into());
+            fields.insert("trigger.description".into(), trigger.description().into());
+        }
+        fields.insert("severity".into(), self.trigger_type.to_string());
+        fields.insert("transition".into(), format!("{:?}", self.transition));
+        fields.insert("slice".into(), self.slice.into());
+        fields.insert("next_time".into(), self.task.next_time().to_string());
+        fields.insert("host".into(), hostname());
+        for (key, value) in &self.captures {
+            fields.insert(format!("captures.{}", key), value.clone());
+        }
+        fields
+    }
+
+    /// renders the event's template
+    /// or the default template if none is configured.
+    /// Broken templates render the error instead
+    pub fn message(&self, default: &str) -> String {
+        match Template::parse(self.template.unwrap_or(default)) {
+            Ok(template) => template.render(&self.fields()),
+            Err(err) => err.to_string(),
+        }
+    }
+
+    /// a copy of the event that always notifies
+    /// e.g. for an escalation handler.
+    /// Changes of the handler to the extra data are not kept
+    pub fn escalated<'b>(&'b self, extra: &'b mut ExtraData) -> Event<'b> {
+        Event {
+            did_trigger: self.did_trigger,
+            notify: true,
+            transition: self.transition,
+            silenced: self.silenced,
+            ack: self.ack.clone(),
+            escalate: vec![],
+            trigger: self.trigger,
+            trigger_type: self.trigger_type,
+            slice: self.slice,
+            matches: self.matches.clone(),
+            captures: self.captures.clone(),
+            task: self.task,
+            extra,
+            text: self.text,
+            name: self.name,
+            template: self.template,
+        }
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -28,9 +119,49 @@ pub struct Logfile {
     source: DataSourceTypes,
     pub triggers: Vec<TriggerTypes>,
     pub task: Task,
-    /// extra data may be used by EventHandlers to store data
-    #[serde(default)]
+    /// extra data may be used by EventHandlers to store data.
+    /// It is kept in the state file of the logset,
+    /// older configurations that contain it are still read
+    #[serde(default, skip_serializing)]
     pub extra: ExtraData,
+    /// groups lines into multi-line records e.g. stack traces
+    #[serde(default)]
+    pub records: Option<RecordMode>,
+    /// cooldown, debounce and re-notify policies by trigger name
+    #[serde(default)]
+    pub policies: HashMap<String, TriggerPolicy>,
+    /// the status computed from all triggers after the last check.
+    /// It is kept in the state file of the logset
+    #[serde(skip)]
+    pub status: LogStatus,
+    /// named handlers that receive the events of this log
+    #[serde(default)]
+    pub handlers: HashMap<String, HandlerTypes>,
+    /// the message template of every trigger
+    #[serde(default)]
+    pub template: Option<String>,
+    /// message templates by trigger name
+    #[serde(default)]
+    pub templates: HashMap<String, String>,
+    /// free-form tags e.g. for routing
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// acks by trigger name.
+    /// Acks are removed once the trigger stops firing or the ack expires.
+    /// They are kept in the state file of the logset
+    #[serde(skip)]
+    pub acks: HashMap<String, Ack>,
+    /// the silences of the logset.
+    /// Set by the logset before every update
+    #[serde(skip)]
+    pub silences: Silences,
+    /// the escalation chains of the logset.
+    /// Set by the logset before every update
+    #[serde(skip)]
+    pub escalations: Vec<Escalation>,
+    /// load and trigger counters since minutecat started
+    #[serde(skip)]
+    pub metrics: LogMetrics,
 }
 
 impl PartialEq for Logfile {
@@ -54,6 +185,17 @@ impl Logfile {
             triggers: vec![],
             task,
             extra: ExtraData::new(),
+            records: None,
+            policies: HashMap::new(),
+            status: LogStatus::new(),
+            handlers: HashMap::new(),
+            template: None,
+            templates: HashMap::new(),
+            tags: vec![],
+            acks: HashMap::new(),
+            silences: Silences::new(),
+            escalations: vec![],
+            metrics: LogMetrics::new(),
         }
     }
 
@@ -61,6 +203,32 @@ impl Logfile {
         self.triggers.push(trigger);
     }
 
+    /// the names of all triggers including nested ones
+    /// in config order
+    pub fn trigger_names(&self) -> Vec<&str> {
+        fn walk<'a>(triggers: &'a [TriggerTypes], names: &mut Vec<&'a str>) {
+            for trigger in triggers {
+                names.push(trigger.name());
+                walk(trigger.nested(), names);
+            }
+        }
+        let mut names = vec![];
+        walk(&self.triggers, &mut names);
+        names
+    }
+
+    /// fails if two triggers share a name, including nested triggers.
+    /// Trigger state, policies and acks are stored by name
+    pub fn check_names(&self) -> Result<(), Error> {
+        let names = self.trigger_names();
+        for (i, name) in names.iter().enumerate() {
+            if names[..i].contains(name) {
+                return Err(Error::DuplicateTrigger(name.to_string()));
+            }
+        }
+        Ok(())
+    }
+
     pub fn pop(&mut self) -> Option<TriggerTypes> {
         self.triggers.pop()
     }
@@ -96,57 +264,271 @@ impl Logfile {
         if !self.task.is_due() {
             return Ok(false);
         }
-        self.force_update(handlers).await
+        self.load(handlers).await
     }
 
+    /// updates the logfile regardless of the task timer.
+    /// The check uses the current time, the timer is not restarted
     pub async fn force_update(
         &mut self,
         handlers: &mut Vec<&mut dyn EventHandler>,
     ) -> Result<bool, Error> {
+        self.task.force();
+        self.load(handlers).await
+    }
+
+    async fn load(&mut self, handlers: &mut Vec<&mut dyn EventHandler>) -> Result<bool, Error> {
         // if so refresh source
-        let text = self.source.load().await?;
+        let start = Instant::now();
+        let text = match self.source.load().await {
+            Ok(text) => text,
+            Err(err) => {
+                self.metrics.load_failed(&err);
+                return Err(err);
+            }
+        };
+        self.metrics.loaded(start.elapsed(), text.len());
 
         self.check(handlers, &text)?;
 
         Ok(true)
     }
 
+    /// returns the slice of a trigger after the check of ctx
+    /// expanded to the records of ctx
+    fn slice<'a>(
+        trigger: &dyn Trigger,
+        text: &'a str,
+        ctx: &TriggerContext,
+    ) -> Result<&'a str, Error> {
+        let slice = trigger.slice_ctx(text, ctx)?;
+        match ctx.records {
+            Some(records) => Ok(RecordMode::expand(records, text, slice)),
+            _ => Ok(slice),
+        }
+    }
+
+    /// returns the matches of a trigger after the check of ctx
+    /// expanded to the records of ctx
+    fn matches(
+        trigger: &dyn Trigger,
+        text: &str,
+        ctx: &TriggerContext,
+    ) -> Result<Vec<TriggerMatch>, Error> {
+        let matches = trigger.matches_ctx(text, ctx)?;
+        let records = match ctx.records {
+            Some(records) => records,
+            _ => return Ok(matches),
+        };
+
+        let mut expanded = vec![];
+        for m in matches {
+            let range = RecordMode::expand_range(records, m.start..m.end);
+            expanded.push(
+                TriggerMatch::new(text, range.start, range.end, m.captures).with_named(m.named),
+            );
+        }
+        Ok(expanded)
+    }
+
     pub fn check(
         &mut self,
         handlers: &mut Vec<&mut dyn EventHandler>,
         text: &str,
     ) -> Result<(), Error> {
+        self.check_names()?;
+        // split the text once, every trigger sees the same records
+        let records = match &self.records {
+            Some(mode) => Some(mode.split(text)?),
+            _ => None,
+        };
+
         // and check triggers
+        let mut active = vec![];
 
         if self.triggers.is_empty() {
             let event = Event {
                 did_trigger: false,
+                notify: false,
+                transition: Transition::Idle,
+                silenced: false,
+                ack: None,
+                escalate: vec![],
                 trigger: None,
+                trigger_type: TriggerType::NoEvent,
+                slice: &text[0..0],
+                matches: vec![],
+                captures: HashMap::new(),
                 task: &self.task,
                 extra: &mut self.extra,
                 text,
                 name: &self.name,
+                template: self.template.as_deref(),
             };
             for handler in &mut handlers[..] {
                 handler.on_event(&event);
             }
+            for handler in self.handlers.values_mut() {
+                handler.on_event(&event);
+            }
         } else {
             for trigger in &self.triggers[..] {
+                let mut ctx = TriggerContext::new(self.task.last_time(), &mut self.extra)
+                    .with_size(self.source.size())
+                    .with_records(records.as_deref());
+                let fired = trigger.check_ctx(text, &mut ctx)?;
+
+                // apply the policy, triggers without a policy use the default
+                let mut state = TriggerState::load(ctx.extra, trigger.name());
+                let was_active = state.active;
+                let notified = state.notified;
+                let (did_trigger, notify) = self
+                    .policies
+                    .get(trigger.name())
+                    .cloned()
+                    .unwrap_or_default()
+                    .apply(&mut state, fired, self.task.last_time())?;
+                let transition = Transition::new(was_active, state.active);
+
+                let (trigger_type, slice, matches) = if did_trigger {
+                    (
+                        trigger.get_type_for_ctx(text, &ctx)?,
+                        Self::slice(trigger, text, &ctx)?,
+                        Self::matches(trigger, text, &ctx)?,
+                    )
+                } else {
+                    (trigger.get_type(), &text[0..0], vec![])
+                };
+                if did_trigger {
+                    active.push(trigger_type);
+                }
+                self.metrics
+                    .checked(trigger.name(), did_trigger, self.task.last_time());
+
+                let silenced = self.silences.is_silenced(
+                    &self.name,
+                    trigger.name(),
+                    trigger_type,
+                    self.task.last_time(),
+                );
+
+                let now = self.task.last_time();
+                if !state.active
+                    || self
+                        .acks
+                        .get(trigger.name())
+                        .is_some_and(|a| !a.is_active(now))
+                {
+                    self.acks.remove(trigger.name());
+                }
+                let ack = self.acks.get(trigger.name()).cloned();
+
+                if (silenced || ack.is_some()) && notify {
+                    // notify once the silence or ack ends
+                    state.notified = notified;
+                    state.suppressed = true;
+                }
+
+                // acks and silences hold back escalations,
+                // chains with invalid delays are rejected when the logset
+                // is loaded and skipped here
+                if silenced || ack.is_some() {
+                    state.unheld = None;
+                }
+                let escalate = match Escalation::find(
+                    &self.escalations,
+                    &self.name,
+                    trigger.name(),
+                    trigger_type,
+                ) {
+                    Some(escalation) if did_trigger && !silenced && ack.is_none() => {
+                        escalation.due(&mut state, now).unwrap_or_default()
+                    }
+                    _ => vec![],
+                };
+                state.store(&mut self.extra, trigger.name())?;
+
+                let captures = matches.first().map(|m| m.named.clone()).unwrap_or_default();
                 let event = Event {
-                    did_trigger: trigger.check(text)?,
+                    did_trigger,
+                    notify,
+                    transition,
+                    silenced,
+                    ack,
+                    escalate,
                     trigger: Some(trigger),
+                    trigger_type,
+                    slice,
+                    matches,
+                    captures,
                     task: &self.task,
                     extra: &mut self.extra,
                     text,
                     name: &self.name,
+                    template: self
+                        .templates
+                        .get(trigger.name())
+                        .or(self.template.as_ref())
+                        .map(|t| t.as_str()),
                 };
                 for handler in &mut handlers[..] {
                     handler.on_event(&event);
                 }
+                for handler in self.handlers.values_mut() {
+                    handler.on_event(&event);
+                }
             }
         }
+        self.status.update(&active, self.task.last_time());
         Ok(())
     }
+
+    /// renders the message of a trigger for a sample text
+    /// using the given template or the configured one.
+    /// The check is stateless: it starts with fresh extra data,
+    /// the default policy and without acks or silences,
+    /// so the preview does not depend on earlier checks.
+    /// The logfile is not changed and no handlers are called.
+    /// Returns None if the trigger does not exist
+    pub fn render_template(
+        &self,
+        trigger: &str,
+        text: &str,
+        template: Option<&str>,
+    ) -> Result<Option<String>, Error> {
+        let mut log = self.clone();
+        log.handlers.clear();
+        log.extra = ExtraData::new();
+        log.policies.clear();
+        log.acks.clear();
+        log.silences = Silences::new();
+        log.escalations.clear();
+        if let Some(template) = template {
+            Template::parse(template)?;
+            log.templates.insert(trigger.into(), template.into());
+        }
+
+        let mut preview = TemplatePreview {
+            trigger: trigger.into(),
+            message: None,
+        };
+        log.check(&mut vec![&mut preview], text)?;
+        Ok(preview.message)
+    }
+}
+
+/// collects the message of a single trigger
+struct TemplatePreview {
+    trigger: String,
+    message: Option<String>,
+}
+
+impl EventHandler for TemplatePreview {
+    fn on_event(&mut self, event: &Event) {
+        if event.trigger.map(|t| t.name()) == Some(self.trigger.as_str()) {
+            self.message = Some(event.message(DEFAULT_TEMPLATE));
+        }
+    }
 }
 
 // TODO test push,pop and remove
@@ -155,9 +537,9 @@ impl Logfile {
 mod tests {
     use super::*;
     use crate::source::InMemoryDataSource;
-    use crate::task::InMemoryTimeSource;
     use crate::task::TimeSourceTypes;
-    use crate::trigger::{RegexTrigger, TriggerType};
+    use crate::task::{InMemoryTimeSource, TimeMs};
+    use crate::trigger::RegexTrigger;
 
     struct TestHandler(Option<TriggerType>, Option<TriggerType>);
     impl EventHandler for TestHandler {
@@ -211,6 +593,138 @@ mod tests {
         assert_eq!(handler.1, Some(TriggerType::Success));
     }
 
+    #[tokio::test]
+    async fn it_should_compute_status_from_all_triggers() {
+        let mut lf = Logfile::new(
+            "test",
+            DataSourceTypes::InMemory(InMemoryDataSource::new(vec![
+                "all good".into(),
+                "error and success".into(),
+            ])),
+            Task::new(
+                true,
+                10,
+                // every due update reads the time twice
+                TimeSourceTypes::InMemory(InMemoryTimeSource::new(vec![200, 200, 100, 100, 0])),
+            ),
+        );
+        lf.push(TriggerTypes::Regex(RegexTrigger::new(
+            "failure",
+            "on error",
+            TriggerType::Error,
+            "error",
+            false,
+        )));
+        // fires after the error trigger in the same check
+        lf.push(TriggerTypes::Regex(RegexTrigger::new(
+            "success",
+            "on success",
+            TriggerType::Success,
+            "success",
+            false,
+        )));
+
+        assert!(lf.update(&mut vec![]).await.unwrap());
+        assert_eq!(lf.status.severity, TriggerType::Error);
+        assert_eq!(lf.status.count(TriggerType::Error), 1);
+        assert_eq!(lf.status.count(TriggerType::Success), 1);
+        assert_eq!(lf.status.last_change, Some(100));
+
+        assert!(lf.update(&mut vec![]).await.unwrap());
+        assert_eq!(lf.status.severity, TriggerType::NoEvent);
+        assert_eq!(lf.status.last_change, Some(200));
+    }
+
+    #[tokio::test]
+    async fn it_should_reject_duplicate_trigger_names() {
+        use crate::{SequenceMode, SequenceTrigger};
+
+        let mut lf = Logfile::new(
+            "test",
+            DataSourceTypes::InMemory(InMemoryDataSource::new(vec!["a b".into(); 2])),
+            Task::new(
+                true,
+                10,
+                TimeSourceTypes::InMemory(InMemoryTimeSource::new(vec![])),
+            ),
+        );
+        for second in ["b", "c"] {
+            lf.push(TriggerTypes::Sequence(SequenceTrigger::new(
+                "login",
+                "",
+                TriggerType::Error,
+                "a",
+                second,
+                "1m",
+                SequenceMode::FollowedBy,
+            )));
+        }
+        assert!(matches!(
+            lf.force_update(&mut vec![]).await,
+            Err(Error::DuplicateTrigger(name)) if name == "login"
+        ));
+
+        // nested triggers keep their state by name as well
+        lf.pop();
+        let mut composite =
+            crate::CompositeTrigger::new("any", "", TriggerType::Error, crate::CompositeMode::Any);
+        composite.push(lf.pop().unwrap());
+        lf.push(TriggerTypes::Composite(composite));
+        lf.push(TriggerTypes::Regex(RegexTrigger::new(
+            "login",
+            "",
+            TriggerType::Error,
+            "a",
+            false,
+        )));
+        assert_eq!(lf.trigger_names(), vec!["any", "login", "login"]);
+        assert!(matches!(
+            lf.force_update(&mut vec![]).await,
+            Err(Error::DuplicateTrigger(name)) if name == "login"
+        ));
+    }
+
+    #[tokio::test]
+    async fn it_should_force_updates_at_the_current_time() {
+        let mut lf = Logfile::new(
+            "test",
+            DataSourceTypes::InMemory(InMemoryDataSource::new(vec!["error".into(); 2])),
+            Task::new(
+                true,
+                1000,
+                // started at 0, forced at 500 and 700
+                TimeSourceTypes::InMemory(InMemoryTimeSource::new(vec![700, 500, 0])),
+            ),
+        );
+        lf.push(TriggerTypes::Regex(RegexTrigger::new(
+            "error",
+            "",
+            TriggerType::Error,
+            "error",
+            false,
+        )));
+        lf.policies.insert(
+            "error".into(),
+            TriggerPolicy::new().with_renotify_every("100ms"),
+        );
+
+        struct Notified(Vec<(bool, TimeMs)>);
+        impl EventHandler for Notified {
+            fn on_event(&mut self, event: &Event) {
+                self.0.push((event.notify, event.task.last_time()));
+            }
+        }
+        let mut handler = Notified(vec![]);
+        lf.force_update(&mut vec![&mut handler]).await.unwrap();
+        lf.force_update(&mut vec![&mut handler]).await.unwrap();
+
+        // the policy sees the time of the forced checks
+        assert_eq!(handler.0, vec![(true, 500), (true, 700)]);
+        assert_eq!(lf.status.last_change, Some(500));
+        // and the timer keeps running
+        assert_eq!(lf.task.next_time(), 1000);
+    }
+
     #[tokio::test]
     async fn it_should_not_trigger() {
         let mut lf = Logfile::new(
@@ -247,4 +761,403 @@ mod tests {
         assert_eq!(handler.0, None);
         assert_eq!(handler.1, None);
     }
+
+    struct SliceHandler(Vec<String>);
+    impl EventHandler for SliceHandler {
+        fn on_event(&mut self, event: &Event) {
+            self.0.push(event.slice.into());
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_report_whole_records() {
+        let mut lf = Logfile::new(
+            "test",
+            DataSourceTypes::InMemory(InMemoryDataSource::new(vec![
+                "INFO start\nERROR failed\njava.lang.IllegalStateException: boom\n\tat Foo.bar(Foo.java:1)\nINFO done".into(),
+            ])),
+            Task::new(
+                true,
+                10,
+                TimeSourceTypes::InMemory(InMemoryTimeSource::new(vec![])),
+            ),
+        );
+        lf.records = Some(RecordMode::Preset(crate::record::RecordPreset::Java));
+        lf.push(TriggerTypes::Regex(RegexTrigger::new(
+            "exception",
+            "on exception",
+            TriggerType::Error,
+            "Exception",
+            false,
+        )));
+
+        let mut handler = SliceHandler(vec![]);
+        lf.force_update(&mut vec![&mut handler]).await.unwrap();
+        assert_eq!(
+            handler.0,
+            vec!["ERROR failed\njava.lang.IllegalStateException: boom\n\tat Foo.bar(Foo.java:1)"]
+        );
+    }
+
+    struct MatchHandler(Vec<TriggerMatch>);
+    impl EventHandler for MatchHandler {
+        fn on_event(&mut self, event: &Event) {
+            self.0.extend(event.matches.iter().cloned());
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_check_per_line_triggers_on_records() {
+        let text = "INFO start\nERROR failed\njava.lang.IllegalStateException: boom\n\tat Foo.bar(Foo.java:1)\nINFO done";
+        let mut lf = Logfile::new(
+            "test",
+            DataSourceTypes::InMemory(InMemoryDataSource::new(vec![text.into(); 2])),
+            Task::new(
+                true,
+                10,
+                TimeSourceTypes::InMemory(InMemoryTimeSource::new(vec![])),
+            ),
+        );
+        lf.records = Some(RecordMode::Preset(crate::record::RecordPreset::Java));
+        lf.push(TriggerTypes::Regex(
+            RegexTrigger::new(
+                "failed",
+                "failed with an exception",
+                TriggerType::Error,
+                r"^ERROR failed\njava\.lang\.\w+Exception",
+                false,
+            )
+            .with_per_line(true),
+        ));
+
+        let mut handler = MatchHandler(vec![]);
+        lf.force_update(&mut vec![&mut handler]).await.unwrap();
+        assert_eq!(lf.status.severity, TriggerType::Error);
+        assert_eq!(handler.0, vec![TriggerMatch::new(text, 11, 85, vec![])]);
+        assert_eq!(
+            handler.0[0].text,
+            "ERROR failed\njava.lang.IllegalStateException: boom\n\tat Foo.bar(Foo.java:1)"
+        );
+
+        // without records the pattern can not match a single line
+        lf.records = None;
+        let mut handler = MatchHandler(vec![]);
+        lf.force_update(&mut vec![&mut handler]).await.unwrap();
+        assert!(handler.0.is_empty());
+    }
+
+    struct CaptureHandler(HashMap<String, String>);
+    impl EventHandler for CaptureHandler {
+        fn on_event(&mut self, event: &Event) {
+            self.0 = event.captures.clone();
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_pass_named_captures() {
+        let mut lf = Logfile::new(
+            "test",
+            DataSourceTypes::InMemory(InMemoryDataSource::new(vec![
+                "login failed for bob\nlogin failed for alice".into(),
+            ])),
+            Task::new(
+                true,
+                10,
+                TimeSourceTypes::InMemory(InMemoryTimeSource::new(vec![])),
+            ),
+        );
+        lf.push(TriggerTypes::Regex(RegexTrigger::new(
+            "login",
+            "failed logins",
+            TriggerType::Warning,
+            r"login failed for (?P<user>\w+)",
+            false,
+        )));
+
+        let mut handler = CaptureHandler(HashMap::new());
+        lf.force_update(&mut vec![&mut handler]).await.unwrap();
+        assert_eq!(handler.0.get("user"), Some(&"bob".to_string()));
+    }
+
+    struct NotifyHandler(Vec<(bool, bool)>);
+    impl EventHandler for NotifyHandler {
+        fn on_event(&mut self, event: &Event) {
+            self.0.push((event.did_trigger, event.notify));
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_apply_policies() {
+        // every update reads the time twice and the task is started at 0
+        let mut times = vec![0, 1000, 1000, 2000, 2000, 3000, 3000, 70000, 70000];
+        times.reverse();
+        let mut lf = Logfile::new(
+            "test",
+            DataSourceTypes::InMemory(InMemoryDataSource::new(vec!["error".into(); 4])),
+            Task::new(
+                true,
+                0,
+                TimeSourceTypes::InMemory(InMemoryTimeSource::new(times)),
+            ),
+        );
+        lf.push(TriggerTypes::Regex(RegexTrigger::new(
+            "failure",
+            "on error",
+            TriggerType::Error,
+            "error",
+            false,
+        )));
+        lf.policies.insert(
+            "failure".into(),
+            TriggerPolicy::new()
+                .with_debounce(2)
+                .with_renotify_every("1m"),
+        );
+
+        let mut handler = NotifyHandler(vec![]);
+        lf.update(&mut vec![&mut handler]).await.unwrap();
+        lf.update(&mut vec![&mut handler]).await.unwrap();
+
+        // state survives a restart, it is kept apart from the config
+        let state = serde_yaml::to_string(&lf.extra).unwrap();
+        let ser = serde_yaml::to_string(&lf).unwrap();
+        let mut lf: Logfile = serde_yaml::from_str(&ser).unwrap();
+        lf.extra = serde_yaml::from_str(&state).unwrap();
+        lf.update(&mut vec![&mut handler]).await.unwrap();
+        lf.update(&mut vec![&mut handler]).await.unwrap();
+
+        assert_eq!(
+            handler.0,
+            vec![(false, false), (true, true), (true, false), (true, true)]
+        );
+    }
+
+    struct TransitionHandler(Vec<Transition>);
+    impl EventHandler for TransitionHandler {
+        fn on_event(&mut self, event: &Event) {
+            self.0.push(event.transition);
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_report_transitions() {
+        let mut lf = Logfile::new(
+            "test",
+            DataSourceTypes::InMemory(InMemoryDataSource::new(vec![
+                "ok".into(),
+                "ok".into(),
+                "error".into(),
+                "error".into(),
+            ])),
+            Task::new(
+                true,
+                10,
+                TimeSourceTypes::InMemory(InMemoryTimeSource::new(vec![])),
+            ),
+        );
+        lf.push(TriggerTypes::Regex(RegexTrigger::new(
+            "failure",
+            "on error",
+            TriggerType::Error,
+            "error",
+            false,
+        )));
+
+        let mut handler = TransitionHandler(vec![]);
+        for _ in 0..4 {
+            lf.force_update(&mut vec![&mut handler]).await.unwrap();
+        }
+        assert_eq!(
+            handler.0,
+            vec![
+                Transition::Raised,
+                Transition::Ongoing,
+                Transition::Resolved,
+                Transition::Idle
+            ]
+        );
+    }
+
+    #[test]
+    fn it_should_render_templates_per_trigger_or_log() {
+        let mut lf = Logfile::new(
+            "prod-api",
+            DataSourceTypes::InMemory(InMemoryDataSource::new(vec![])),
+            Task::new(
+                true,
+                10,
+                TimeSourceTypes::InMemory(InMemoryTimeSource::new(vec![])),
+            ),
+        );
+        lf.push(TriggerTypes::Regex(RegexTrigger::new(
+            "login",
+            "failed logins",
+            TriggerType::Warning,
+            r"login failed for (?P<user>\w+)",
+            false,
+        )));
+        lf.push(TriggerTypes::Regex(RegexTrigger::new(
+            "panic",
+            "panics",
+            TriggerType::Fatal,
+            "panic",
+            false,
+        )));
+        let text = "login failed for bob\npanic";
+
+        assert_eq!(
+            lf.render_template("panic", text, None).unwrap().unwrap(),
+            "[Fatal] prod-api/panic: panics (Raised)"
+        );
+        assert_eq!(lf.render_template("missing", text, None).unwrap(), None);
+
+        lf.template = Some("{{ name | upper }}: {{ trigger.name }}".into());
+        lf.templates.insert(
+            "login".into(),
+            "{% if captures.user %}{{ captures.user }} failed{% endif %}".into(),
+        );
+        assert_eq!(
+            lf.render_template("login", text, None).unwrap().unwrap(),
+            "bob failed"
+        );
+        assert_eq!(
+            lf.render_template("panic", text, None).unwrap().unwrap(),
+            "PROD-API: panic"
+        );
+        assert_eq!(
+            lf.render_template("panic", text, Some("{{ severity | lower }}"))
+                .unwrap()
+                .unwrap(),
+            "fatal"
+        );
+        assert!(lf.render_template("panic", text, Some("{{ x")).is_err());
+
+        // the preview ignores the state and policy of the log
+        let mut state = TriggerState::load(&mut lf.extra, "panic");
+        state.active = true;
+        state.store(&mut lf.extra, "panic").unwrap();
+        lf.policies
+            .insert("panic".into(), TriggerPolicy::new().with_debounce(3));
+        assert_eq!(
+            lf.render_template("panic", text, Some("{{ transition }}"))
+                .unwrap()
+                .unwrap(),
+            "Raised"
+        );
+
+        // rendering does not change the logfile
+        assert_eq!(lf.status.severity, TriggerType::NoEvent);
+    }
+
+    #[tokio::test]
+    async fn it_should_mark_silenced_events() {
+        use crate::handler::should_notify;
+        use crate::silence::{Silence, SilenceMatcher};
+
+        struct SilenceHandler(Vec<(bool, bool)>);
+        impl EventHandler for SilenceHandler {
+            fn on_event(&mut self, event: &Event) {
+                self.0.push((event.silenced, should_notify(event, false)));
+            }
+        }
+
+        let mut lf = Logfile::new(
+            "prod-api",
+            DataSourceTypes::InMemory(InMemoryDataSource::new(vec![
+                "error".into(),
+                "error".into(),
+            ])),
+            Task::new(
+                true,
+                10,
+                TimeSourceTypes::InMemory(InMemoryTimeSource::new(vec![])),
+            ),
+        );
+        lf.push(TriggerTypes::Regex(RegexTrigger::new(
+            "failure",
+            "on error",
+            TriggerType::Error,
+            "error",
+            false,
+        )));
+
+        let mut handler = SilenceHandler(vec![]);
+        lf.silences.silences.push(Silence::new(
+            SilenceMatcher::new().with_log("prod-*"),
+            u128::MAX,
+            "deploy",
+        ));
+        lf.force_update(&mut vec![&mut handler]).await.unwrap();
+        lf.silences.silences.clear();
+        lf.force_update(&mut vec![&mut handler]).await.unwrap();
+
+        assert_eq!(handler.0, vec![(true, false), (false, true)]);
+    }
+
+    #[tokio::test]
+    async fn it_should_stop_notifying_while_acked() {
+        use crate::handler::should_notify;
+
+        struct AckHandler(Vec<(bool, bool)>);
+        impl EventHandler for AckHandler {
+            fn on_event(&mut self, event: &Event) {
+                self.0
+                    .push((event.ack.is_some(), should_notify(event, false)));
+            }
+        }
+
+        let mut data: Vec<String> = vec!["error", "error", "error", "ok", "error"]
+            .into_iter()
+            .map(|s| s.into())
+            .collect();
+        data.reverse();
+        let mut lf = Logfile::new(
+            "test",
+            DataSourceTypes::InMemory(InMemoryDataSource::new(data)),
+            Task::new(
+                true,
+                10,
+                TimeSourceTypes::InMemory(InMemoryTimeSource::new(vec![])),
+            ),
+        );
+        lf.push(TriggerTypes::Regex(RegexTrigger::new(
+            "failure",
+            "on error",
+            TriggerType::Error,
+            "error",
+            false,
+        )));
+        lf.policies.insert(
+            "failure".into(),
+            TriggerPolicy::new().with_renotify_every("0s"),
+        );
+
+        let mut handler = AckHandler(vec![]);
+        lf.acks.insert(
+            "failure".into(),
+            Ack::new("alice", 0).with_expires(u128::MAX),
+        );
+        lf.force_update(&mut vec![&mut handler]).await.unwrap();
+        lf.force_update(&mut vec![&mut handler]).await.unwrap();
+        // expired acks are removed
+        lf.acks
+            .insert("failure".into(), Ack::new("alice", 0).with_expires(0));
+        lf.force_update(&mut vec![&mut handler]).await.unwrap();
+        // acks are removed on resolve
+        lf.acks.insert("failure".into(), Ack::new("alice", 0));
+        lf.force_update(&mut vec![&mut handler]).await.unwrap();
+        lf.force_update(&mut vec![&mut handler]).await.unwrap();
+
+        assert_eq!(
+            handler.0,
+            vec![
+                (true, false),
+                (true, false),
+                (false, true),
+                (false, false),
+                (false, true)
+            ]
+        );
+        assert!(lf.acks.is_empty());
+    }
 }
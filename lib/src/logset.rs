@@ -1,7 +1,15 @@
 use super::error::Error;
+use super::escalation::Escalation;
+use super::handler::{Handler, HandlerTypes};
 use super::logfile::{EventHandler, Logfile};
+use super::route::{Route, Router};
 use super::serde::{Deserialize, Serialize};
 use super::serde_yaml;
+use super::silence::Silences;
+use super::state::{LogState, State};
+use super::status::LogStatus;
+use std::collections::HashMap;
+use std::fmt;
 use std::fs::File;
 use std::io::Read;
 use std::io::Write;
@@ -13,14 +21,65 @@ use std::str;
 /// and should be used in threads if blocking is not desired.
 /// All structs in lib are thread safe and maye be placed inside Arc<Mutex>
 /// TODO There may be an async option in the future
-#[derive(Default, PartialEq, Clone, Debug, Serialize, Deserialize)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub struct LogSet {
     pub logs: Vec<Logfile>,
+    /// named handlers that receive the events of every log
+    #[serde(default)]
+    pub handlers: HashMap<String, HandlerTypes>,
+    /// routes events to the logset's handlers.
+    /// Without routes every handler receives every event
+    #[serde(default)]
+    pub routes: Vec<Route>,
+    /// silences and maintenance windows of every log.
+    /// They are kept in the state file
+    #[serde(skip)]
+    pub silences: Silences,
+    /// escalation chains that notify further handlers
+    /// while a trigger stays active and unacked
+    #[serde(default)]
+    pub escalations: Vec<Escalation>,
+    /// address of the Prometheus endpoint e.g. 127.0.0.1:9898.
+    /// Metrics are not served if it is not set
+    #[serde(default)]
+    pub metrics: Option<String>,
+    /// the file the runtime state is kept in.
+    /// The state is only kept in memory if it is not set
+    #[serde(skip)]
+    pub state_path: Option<String>,
+    /// the state as it was last loaded or saved
+    #[serde(skip)]
+    state: State,
+}
+
+impl PartialEq for LogSet {
+    fn eq(&self, other: &LogSet) -> bool {
+        self.logs == other.logs
+            && self.handlers.len() == other.handlers.len()
+            && self.routes == other.routes
+            && self.silences == other.silences
+            && self.escalations == other.escalations
+    }
+}
+
+impl fmt::Debug for LogSet {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.logs)
+    }
 }
 
 impl LogSet {
     pub fn new() -> Self {
-        Self { logs: vec![] }
+        Self {
+            logs: vec![],
+            handlers: HashMap::new(),
+            routes: vec![],
+            silences: Silences::new(),
+            escalations: vec![],
+            metrics: None,
+            state_path: None,
+            state: State::new(),
+        }
     }
 
     pub fn from_path(path: &str) -> Result<Self, Error> {
@@ -38,7 +97,17 @@ impl LogSet {
     }
 
     pub fn deserialize(s: &str) -> Result<Self, Error> {
-        Ok(serde_yaml::from_str(s)?)
+        let logset: Self = serde_yaml::from_str(s)?;
+        logset.validate()?;
+        Ok(logset)
+    }
+
+    /// checks the parts of the config serde does not check
+    pub fn validate(&self) -> Result<(), Error> {
+        for escalation in &self.escalations {
+            escalation.validate()?;
+        }
+        Ok(())
     }
 
     pub fn push(&mut self, logfile: Logfile) {
@@ -65,24 +134,167 @@ impl LogSet {
         self.len() == 0
     }
 
+    /// updates every log that is due.
+    /// A failing log does not stop the others, the errors of
+    /// all logs are returned after the handlers are flushed
+    /// and the state is saved
     pub async fn update(
         &mut self,
         handlers: &mut Vec<&mut dyn EventHandler>,
     ) -> Result<bool, Error> {
-        for log in self.slice_mut() {
-            log.update(handlers).await?;
-        }
-        Ok(true)
+        self.update_all(handlers, false).await
     }
 
+    /// updates every log regardless of its task timer
     pub async fn force_update(
         &mut self,
         handlers: &mut Vec<&mut dyn EventHandler>,
     ) -> Result<bool, Error> {
-        for log in self.slice_mut() {
-            log.force_update(handlers).await?;
+        self.update_all(handlers, true).await
+    }
+
+    async fn update_all(
+        &mut self,
+        handlers: &mut Vec<&mut dyn EventHandler>,
+        force: bool,
+    ) -> Result<bool, Error> {
+        let mut errors = vec![];
+        if let Err(err) = self.load_state() {
+            errors.push(("state".to_string(), err));
+        }
+        for index in 0..self.len() {
+            if let Err(err) = self.update_log(index, handlers, force).await {
+                errors.push((self.logs[index].name.clone(), err));
+            }
+        }
+        self.flush();
+        if let Err(err) = self.save_state() {
+            errors.push(("state".to_string(), err));
+        }
+
+        if errors.is_empty() {
+            Ok(true)
+        } else {
+            Err(Error::UpdateErrors(errors))
+        }
+    }
+
+    /// the runtime state of every log
+    pub fn state(&self) -> State {
+        let mut state = State::new();
+        state.silences = self.silences.clone();
+        for log in &self.logs {
+            state.logs.insert(
+                log.name.clone(),
+                LogState {
+                    extra: log.extra.clone(),
+                    status: log.status.clone(),
+                    acks: log.acks.clone(),
+                },
+            );
+        }
+        state
+    }
+
+    /// applies a state to the logset.
+    /// Logs without state keep their current state
+    pub fn apply_state(&mut self, state: &State) {
+        self.silences = state.silences.clone();
+        for log in &mut self.logs {
+            if let Some(s) = state.logs.get(&log.name) {
+                log.extra = s.extra.clone();
+                log.status = s.status.clone();
+                log.acks = s.acks.clone();
+            }
+        }
+    }
+
+    /// reloads the state file if it is set.
+    /// Call this before every update cycle
+    pub fn load_state(&mut self) -> Result<(), Error> {
+        if let Some(path) = &self.state_path {
+            let state = State::from_path(path)?;
+            self.apply_state(&state);
+            self.state = state;
+        }
+        Ok(())
+    }
+
+    /// merges the changes since the state was loaded into the state file.
+    /// Call this after every update cycle
+    pub fn save_state(&mut self) -> Result<(), Error> {
+        if let Some(path) = &self.state_path {
+            let state = self.state().merge(&self.state, State::from_path(path)?);
+            state.to_file(path)?;
+            self.state = state;
+        }
+        Ok(())
+    }
+
+    /// updates a single log and passes its events to
+    /// the given handlers and the routed handlers of the logset
+    /// returns false if the log was not updated
+    pub async fn update_log(
+        &mut self,
+        index: usize,
+        handlers: &mut Vec<&mut dyn EventHandler>,
+        force: bool,
+    ) -> Result<bool, Error> {
+        let log = match self.logs.get_mut(index) {
+            Some(log) => log,
+            _ => return Ok(false),
+        };
+
+        let mut all: Vec<&mut dyn EventHandler> = vec![];
+        for handler in handlers.iter_mut() {
+            all.push(&mut **handler);
+        }
+        log.silences = self.silences.clone();
+        log.escalations = self.escalations.clone();
+        let tags = log.tags.clone();
+        let mut router = Router::new(&self.routes, &mut self.handlers, &tags);
+        all.push(&mut router);
+
+        if force {
+            log.force_update(&mut all).await
+        } else {
+            log.update(&mut all).await
+        }
+    }
+
+    /// flushes the handlers of the logset and of every log.
+    /// Call this after every update cycle
+    pub fn flush(&mut self) {
+        for handler in self.handlers.values_mut() {
+            handler.flush();
+        }
+        for log in &mut self.logs {
+            for handler in log.handlers.values_mut() {
+                handler.flush();
+            }
+        }
+    }
+
+    /// removes and returns the errors the handlers reported
+    /// since the last call, prefixed with the name of the handler
+    pub fn take_errors(&mut self) -> Vec<String> {
+        let mut errors = vec![];
+        let logs = self.logs.iter_mut().flat_map(|log| log.handlers.iter_mut());
+        for (name, handler) in self.handlers.iter_mut().chain(logs) {
+            for err in handler.take_errors() {
+                errors.push(format!("{}: {}", name, err));
+            }
+        }
+        errors
+    }
+
+    /// the combined status of all logs
+    pub fn status(&self) -> LogStatus {
+        let mut status = LogStatus::new();
+        for log in &self.logs {
+            status.merge(&log.status);
         }
-        Ok(true)
+        status
     }
 
     pub fn slice_mut(&mut self) -> &mut [Logfile] {
@@ -0,0 +1,380 @@
+use super::error::Error;
+use super::logset::LogSet;
+use super::task::TimeMs;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// connections the metrics server handles at the same time
+const MAX_CONNECTIONS: usize = 16;
+
+/// Counters of a single trigger since minutecat started
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct TriggerMetrics {
+    /// number of checks the trigger fired on
+    pub fires: u64,
+    /// time of the last check the trigger fired on
+    pub last_fire: Option<TimeMs>,
+    /// the trigger fired on the last check
+    pub active: bool,
+}
+
+/// Counters of a single log since minutecat started.
+/// They are not persisted
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct LogMetrics {
+    /// number of successful loads
+    pub loads: u64,
+    /// failed loads by error kind
+    pub load_errors: BTreeMap<String, u64>,
+    /// duration of the last successful load
+    pub load_duration: Duration,
+    /// sum of all bytes loaded from the source
+    pub bytes_read: u64,
+    /// trigger counters by trigger name
+    pub triggers: BTreeMap<String, TriggerMetrics>,
+}
+
+impl LogMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn loaded(&mut self, duration: Duration, bytes: usize) {
+        self.loads += 1;
+        self.load_duration = duration;
+        self.bytes_read += bytes as u64;
+    }
+
+    pub fn load_failed(&mut self, err: &Error) {
+        *self.load_errors.entry(err.kind().into()).or_insert(0) += 1;
+    }
+
+    pub fn checked(&mut self, trigger: &str, did_trigger: bool, now: TimeMs) {
+        let metrics = self.triggers.entry(trigger.into()).or_default();
+        metrics.active = did_trigger;
+        if did_trigger {
+            metrics.fires += 1;
+            metrics.last_fire = Some(now);
+        }
+    }
+}
+
+/// A metric family in the Prometheus text format
+struct Family {
+    name: &'static str,
+    help: &'static str,
+    kind: &'static str,
+    samples: Vec<(String, String)>,
+}
+
+impl Family {
+    fn new(name: &'static str, kind: &'static str, help: &'static str) -> Self {
+        Self {
+            name,
+            help,
+            kind,
+            samples: vec![],
+        }
+    }
+
+    fn push(&mut self, labels: &[(&str, &str)], value: impl ToString) {
+        let labels: Vec<String> = labels
+            .iter()
+            .map(|(k, v)| format!("{}=\"{}\"", k, escape(v)))
+            .collect();
+        self.samples
+            .push((format!("{{{}}}", labels.join(",")), value.to_string()));
+    }
+
+    fn write(&self, out: &mut String) {
+        if self.samples.is_empty() {
+            return;
+        }
+        let _ = writeln!(out, "# HELP {} {}", self.name, self.help);
+        let _ = writeln!(out, "# TYPE {} {}", self.name, self.kind);
+        for (labels, value) in &self.samples {
+            let _ = writeln!(out, "{}{} {}", self.name, labels, value);
+        }
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn seconds(time: TimeMs) -> String {
+    format!("{:.3}", time as f64 / 1000.0)
+}
+
+/// renders the metrics of every log in the Prometheus text format
+pub fn render(logset: &LogSet) -> String {
+    let mut status = Family::new(
+        "minutecat_log_status",
+        "gauge",
+        "Highest severity of the active triggers (0 = no event, 8 = fatal).",
+    );
+    let mut loads = Family::new(
+        "minutecat_log_loads_total",
+        "counter",
+        "Successful loads of the log source.",
+    );
+    let mut load_errors = Family::new(
+        "minutecat_log_load_errors_total",
+        "counter",
+        "Failed loads of the log source by error kind.",
+    );
+    let mut load_duration = Family::new(
+        "minutecat_log_load_duration_seconds",
+        "gauge",
+        "Duration of the last successful load.",
+    );
+    let mut bytes_read = Family::new(
+        "minutecat_log_bytes_read_total",
+        "counter",
+        "Bytes loaded from the log source.",
+    );
+    let mut next_check = Family::new(
+        "minutecat_log_next_check_timestamp_seconds",
+        "gauge",
+        "Time of the next scheduled check.",
+    );
+    let mut active = Family::new(
+        "minutecat_trigger_active",
+        "gauge",
+        "1 if the trigger fired on the last check.",
+    );
+    let mut fires = Family::new(
+        "minutecat_trigger_fires_total",
+        "counter",
+        "Checks the trigger fired on.",
+    );
+    let mut last_fire = Family::new(
+        "minutecat_trigger_last_fire_timestamp_seconds",
+        "gauge",
+        "Time of the last check the trigger fired on.",
+    );
+
+    for log in &logset.logs {
+        let metrics = &log.metrics;
+        let labels = [("log", log.name.as_str())];
+        status.push(&labels, log.status.severity as u8);
+        loads.push(&labels, metrics.loads);
+        for (kind, count) in &metrics.load_errors {
+            load_errors.push(&[("log", &log.name), ("kind", kind)], count);
+        }
+        load_duration.push(&labels, metrics.load_duration.as_secs_f64());
+        bytes_read.push(&labels, metrics.bytes_read);
+        next_check.push(&labels, seconds(log.task.next_time()));
+
+        for (name, trigger) in &metrics.triggers {
+            let labels = [("log", log.name.as_str()), ("trigger", name.as_str())];
+            active.push(&labels, trigger.active as u8);
+            fires.push(&labels, trigger.fires);
+            if let Some(time) = trigger.last_fire {
+                last_fire.push(&labels, seconds(time));
+            }
+        }
+    }
+
+    let mut out = String::new();
+    for family in [
+        status,
+        loads,
+        load_errors,
+        load_duration,
+        bytes_read,
+        next_check,
+        active,
+        fires,
+        last_fire,
+    ] {
+        family.write(&mut out);
+    }
+    out
+}
+
+/// A minimal HTTP server that serves the latest metrics at /metrics.
+/// The metrics are rendered by the owner of the logset
+/// and handed to the server after every update cycle
+pub struct MetricsServer {
+    addr: SocketAddr,
+    body: Arc<Mutex<String>>,
+}
+
+impl MetricsServer {
+    /// binds the address e.g. 127.0.0.1:9898
+    /// and serves every connection in its own thread.
+    /// Connections beyond MAX_CONNECTIONS are closed right away
+    pub fn start(listen: &str) -> Result<Self, Error> {
+        let listener = TcpListener::bind(listen)?;
+        let addr = listener.local_addr()?;
+        let body = Arc::new(Mutex::new(String::new()));
+
+        let shared = body.clone();
+        let open = Arc::new(AtomicUsize::new(0));
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                if open.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+                    open.fetch_sub(1, Ordering::SeqCst);
+                    continue;
+                }
+                // a slow or broken client should not block the others
+                let body = shared.clone();
+                let open = open.clone();
+                thread::spawn(move || {
+                    let _ = Self::serve(stream, &body);
+                    open.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+        });
+
+        Ok(Self { addr, body })
+    }
+
+    /// the bound address
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// replaces the served metrics
+    pub fn update(&self, metrics: String) {
+        if let Ok(mut body) = self.body.lock() {
+            *body = metrics;
+        }
+    }
+
+    fn serve(stream: TcpStream, body: &Mutex<String>) -> Result<(), Error> {
+        stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+        let mut reader = BufReader::new(&stream);
+        let mut request = String::new();
+        reader.read_line(&mut request)?;
+        // skip the headers
+        let mut line = String::new();
+        while reader.read_line(&mut line)? > 2 {
+            line.clear();
+        }
+
+        let mut parts = request.split_whitespace();
+        let (status, content) = match (parts.next(), parts.next()) {
+            (Some("GET"), Some("/metrics")) => {
+                ("200 OK", body.lock().map(|b| b.clone()).unwrap_or_default())
+            }
+            _ => ("404 Not Found", "Not Found\n".into()),
+        };
+
+        let mut stream = &stream;
+        write!(
+            stream,
+            "HTTP/1.1 {}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+            status,
+            content.len(),
+            content
+        )?;
+        stream.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logfile::Logfile;
+    use crate::source::{DataSourceTypes, InMemoryDataSource};
+    use crate::task::{InMemoryTimeSource, Task, TimeSourceTypes};
+    use crate::{RegexTrigger, TriggerType, TriggerTypes};
+
+    async fn logset() -> LogSet {
+        let mut lf = Logfile::new(
+            "api \"prod\"",
+            DataSourceTypes::InMemory(InMemoryDataSource::new(vec![
+                "panic".into(),
+                "ok".into(),
+                "panic".into(),
+            ])),
+            // checks at 2000, 4000, 6000 and 8000
+            Task::new(
+                true,
+                1000,
+                TimeSourceTypes::InMemory(InMemoryTimeSource::new(vec![
+                    8000, 8000, 6000, 6000, 4000, 4000, 2000, 2000, 0,
+                ])),
+            ),
+        );
+        lf.push(TriggerTypes::Regex(RegexTrigger::new(
+            "panic",
+            "panics",
+            TriggerType::Fatal,
+            "panic",
+            false,
+        )));
+
+        let mut logset = LogSet::new();
+        logset.push(lf);
+        for _ in 0..4 {
+            let _ = logset.logs[0].update(&mut vec![]).await;
+        }
+        logset
+    }
+
+    #[tokio::test]
+    async fn it_should_render_metrics() {
+        let logset = logset().await;
+        let metrics = render(&logset);
+        let lines: Vec<&str> = metrics.lines().collect();
+
+        assert!(lines.contains(&"# TYPE minutecat_trigger_fires_total counter"));
+        assert!(lines.contains(&"minutecat_log_status{log=\"api \\\"prod\\\"\"} 8"));
+        assert!(lines.contains(&"minutecat_log_loads_total{log=\"api \\\"prod\\\"\"} 3"));
+        assert!(lines.contains(
+            &"minutecat_log_load_errors_total{log=\"api \\\"prod\\\"\",kind=\"in_memory_data\"} 1"
+        ));
+        assert!(lines.contains(&"minutecat_log_bytes_read_total{log=\"api \\\"prod\\\"\"} 12"));
+        assert!(lines.contains(
+            &"minutecat_log_next_check_timestamp_seconds{log=\"api \\\"prod\\\"\"} 9.000"
+        ));
+        assert!(lines.contains(
+            &"minutecat_trigger_fires_total{log=\"api \\\"prod\\\"\",trigger=\"panic\"} 2"
+        ));
+        assert!(lines.contains(
+            &"minutecat_trigger_last_fire_timestamp_seconds{log=\"api \\\"prod\\\"\",trigger=\"panic\"} 6.000"
+        ));
+        assert!(lines
+            .contains(&"minutecat_trigger_active{log=\"api \\\"prod\\\"\",trigger=\"panic\"} 1"));
+    }
+
+    #[test]
+    fn it_should_serve_metrics() {
+        let server = MetricsServer::start("127.0.0.1:0").unwrap();
+        server.update("minutecat_log_status{log=\"a\"} 0\n".into());
+
+        let url = format!("http://{}", server.addr());
+        let res = reqwest::blocking::get(format!("{}/metrics", url)).unwrap();
+        assert_eq!(res.status(), 200);
+        assert!(res.headers()["content-type"]
+            .to_str()
+            .unwrap()
+            .starts_with("text/plain"));
+        assert_eq!(res.text().unwrap(), "minutecat_log_status{log=\"a\"} 0\n");
+
+        let res = reqwest::blocking::get(format!("{}/other", url)).unwrap();
+        assert_eq!(res.status(), 404);
+
+        // an idle client does not hold up the others
+        let _idle = TcpStream::connect(server.addr()).unwrap();
+        let client = reqwest::blocking::Client::builder()
+            .timeout(Duration::from_secs(1))
+            .build()
+            .unwrap();
+        let res = client.get(format!("{}/metrics", url)).send().unwrap();
+        assert_eq!(res.status(), 200);
+    }
+}
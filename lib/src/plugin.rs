@@ -0,0 +1,276 @@
+use super::error::Error;
+use super::wasmi::{
+    Config, Engine, Instance, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder,
+};
+use std::fs;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// The default amount of fuel a single plugin call may consume
+pub fn default_fuel() -> u64 {
+    10_000_000
+}
+
+/// The default size in bytes the memory of a plugin may grow to
+pub fn default_max_memory() -> usize {
+    64 * 1024 * 1024
+}
+
+/// A compiled .wasm module.
+/// Compiling is expensive, every call instantiates a new WasmPlugin from it
+#[derive(Clone)]
+pub struct WasmModule {
+    engine: Engine,
+    module: Arc<Module>,
+}
+
+impl WasmModule {
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let mut config = Config::default();
+        config.consume_fuel(true);
+        let engine = Engine::new(&config);
+        let module = Module::new(&engine, bytes).map_err(WasmPlugin::error)?;
+        Ok(Self {
+            engine,
+            module: Arc::new(module),
+        })
+    }
+
+    /// instantiates the module with the fuel for a call
+    /// and the size in bytes its memory may grow to
+    pub fn instantiate(&self, fuel: u64, max_memory: usize) -> Result<WasmPlugin, Error> {
+        let limits = StoreLimitsBuilder::new().memory_size(max_memory).build();
+        let mut store = Store::new(&self.engine, limits);
+        store.limiter(|limits| limits);
+        store.add_fuel(fuel).map_err(WasmPlugin::error)?;
+
+        // an empty linker, modules may not import anything
+        let linker = <Linker<StoreLimits>>::new(&self.engine);
+        let instance = linker
+            .instantiate(&mut store, &self.module)
+            .map_err(WasmPlugin::error)?
+            .start(&mut store)
+            .map_err(WasmPlugin::error)?;
+
+        let memory = match instance.get_memory(&store, "memory") {
+            Some(memory) => memory,
+            _ => return Err(Error::PluginError("plugin does not export memory".into())),
+        };
+
+        Ok(WasmPlugin {
+            store,
+            instance,
+            memory,
+        })
+    }
+}
+
+/// The compiled module of a path.
+/// The file is compiled again once it is modified.
+/// Clones share the cache
+#[derive(Clone, Default)]
+pub struct WasmCache {
+    module: Arc<Mutex<Option<(SystemTime, WasmModule)>>>,
+}
+
+impl WasmCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn module(&self, path: &str) -> Result<WasmModule, Error> {
+        let modified = fs::metadata(path)?.modified()?;
+        let mut cached = self
+            .module
+            .lock()
+            .map_err(|_| Error::PluginError("plugin cache is poisoned".into()))?;
+        if let Some((time, module)) = &*cached {
+            if *time == modified {
+                return Ok(module.clone());
+            }
+        }
+
+        let module = WasmModule::from_bytes(&fs::read(path)?)?;
+        *cached = Some((modified, module.clone()));
+        Ok(module)
+    }
+}
+
+/// A WasmPlugin is an instantiated .wasm module.
+///
+/// Plugins use a small ABI based on linear memory:
+///   - the module exports its memory as memory
+///   - alloc(len: i32) -> i32 returns a pointer to len free bytes
+///   - strings are passed to the plugin as (ptr: i32, len: i32)
+///   - strings and ranges are returned as a packed i64
+///     with the pointer or offset in the upper and the length in the lower 32 bits.
+///     A negative value means nothing is returned
+///
+/// Plugins run in a sandbox. No host functions are provided,
+/// so a module that imports anything (e.g. WASI file system or network access)
+/// fails to load. Every call is limited by the fuel of the plugin
+/// and its memory can not grow beyond the limit of the plugin.
+pub struct WasmPlugin {
+    store: Store<StoreLimits>,
+    instance: Instance,
+    memory: Memory,
+}
+
+impl WasmPlugin {
+    pub fn from_bytes(bytes: &[u8], fuel: u64) -> Result<Self, Error> {
+        WasmModule::from_bytes(bytes)?.instantiate(fuel, default_max_memory())
+    }
+
+    fn error<E: std::fmt::Display>(error: E) -> Error {
+        Error::PluginError(error.to_string())
+    }
+
+    /// true if the plugin exports a function of this name
+    pub fn exports(&self, name: &str) -> bool {
+        self.instance.get_func(&self.store, name).is_some()
+    }
+
+    /// copies a string into the plugin's memory
+    /// returns (ptr, len)
+    pub fn write_str(&mut self, s: &str) -> Result<(i32, i32), Error> {
+        let len = s.len() as i32;
+        let alloc = self
+            .instance
+            .get_typed_func::<i32, i32>(&self.store, "alloc")
+            .map_err(Self::error)?;
+        let ptr = alloc.call(&mut self.store, len).map_err(Self::error)?;
+        self.memory
+            .write(&mut self.store, ptr as usize, s.as_bytes())
+            .map_err(Self::error)?;
+        Ok((ptr, len))
+    }
+
+    /// reads a string from the plugin's memory
+    pub fn read_str(&self, ptr: usize, len: usize) -> Result<String, Error> {
+        let mut buffer = vec![0; len];
+        self.memory
+            .read(&self.store, ptr, &mut buffer)
+            .map_err(Self::error)?;
+        String::from_utf8(buffer).map_err(Self::error)
+    }
+
+    /// unpacks an i64 into (upper, lower)
+    /// returns None for negative values
+    pub fn unpack(value: i64) -> Option<(usize, usize)> {
+        if value < 0 {
+            None
+        } else {
+            Some(((value >> 32) as usize, (value & 0xffff_ffff) as usize))
+        }
+    }
+
+    /// calls a function that takes a string
+    pub fn call_str<R: wasmi::WasmResults>(&mut self, name: &str, s: &str) -> Result<R, Error> {
+        let (ptr, len) = self.write_str(s)?;
+        let func = self
+            .instance
+            .get_typed_func::<(i32, i32), R>(&self.store, name)
+            .map_err(Self::error)?;
+        func.call(&mut self.store, (ptr, len)).map_err(Self::error)
+    }
+
+    /// calls a function without arguments
+    pub fn call<R: wasmi::WasmResults>(&mut self, name: &str) -> Result<R, Error> {
+        let func = self
+            .instance
+            .get_typed_func::<(), R>(&self.store, name)
+            .map_err(Self::error)?;
+        func.call(&mut self.store, ()).map_err(Self::error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_deny_imports() {
+        let wasm = wat::parse_str(
+            r#"(module
+                (import "wasi_snapshot_preview1" "path_open"
+                    (func $open (param i32 i32 i32 i32 i32 i64 i64 i32 i32) (result i32)))
+                (memory (export "memory") 1))"#,
+        )
+        .unwrap();
+        assert!(matches!(
+            WasmPlugin::from_bytes(&wasm, default_fuel()),
+            Err(Error::PluginError(_))
+        ));
+    }
+
+    #[test]
+    fn it_should_require_memory() {
+        let wasm = wat::parse_str("(module)").unwrap();
+        assert!(matches!(
+            WasmPlugin::from_bytes(&wasm, default_fuel()),
+            Err(Error::PluginError(_))
+        ));
+    }
+
+    #[test]
+    fn it_should_limit_memory() {
+        // grows the memory by 100 pages (6.4 MB) on instantiation
+        let wasm = wat::parse_str(
+            r#"(module
+                (memory (export "memory") 1)
+                (func $grow (drop (memory.grow (i32.const 100))))
+                (start $grow))"#,
+        )
+        .unwrap();
+        let module = WasmModule::from_bytes(&wasm).unwrap();
+        let plugin = module.instantiate(default_fuel(), 1024 * 1024).unwrap();
+        assert_eq!(plugin.memory.current_pages(&plugin.store), 1.into());
+        let plugin = module
+            .instantiate(default_fuel(), default_max_memory())
+            .unwrap();
+        assert_eq!(plugin.memory.current_pages(&plugin.store), 101.into());
+
+        // a memory that starts larger than the limit
+        let wasm = wat::parse_str(r#"(module (memory (export "memory") 100))"#).unwrap();
+        let module = WasmModule::from_bytes(&wasm).unwrap();
+        assert!(module.instantiate(default_fuel(), 1024 * 1024).is_err());
+    }
+
+    #[test]
+    fn it_should_cache_modules_until_they_change() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("plugin.wasm");
+        let memory = |pages: u32| {
+            wat::parse_str(format!(r#"(module (memory (export "memory") {}))"#, pages)).unwrap()
+        };
+        let pages = |cache: &WasmCache| {
+            let plugin = cache
+                .module(path.to_str().unwrap())
+                .unwrap()
+                .instantiate(default_fuel(), default_max_memory())
+                .unwrap();
+            plugin.memory.current_pages(&plugin.store)
+        };
+
+        fs::write(&path, memory(1)).unwrap();
+        let modified = fs::metadata(&path).unwrap().modified().unwrap();
+        let cache = WasmCache::new();
+        assert_eq!(pages(&cache), 1.into());
+
+        // the file is not read again while it is not modified
+        fs::write(&path, memory(2)).unwrap();
+        let file = fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(modified).unwrap();
+        assert_eq!(pages(&cache), 1.into());
+
+        file.set_modified(modified + std::time::Duration::from_secs(1))
+            .unwrap();
+        assert_eq!(pages(&cache), 2.into());
+    }
+
+    #[test]
+    fn it_should_unpack() {
+        assert_eq!(WasmPlugin::unpack((4 << 32) | 5), Some((4, 5)));
+        assert_eq!(WasmPlugin::unpack(-1), None);
+    }
+}
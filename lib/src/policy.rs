@@ -0,0 +1,285 @@
+use super::error::Error;
+use super::extra::ExtraData;
+use super::serde::{Deserialize, Serialize};
+use super::task::{Task, TimeMs};
+
+/// TriggerPolicy decides when a firing trigger
+/// is reported and when handlers should notify.
+/// Durations use the same format as task timers e.g. 1h30m
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct TriggerPolicy {
+    /// suppress notifications for this long after a notification
+    #[serde(default)]
+    pub cooldown: Option<String>,
+    /// the trigger has to fire on this many consecutive checks
+    /// before it is reported at all
+    #[serde(default)]
+    pub debounce: usize,
+    /// notify again after this long while the trigger keeps firing.
+    /// Without it an ongoing trigger notifies only once
+    #[serde(default)]
+    pub renotify_every: Option<String>,
+}
+
+/// Transition describes how the state of a trigger
+/// changed since the previous check
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize, Copy, Clone)]
+pub enum Transition {
+    /// the trigger started firing
+    Raised,
+    /// the trigger fired on the previous check and still fires
+    Ongoing,
+    /// the trigger fired on the previous check and stopped firing
+    Resolved,
+    /// the trigger did not fire on either check
+    Idle,
+}
+
+impl Transition {
+    pub fn new(was_active: bool, active: bool) -> Self {
+        match (was_active, active) {
+            (false, true) => Self::Raised,
+            (true, true) => Self::Ongoing,
+            (true, false) => Self::Resolved,
+            (false, false) => Self::Idle,
+        }
+    }
+}
+
+/// The state of a single trigger between checks.
+/// It is stored in the logfile's extra data
+/// and therefore persists across restarts
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct TriggerState {
+    /// number of consecutive checks the trigger fired
+    pub consecutive: usize,
+    /// the trigger fired and passed the debounce
+    pub active: bool,
+    /// time of the last notification
+    pub notified: Option<TimeMs>,
+    /// a notification was held back e.g. by a silence
+    /// and is sent with the next check
+    #[serde(default)]
+    pub suppressed: bool,
+    /// time the trigger became active
+    #[serde(default)]
+    pub raised: Option<TimeMs>,
+    /// time the trigger was last active without being held back
+    /// by an ack or silence, escalation delays count from it
+    #[serde(default)]
+    pub unheld: Option<TimeMs>,
+    /// number of escalation steps that were sent
+    #[serde(default)]
+    pub escalated: usize,
+}
+
+impl TriggerState {
+    fn key(trigger: &str) -> String {
+        format!("trigger.{}", trigger)
+    }
+
+    /// loads the state of a trigger, a missing state is the default state
+    pub fn load(extra: &mut ExtraData, trigger: &str) -> Self {
+        extra
+            .get::<Self>(&Self::key(trigger), ExtraData::deserialize)
+            .unwrap_or_default()
+    }
+
+    pub fn store(&self, extra: &mut ExtraData, trigger: &str) -> Result<(), Error> {
+        extra.put(&Self::key(trigger), self, ExtraData::serialize)
+    }
+}
+
+impl TriggerPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_cooldown(mut self, cooldown: &str) -> Self {
+        self.cooldown = Some(cooldown.into());
+        self
+    }
+
+    pub fn with_debounce(mut self, debounce: usize) -> Self {
+        self.debounce = debounce;
+        self
+    }
+
+    pub fn with_renotify_every(mut self, renotify_every: &str) -> Self {
+        self.renotify_every = Some(renotify_every.into());
+        self
+    }
+
+    fn scan(time_str: &Option<String>) -> Result<Option<TimeMs>, Error> {
+        match time_str {
+            Some(time_str) => Ok(Some(Task::scan(time_str)?)),
+            _ => Ok(None),
+        }
+    }
+
+    /// applies the policy to the result of a check and updates the state.
+    /// returns (did_trigger, notify)
+    pub fn apply(
+        &self,
+        state: &mut TriggerState,
+        fired: bool,
+        now: TimeMs,
+    ) -> Result<(bool, bool), Error> {
+        let cooldown = Self::scan(&self.cooldown)?.unwrap_or(0);
+        let renotify_every = Self::scan(&self.renotify_every)?;
+
+        if !fired {
+            state.consecutive = 0;
+            state.active = false;
+            state.suppressed = false;
+            state.raised = None;
+            state.unheld = None;
+            state.escalated = 0;
+            return Ok((false, false));
+        }
+
+        state.consecutive = state.consecutive.saturating_add(1);
+        if state.consecutive < self.debounce {
+            return Ok((false, false));
+        }
+
+        let was_active = state.active;
+        state.active = true;
+        if !was_active {
+            state.raised = Some(now);
+        }
+
+        let notify = state.suppressed
+            || match state.notified.map(|t| now.saturating_sub(t)) {
+                None => true,
+                Some(since) if !was_active => since >= cooldown,
+                Some(since) => renotify_every.is_some_and(|every| since >= every.max(cooldown)),
+            };
+        if notify {
+            state.notified = Some(now);
+            state.suppressed = false;
+        }
+
+        Ok((true, notify))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(policy: &TriggerPolicy, checks: Vec<(bool, TimeMs)>) -> Vec<(bool, bool)> {
+        let mut state = TriggerState::default();
+        checks
+            .into_iter()
+            .map(|(fired, now)| policy.apply(&mut state, fired, now).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn it_should_notify_once_by_default() {
+        let res = run(
+            &TriggerPolicy::new(),
+            vec![(true, 0), (true, 1000), (false, 2000), (true, 3000)],
+        );
+        assert_eq!(
+            res,
+            vec![(true, true), (true, false), (false, false), (true, true)]
+        );
+    }
+
+    #[test]
+    fn it_should_debounce() {
+        let res = run(
+            &TriggerPolicy::new().with_debounce(3),
+            vec![
+                (true, 0),
+                (true, 1000),
+                (false, 2000),
+                (true, 3000),
+                (true, 4000),
+                (true, 5000),
+            ],
+        );
+        assert_eq!(
+            res,
+            vec![
+                (false, false),
+                (false, false),
+                (false, false),
+                (false, false),
+                (false, false),
+                (true, true)
+            ]
+        );
+    }
+
+    #[test]
+    fn it_should_cool_down() {
+        let res = run(
+            &TriggerPolicy::new().with_cooldown("10s"),
+            vec![
+                (true, 0),
+                (false, 1000),
+                (true, 2000),
+                (false, 3000),
+                (true, 11000),
+            ],
+        );
+        assert_eq!(
+            res,
+            vec![
+                (true, true),
+                (false, false),
+                (true, false),
+                (false, false),
+                (true, true)
+            ]
+        );
+    }
+
+    #[test]
+    fn it_should_renotify() {
+        let res = run(
+            &TriggerPolicy::new().with_renotify_every("1m"),
+            vec![(true, 0), (true, 30000), (true, 60000), (true, 90000)],
+        );
+        assert_eq!(
+            res,
+            vec![(true, true), (true, false), (true, true), (true, false)]
+        );
+    }
+
+    #[test]
+    fn it_should_track_transitions() {
+        let policy = TriggerPolicy::new().with_debounce(2);
+        let mut state = TriggerState::default();
+        let transitions: Vec<Transition> = vec![true, true, true, false, false]
+            .into_iter()
+            .map(|fired| {
+                let was_active = state.active;
+                policy.apply(&mut state, fired, 0).unwrap();
+                Transition::new(was_active, state.active)
+            })
+            .collect();
+        assert_eq!(
+            transitions,
+            vec![
+                Transition::Idle,
+                Transition::Raised,
+                Transition::Ongoing,
+                Transition::Resolved,
+                Transition::Idle
+            ]
+        );
+    }
+
+    #[test]
+    fn it_should_fail_on_bad_durations() {
+        let mut state = TriggerState::default();
+        assert!(TriggerPolicy::new()
+            .with_cooldown("10x")
+            .apply(&mut state, true, 0)
+            .is_err());
+    }
+}
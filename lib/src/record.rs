@@ -0,0 +1,218 @@
+use super::error::Error;
+use super::regex::Regex;
+use super::serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// Presets for common multi-line records
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize, Copy, Clone)]
+pub enum RecordPreset {
+    /// java exceptions and their stack traces
+    Java,
+    /// python tracebacks
+    Python,
+    /// go panics and goroutine dumps
+    Go,
+}
+
+impl RecordPreset {
+    /// the continuation regex of the preset
+    pub fn continuation(&self) -> &'static str {
+        match self {
+            Self::Java => concat!(
+                r"^(\s+(at |\.\.\. \d+ |Suppressed: )|Caused by: |",
+                r"[\w$.]+(Exception|Error|Throwable)(: |$))"
+            ),
+            Self::Python => r"^(\s+|\w+(\.\w+)*(Error|Exception|Exit|Interrupt|Warning)(:|$))",
+            Self::Go => {
+                r"^(\s+|$|goroutine \d+ \[|[\w./*()\[\]-]+\(.*\)$|exit status \d+|\[signal )"
+            }
+        }
+    }
+}
+
+/// RecordMode describes how lines are grouped
+/// into multi-line records e.g. stack traces.
+/// The logfile splits the text into records once per check
+/// and hands them to the triggers in the context, so line based triggers (per line regexes, json, logfmt and scripts)
+/// see every record as a single line.
+/// Slices and matches are expanded to all records they touch.
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize, Clone)]
+pub enum RecordMode {
+    /// a line matching the regex starts a new record,
+    /// all other lines continue the previous record
+    Start(String),
+    /// a line matching the regex continues the previous record,
+    /// all other lines start a new record
+    Continuation(String),
+    Preset(RecordPreset),
+}
+
+impl RecordMode {
+    /// splits the text into records
+    /// returns the byte range of every record without
+    /// the trailing new line
+    pub fn split(&self, text: &str) -> Result<Vec<Range<usize>>, Error> {
+        let (re, starts) = match self {
+            Self::Start(re) => (Regex::new(re)?, true),
+            Self::Continuation(re) => (Regex::new(re)?, false),
+            Self::Preset(preset) => (Regex::new(preset.continuation())?, false),
+        };
+
+        let mut records: Vec<Range<usize>> = vec![];
+        let mut offset = 0;
+        for line in text.split_inclusive('\n') {
+            let content = line.trim_end_matches(&['\n', '\r'][..]);
+            let range = offset..offset + content.len();
+            offset += line.len();
+
+            let new_record = re.is_match(content) == starts;
+            match records.last_mut() {
+                Some(last) if !new_record => last.end = range.end,
+                _ => records.push(range),
+            }
+        }
+
+        Ok(records)
+    }
+
+    /// the records of a check if the logfile groups lines into records,
+    /// otherwise every line is a record
+    pub fn records(records: Option<&[Range<usize>]>, text: &str) -> Vec<Range<usize>> {
+        if let Some(records) = records {
+            return records.to_vec();
+        }
+
+        let mut offset = 0;
+        let mut lines = vec![];
+        for line in text.split_inclusive('\n') {
+            let content = line.trim_end_matches(&['\n', '\r'][..]);
+            lines.push(offset..offset + content.len());
+            offset += line.len();
+        }
+        lines
+    }
+
+    /// expands a byte range of the text to all records it touches.
+    /// The records have to be ordered as returned by split
+    pub fn expand_range(records: &[Range<usize>], range: Range<usize>) -> Range<usize> {
+        let first = records.partition_point(|r| r.end <= range.start);
+        let last = records.partition_point(|r| r.start < range.end);
+
+        let touched = &records[first..last.max(first)];
+        match (touched.first(), touched.last()) {
+            (Some(first), Some(last)) => first.start.min(range.start)..last.end.max(range.end),
+            _ => range,
+        }
+    }
+
+    /// expands a slice of the text to all records it touches.
+    /// empty slices and slices that are not part of the text
+    /// are returned as is
+    pub fn expand<'a>(records: &[Range<usize>], text: &'a str, slice: &'a str) -> &'a str {
+        let base = text.as_ptr() as usize;
+        let ptr = slice.as_ptr() as usize;
+        if slice.is_empty() || ptr < base || ptr + slice.len() > base + text.len() {
+            return slice;
+        }
+
+        let start = ptr - base;
+        &text[Self::expand_range(records, start..start + slice.len())]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records<'a>(mode: &RecordMode, text: &'a str) -> Vec<&'a str> {
+        mode.split(text)
+            .unwrap()
+            .into_iter()
+            .map(|r| &text[r])
+            .collect()
+    }
+
+    const JAVA: &str = "INFO starting\n\
+ERROR request failed\n\
+java.lang.IllegalStateException: boom\n\
+\tat com.example.Foo.bar(Foo.java:10)\n\
+\tat com.example.Main.main(Main.java:5)\n\
+Caused by: java.lang.NullPointerException\n\
+\tat com.example.Foo.baz(Foo.java:20)\n\
+\t... 2 more\n\
+INFO done\n";
+
+    #[test]
+    fn it_should_group_java_traces() {
+        let mode = RecordMode::Preset(RecordPreset::Java);
+        let r = records(&mode, JAVA);
+        assert_eq!(r.len(), 3);
+        assert_eq!(r[0], "INFO starting");
+        assert!(r[1].starts_with("ERROR request failed\njava.lang.IllegalStateException"));
+        assert!(r[1].ends_with("... 2 more"));
+        assert_eq!(r[2], "INFO done");
+    }
+
+    #[test]
+    fn it_should_group_python_tracebacks() {
+        let text = "starting\n\
+Traceback (most recent call last):\n  \
+File \"main.py\", line 3, in <module>\n    \
+foo()\n\
+ValueError: bad value\n\
+done";
+        let mode = RecordMode::Preset(RecordPreset::Python);
+        let r = records(&mode, text);
+        assert_eq!(r.len(), 3);
+        assert_eq!(r[0], "starting");
+        assert!(r[1].starts_with("Traceback"));
+        assert!(r[1].ends_with("ValueError: bad value"));
+        assert_eq!(r[2], "done");
+    }
+
+    #[test]
+    fn it_should_group_go_panics() {
+        let text = "serving\n\
+panic: runtime error: index out of range [5] with length 3\n\
+\n\
+goroutine 1 [running]:\n\
+main.main()\n\
+\t/tmp/prog.go:8 +0x1d\n\
+exit status 2\n\
+restarting";
+        let mode = RecordMode::Preset(RecordPreset::Go);
+        let r = records(&mode, text);
+        assert_eq!(r.len(), 3);
+        assert_eq!(r[0], "serving");
+        assert!(r[1].starts_with("panic:"));
+        assert!(r[1].ends_with("exit status 2"));
+        assert_eq!(r[2], "restarting");
+    }
+
+    #[test]
+    fn it_should_group_by_start_regex() {
+        let text = "2021-01-01 first\ncontinued\n2021-01-02 second\n";
+        let mode = RecordMode::Start(r"^\d{4}-\d{2}-\d{2} ".into());
+        assert_eq!(
+            records(&mode, text),
+            vec!["2021-01-01 first\ncontinued", "2021-01-02 second"]
+        );
+    }
+
+    #[test]
+    fn it_should_expand_slices() {
+        let mode = RecordMode::Preset(RecordPreset::Java);
+        let start = JAVA.find("IllegalStateException").unwrap();
+        let slice = &JAVA[start..start + "IllegalStateException".len()];
+        let records = mode.split(JAVA).unwrap();
+        let expanded = RecordMode::expand(&records, JAVA, slice);
+        assert!(expanded.starts_with("ERROR request failed"));
+        assert!(expanded.ends_with("... 2 more"));
+
+        assert_eq!(RecordMode::expand(&records, JAVA, ""), "");
+        assert_eq!(RecordMode::expand(&records, JAVA, "other"), "other");
+
+        // ranges between records are kept
+        assert_eq!(RecordMode::expand_range(&records, 13..14), 13..14);
+    }
+}
@@ -0,0 +1,364 @@
+use super::handler::HandlerTypes;
+use super::logfile::{Event, EventHandler};
+use super::serde::{Deserialize, Serialize};
+use super::trigger::TriggerType;
+use std::collections::HashMap;
+
+/// matches text against a glob pattern
+/// * matches any number of characters, ? matches a single character
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    // position of the last * and the text position it matched up to
+    let mut star: Option<(usize, usize)> = None;
+    let (mut p, mut t) = (0, 0);
+    while t < text.len() {
+        // a * in the pattern is a wildcard even if the text contains a *
+        if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if let Some((sp, st)) = star {
+            // let the last * match one more character
+            p = sp + 1;
+            t = st + 1;
+            star = Some((sp, st + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// A routing rule sends matching events to named handlers of the logset.
+///
+/// Every matcher that is set has to match:
+///   - log: a glob of the log name e.g. prod-*
+///   - trigger: a glob of the trigger name
+///   - severity: the lowest severity e.g. Warning matches Warning and Error
+///   - tags: tags the log has to have
+///
+/// Routes are checked in order. The first matching route stops the routing
+/// unless continue is set, in which case later routes are checked as well.
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Route {
+    pub handlers: Vec<String>,
+
+    #[serde(default)]
+    pub log: Option<String>,
+    #[serde(default)]
+    pub trigger: Option<String>,
+    #[serde(default)]
+    pub severity: Option<TriggerType>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default, rename = "continue")]
+    pub proceed: bool,
+}
+
+impl Route {
+    pub fn new(handlers: Vec<String>) -> Self {
+        Self {
+            handlers,
+            log: None,
+            trigger: None,
+            severity: None,
+            tags: vec![],
+            proceed: false,
+        }
+    }
+
+    pub fn with_log(mut self, log: &str) -> Self {
+        self.log = Some(log.into());
+        self
+    }
+
+    pub fn with_trigger(mut self, trigger: &str) -> Self {
+        self.trigger = Some(trigger.into());
+        self
+    }
+
+    pub fn with_severity(mut self, severity: TriggerType) -> Self {
+        self.severity = Some(severity);
+        self
+    }
+
+    pub fn with_tags(mut self, tags: Vec<String>) -> Self {
+        self.tags = tags;
+        self
+    }
+
+    pub fn with_continue(mut self, proceed: bool) -> Self {
+        self.proceed = proceed;
+        self
+    }
+
+    pub fn matches(
+        &self,
+        log: &str,
+        tags: &[String],
+        trigger: &str,
+        severity: TriggerType,
+    ) -> bool {
+        self.log.as_ref().is_none_or(|l| glob_match(l, log))
+            && self.trigger.as_ref().is_none_or(|t| glob_match(t, trigger))
+            && self.severity.is_none_or(|s| severity >= s)
+            && self.tags.iter().all(|t| tags.contains(t))
+    }
+
+    /// returns the names of the handlers an event is routed to
+    pub fn targets<'a>(
+        routes: &'a [Route],
+        log: &str,
+        tags: &[String],
+        trigger: &str,
+        severity: TriggerType,
+    ) -> Vec<&'a str> {
+        let mut targets: Vec<&str> = vec![];
+        for route in routes {
+            if !route.matches(log, tags, trigger, severity) {
+                continue;
+            }
+            for handler in &route.handlers {
+                if !targets.contains(&handler.as_str()) {
+                    targets.push(handler);
+                }
+            }
+            if !route.proceed {
+                break;
+            }
+        }
+        targets
+    }
+}
+
+/// Passes the events of a log to the routed handlers.
+/// Without routes every handler receives every event
+pub struct Router<'a> {
+    routes: &'a [Route],
+    handlers: &'a mut HashMap<String, HandlerTypes>,
+    tags: &'a [String],
+}
+
+impl<'a> Router<'a> {
+    pub fn new(
+        routes: &'a [Route],
+        handlers: &'a mut HashMap<String, HandlerTypes>,
+        tags: &'a [String],
+    ) -> Self {
+        Self {
+            routes,
+            handlers,
+            tags,
+        }
+    }
+}
+
+impl<'a> EventHandler for Router<'a> {
+    fn on_event(&mut self, event: &Event) {
+        // escalation targets receive a notifying copy instead of the event
+        for name in &event.escalate {
+            if let Some(handler) = self.handlers.get_mut(name) {
+                let mut extra = event.extra.clone();
+                handler.on_event(&event.escalated(&mut extra));
+            }
+        }
+
+        if self.routes.is_empty() {
+            for (name, handler) in self.handlers.iter_mut() {
+                if !event.escalate.contains(name) {
+                    handler.on_event(event);
+                }
+            }
+            return;
+        }
+
+        let trigger = event.trigger.map_or("", |t| t.name());
+        for name in Route::targets(
+            self.routes,
+            event.name,
+            self.tags,
+            trigger,
+            event.trigger_type,
+        ) {
+            if event.escalate.iter().any(|e| e == name) {
+                continue;
+            }
+            if let Some(handler) = self.handlers.get_mut(name) {
+                handler.on_event(event);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(v: &[&str]) -> Vec<String> {
+        v.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn it_should_match_globs() {
+        assert!(glob_match("prod-*", "prod-api"));
+        assert!(glob_match("prod-*", "prod-"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("*-api", "prod-api"));
+        assert!(glob_match("p?od-*-db*", "prod-eu-db1"));
+        assert!(glob_match("a*b*c", "aXbYbZc"));
+        assert!(glob_match("a*", "a*b"));
+        assert!(glob_match("*b", "a*b"));
+        assert!(!glob_match("prod-*", "staging-api"));
+        assert!(!glob_match("prod-?", "prod-"));
+        assert!(!glob_match("a*b", "aXbY"));
+    }
+
+    #[test]
+    fn it_should_match_every_set_matcher() {
+        let route = Route::new(names(&["pager"]))
+            .with_log("prod-*")
+            .with_trigger("login*")
+            .with_severity(TriggerType::Error)
+            .with_tags(names(&["team-a"]));
+
+        let tags = names(&["team-a", "eu"]);
+        assert!(route.matches("prod-api", &tags, "login", TriggerType::Error));
+        assert!(route.matches("prod-api", &tags, "login_failed", TriggerType::Fatal));
+        assert!(!route.matches("dev-api", &tags, "login", TriggerType::Error));
+        assert!(!route.matches("prod-api", &tags, "panic", TriggerType::Error));
+        assert!(!route.matches("prod-api", &tags, "login", TriggerType::Warning));
+        assert!(!route.matches("prod-api", &[], "login", TriggerType::Error));
+
+        let any = Route::new(names(&["audit"]));
+        assert!(any.matches("", &[], "", TriggerType::NoEvent));
+    }
+
+    #[test]
+    fn it_should_stop_at_the_first_match() {
+        let routes = vec![
+            Route::new(names(&["pager"]))
+                .with_log("prod-*")
+                .with_severity(TriggerType::Error),
+            Route::new(names(&["email"])).with_severity(TriggerType::Warning),
+            Route::new(names(&["audit"])),
+        ];
+
+        let targets = |log, severity| Route::targets(&routes, log, &[], "t", severity);
+        assert_eq!(targets("prod-api", TriggerType::Fatal), vec!["pager"]);
+        assert_eq!(targets("dev-api", TriggerType::Fatal), vec!["email"]);
+        assert_eq!(targets("prod-api", TriggerType::Warning), vec!["email"]);
+        assert_eq!(targets("prod-api", TriggerType::Info), vec!["audit"]);
+    }
+
+    #[test]
+    fn it_should_continue_if_requested() {
+        let routes = vec![
+            Route::new(names(&["audit"])).with_continue(true),
+            Route::new(names(&["pager", "audit"]))
+                .with_log("prod-*")
+                .with_severity(TriggerType::Error)
+                .with_continue(true),
+            Route::new(names(&["email"])).with_severity(TriggerType::Warning),
+            Route::new(names(&["never"])),
+        ];
+
+        let targets = |log, severity| Route::targets(&routes, log, &[], "t", severity);
+        assert_eq!(
+            targets("prod-api", TriggerType::Error),
+            vec!["audit", "pager", "email"]
+        );
+        assert_eq!(
+            targets("dev-api", TriggerType::Error),
+            vec!["audit", "email"]
+        );
+        assert_eq!(
+            targets("prod-api", TriggerType::Info),
+            vec!["audit", "never"]
+        );
+    }
+
+    #[tokio::test]
+    async fn it_should_route_events_to_handlers() {
+        use crate::handler::ShellHandler;
+        use crate::logfile::Logfile;
+        use crate::source::{DataSourceTypes, InMemoryDataSource};
+        use crate::task::{InMemoryTimeSource, Task, TimeSourceTypes};
+        use crate::{RegexTrigger, TriggerTypes};
+
+        let mut lf = Logfile::new(
+            "prod-api",
+            DataSourceTypes::InMemory(InMemoryDataSource::new(vec!["warn\nerror".into()])),
+            Task::new(
+                true,
+                10,
+                TimeSourceTypes::InMemory(InMemoryTimeSource::new(vec![])),
+            ),
+        );
+        lf.tags = names(&["team-a"]);
+        for (name, trigger_type) in [
+            ("warn", TriggerType::Warning),
+            ("error", TriggerType::Error),
+        ] {
+            lf.push(TriggerTypes::Regex(RegexTrigger::new(
+                name,
+                "",
+                trigger_type,
+                name,
+                false,
+            )));
+        }
+
+        let mut handlers = HashMap::new();
+        for name in ["pager", "email"] {
+            handlers.insert(
+                name.to_string(),
+                HandlerTypes::Shell(ShellHandler::new("echo $MINUTECAT_TRIGGER_NAME")),
+            );
+        }
+        let routes = vec![
+            Route::new(names(&["pager"]))
+                .with_tags(names(&["team-a"]))
+                .with_severity(TriggerType::Error),
+            Route::new(names(&["email"])).with_severity(TriggerType::Warning),
+        ];
+
+        let tags = lf.tags.clone();
+        let mut router = Router::new(&routes, &mut handlers, &tags);
+        lf.force_update(&mut vec![&mut router]).await.unwrap();
+
+        let outputs = |name: &str| match &handlers[name] {
+            HandlerTypes::Shell(h) => {
+                h.wait();
+                h.results()
+                    .into_iter()
+                    .map(|r| r.stdout)
+                    .collect::<Vec<_>>()
+            }
+            _ => vec![],
+        };
+        assert_eq!(outputs("pager"), vec!["error\n"]);
+        assert_eq!(outputs("email"), vec!["warn\n"]);
+    }
+
+    #[test]
+    fn it_should_read_routes_from_yaml() {
+        let routes: Vec<Route> = serde_yaml::from_str(
+            "- handlers: [pager]\n  log: prod-*\n  severity: error\n  continue: true\n- handlers: [audit]\n",
+        )
+        .unwrap();
+        assert_eq!(
+            routes,
+            vec![
+                Route::new(names(&["pager"]))
+                    .with_log("prod-*")
+                    .with_severity(TriggerType::Error)
+                    .with_continue(true),
+                Route::new(names(&["audit"])),
+            ]
+        );
+    }
+}
@@ -0,0 +1,343 @@
+use super::chrono::{Datelike, Local, NaiveDateTime, NaiveTime, TimeZone, Weekday};
+use super::error::Error;
+use super::route::glob_match;
+use super::serde::{Deserialize, Serialize};
+use super::task::TimeMs;
+use super::trigger::TriggerType;
+use std::fmt;
+use std::str::FromStr;
+
+/// Selects the events a silence applies to.
+/// Every matcher that is set has to match
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
+pub struct SilenceMatcher {
+    /// a glob of the log name e.g. prod-*
+    #[serde(default)]
+    pub log: Option<String>,
+    /// a glob of the trigger name
+    #[serde(default)]
+    pub trigger: Option<String>,
+    /// the lowest severity of the event
+    #[serde(default)]
+    pub severity: Option<TriggerType>,
+}
+
+impl SilenceMatcher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_log(mut self, log: &str) -> Self {
+        self.log = Some(log.into());
+        self
+    }
+
+    pub fn with_trigger(mut self, trigger: &str) -> Self {
+        self.trigger = Some(trigger.into());
+        self
+    }
+
+    pub fn with_severity(mut self, severity: TriggerType) -> Self {
+        self.severity = Some(severity);
+        self
+    }
+
+    pub fn matches(&self, log: &str, trigger: &str, severity: TriggerType) -> bool {
+        self.log.as_ref().is_none_or(|l| glob_match(l, log))
+            && self.trigger.as_ref().is_none_or(|t| glob_match(t, trigger))
+            && self.severity.is_none_or(|s| severity >= s)
+    }
+}
+
+impl fmt::Display for SilenceMatcher {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "log={} trigger={} severity={}",
+            self.log.as_deref().unwrap_or("*"),
+            self.trigger.as_deref().unwrap_or("*"),
+            self.severity.map_or("*".into(), |s| s.to_string())
+        )
+    }
+}
+
+/// parses a local time of the form 2024-09-01 14:30
+/// into ms since the epoch
+pub fn parse_local_time(s: &str) -> Result<TimeMs, Error> {
+    let time =
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M").map_err(|_| Error::FromStringError)?;
+    match Local.from_local_datetime(&time).earliest() {
+        Some(time) => Ok(time.timestamp_millis() as TimeMs),
+        _ => Err(Error::FromStringError),
+    }
+}
+
+/// formats ms since the epoch as local time
+pub fn format_local_time(time: TimeMs) -> String {
+    match Local.timestamp_millis_opt(time as i64).single() {
+        Some(time) => time.format("%Y-%m-%d %H:%M").to_string(),
+        _ => time.to_string(),
+    }
+}
+
+/// A silence mutes matching events until it ends.
+/// Silenced events still reach every handler
+/// but notifiers skip them
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct Silence {
+    #[serde(flatten)]
+    pub matcher: SilenceMatcher,
+    /// end of the silence in ms since the epoch
+    pub ends: TimeMs,
+    #[serde(default)]
+    pub comment: String,
+}
+
+impl Silence {
+    pub fn new(matcher: SilenceMatcher, ends: TimeMs, comment: &str) -> Self {
+        Self {
+            matcher,
+            ends,
+            comment: comment.into(),
+        }
+    }
+
+    pub fn is_active(&self, now: TimeMs) -> bool {
+        now < self.ends
+    }
+}
+
+/// A recurring silence e.g. every sunday from 02:00 to 04:00 local time.
+/// Windows that end before they start continue on the next day
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
+pub struct MaintenanceWindow {
+    #[serde(flatten)]
+    pub matcher: SilenceMatcher,
+    /// days the window starts on e.g. sun or monday
+    /// every day if empty
+    #[serde(default)]
+    pub days: Vec<String>,
+    /// start time e.g. 02:00
+    pub start: String,
+    /// end time e.g. 04:00
+    pub end: String,
+    #[serde(default)]
+    pub comment: String,
+}
+
+impl MaintenanceWindow {
+    /// creates a window and validates days and times
+    pub fn new(
+        matcher: SilenceMatcher,
+        days: Vec<String>,
+        start: &str,
+        end: &str,
+        comment: &str,
+    ) -> Result<Self, Error> {
+        let window = Self {
+            matcher,
+            days,
+            start: start.into(),
+            end: end.into(),
+            comment: comment.into(),
+        };
+        window.weekdays()?;
+        Self::time(&window.start)?;
+        Self::time(&window.end)?;
+        Ok(window)
+    }
+
+    fn time(s: &str) -> Result<NaiveTime, Error> {
+        NaiveTime::parse_from_str(s, "%H:%M").map_err(|_| Error::FromStringError)
+    }
+
+    fn weekdays(&self) -> Result<Vec<Weekday>, Error> {
+        self.days
+            .iter()
+            .map(|d| Weekday::from_str(d).map_err(|_| Error::FromStringError))
+            .collect()
+    }
+
+    /// true if the window is active at this local time
+    pub fn is_active_at(&self, time: NaiveDateTime) -> bool {
+        let (start, end, days) = match (
+            Self::time(&self.start),
+            Self::time(&self.end),
+            self.weekdays(),
+        ) {
+            (Ok(start), Ok(end), Ok(days)) => (start, end, days),
+            _ => return false,
+        };
+        let starts_on = |day: Weekday| days.is_empty() || days.contains(&day);
+
+        let now = time.time();
+        if start <= end {
+            starts_on(time.weekday()) && start <= now && now < end
+        } else {
+            // the window continues after midnight
+            (starts_on(time.weekday()) && start <= now)
+                || (starts_on(time.weekday().pred()) && now < end)
+        }
+    }
+
+    pub fn is_active(&self, now: TimeMs) -> bool {
+        match Local.timestamp_millis_opt(now as i64).single() {
+            Some(time) => self.is_active_at(time.naive_local()),
+            _ => false,
+        }
+    }
+}
+
+/// The silences and maintenance windows of a logset
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
+pub struct Silences {
+    #[serde(default)]
+    pub silences: Vec<Silence>,
+    #[serde(default)]
+    pub maintenance: Vec<MaintenanceWindow>,
+}
+
+impl Silences {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.silences.is_empty() && self.maintenance.is_empty()
+    }
+
+    /// true if an active silence or maintenance window matches
+    pub fn is_silenced(
+        &self,
+        log: &str,
+        trigger: &str,
+        severity: TriggerType,
+        now: TimeMs,
+    ) -> bool {
+        self.silences
+            .iter()
+            .any(|s| s.is_active(now) && s.matcher.matches(log, trigger, severity))
+            || self
+                .maintenance
+                .iter()
+                .any(|w| w.matcher.matches(log, trigger, severity) && w.is_active(now))
+    }
+
+    /// removes silences that ended
+    pub fn prune(&mut self, now: TimeMs) {
+        self.silences.retain(|s| s.is_active(now));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+
+    fn at(day: u32, h: u32, m: u32) -> NaiveDateTime {
+        // 2024-09-01 is a sunday
+        NaiveDate::from_ymd_opt(2024, 9, day)
+            .unwrap()
+            .and_hms_opt(h, m, 0)
+            .unwrap()
+    }
+
+    #[test]
+    fn it_should_match_silences() {
+        let mut silences = Silences::new();
+        silences.silences.push(Silence::new(
+            SilenceMatcher::new()
+                .with_log("prod-*")
+                .with_severity(TriggerType::Error),
+            1000,
+            "deploy",
+        ));
+
+        assert!(silences.is_silenced("prod-api", "login", TriggerType::Error, 999));
+        assert!(!silences.is_silenced("prod-api", "login", TriggerType::Error, 1000));
+        assert!(silences.is_silenced("prod-api", "login", TriggerType::Fatal, 999));
+        assert!(!silences.is_silenced("prod-api", "login", TriggerType::Warning, 999));
+        assert!(!silences.is_silenced("dev-api", "login", TriggerType::Error, 999));
+
+        silences.prune(999);
+        assert_eq!(silences.silences.len(), 1);
+        silences.prune(1000);
+        assert!(silences.is_empty());
+    }
+
+    #[test]
+    fn it_should_recur_weekly() {
+        let window = MaintenanceWindow::new(
+            SilenceMatcher::new(),
+            vec!["sun".into()],
+            "02:00",
+            "04:00",
+            "",
+        )
+        .unwrap();
+
+        assert!(window.is_active_at(at(1, 2, 0)));
+        assert!(window.is_active_at(at(8, 3, 59)));
+        assert!(!window.is_active_at(at(1, 4, 0)));
+        assert!(!window.is_active_at(at(1, 1, 59)));
+        assert!(!window.is_active_at(at(2, 3, 0)));
+    }
+
+    #[test]
+    fn it_should_continue_after_midnight() {
+        let window = MaintenanceWindow::new(
+            SilenceMatcher::new(),
+            vec!["Saturday".into()],
+            "23:00",
+            "01:00",
+            "",
+        )
+        .unwrap();
+
+        assert!(window.is_active_at(at(7, 23, 30)));
+        assert!(window.is_active_at(at(8, 0, 30)));
+        assert!(!window.is_active_at(at(8, 23, 30)));
+        assert!(!window.is_active_at(at(7, 0, 30)));
+
+        let daily =
+            MaintenanceWindow::new(SilenceMatcher::new(), vec![], "23:00", "01:00", "").unwrap();
+        assert!(daily.is_active_at(at(3, 0, 30)));
+        assert!(!daily.is_active_at(at(3, 1, 30)));
+    }
+
+    #[test]
+    fn it_should_validate_windows() {
+        let new = |days: Vec<String>, start, end| {
+            MaintenanceWindow::new(SilenceMatcher::new(), days, start, end, "")
+        };
+        assert!(new(vec!["someday".into()], "02:00", "04:00").is_err());
+        assert!(new(vec![], "2am", "04:00").is_err());
+        assert!(new(vec![], "02:00", "25:00").is_err());
+    }
+
+    #[test]
+    fn it_should_parse_local_times() {
+        let time = parse_local_time("2024-09-01 14:30").unwrap();
+        assert_eq!(format_local_time(time), "2024-09-01 14:30");
+        assert!(parse_local_time("tomorrow").is_err());
+    }
+
+    #[test]
+    fn it_should_read_silences_from_yaml() {
+        let silences: Silences = serde_yaml::from_str(
+            "silences:\n  - log: prod-*\n    ends: 1000\n    comment: deploy\nmaintenance:\n  - trigger: backup\n    days: [sun]\n    start: \"02:00\"\n    end: \"04:00\"\n",
+        )
+        .unwrap();
+        assert_eq!(
+            silences.silences[0],
+            Silence::new(SilenceMatcher::new().with_log("prod-*"), 1000, "deploy")
+        );
+        assert_eq!(
+            silences.maintenance[0].matcher,
+            SilenceMatcher::new().with_trigger("backup")
+        );
+
+        let ser = serde_yaml::to_string(&silences).unwrap();
+        assert_eq!(serde_yaml::from_str::<Silences>(&ser).unwrap(), silences);
+    }
+}
@@ -5,6 +5,7 @@ use crate::typetag;
 use crate::FileDataSource;
 use crate::HttpDataSource;
 use crate::InMemoryDataSource;
+use crate::WasmDataSource;
 use std::str;
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -12,6 +13,7 @@ pub enum DataSourceTypes {
     InMemory(InMemoryDataSource),
     File(FileDataSource),
     Http(HttpDataSource),
+    Wasm(WasmDataSource),
     Generic(Box<dyn DataSource>),
 }
 
@@ -23,9 +25,20 @@ impl DataSource for DataSourceTypes {
             Self::InMemory(s) => s.load().await,
             Self::File(s) => s.load().await,
             Self::Http(s) => s.load().await,
+            Self::Wasm(s) => s.load().await,
             Self::Generic(s) => s.load().await,
         }
     }
+
+    fn size(&self) -> Option<u64> {
+        match self {
+            Self::InMemory(s) => s.size(),
+            Self::File(s) => s.size(),
+            Self::Http(s) => s.size(),
+            Self::Wasm(s) => s.size(),
+            Self::Generic(s) => s.size(),
+        }
+    }
 }
 
 pub trait DataSourceClone {
@@ -48,6 +61,14 @@ where
 #[async_trait]
 pub trait DataSource: DataSourceClone + Send {
     async fn load(&mut self) -> Result<String, Error>;
+
+    /// the size of the whole source at the end of the last loaded text
+    /// e.g. the file size. It tells triggers how much was added
+    /// even if the text is cut to a line limit.
+    /// Sources that do not know their size return None
+    fn size(&self) -> Option<u64> {
+        None
+    }
 }
 
 impl Clone for Box<dyn DataSource> {
@@ -22,6 +22,7 @@ where
     input: T,
     line_limit: usize,
     chunk_size: u64,
+    size: u64,
 }
 
 impl<T> TailReader<T>
@@ -33,9 +34,15 @@ where
             input,
             line_limit,
             chunk_size: 64,
+            size: 0,
         }
     }
 
+    /// the size of the input when the lines were read
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
     async fn read_chunk(&mut self, chunk_size: usize) -> Result<(usize, String), Error> {
         let mut buf = vec![0u8; chunk_size];
         self.input.read_exact(&mut buf).await?;
@@ -59,6 +66,7 @@ where
 
         // get total file size
         let mut seek_pos = self.input.seek(SeekFrom::End(0)).await?;
+        self.size = seek_pos;
 
         let mut lines = 0;
         let mut strbuf = "".to_string();
@@ -99,6 +107,10 @@ where
 pub struct FileDataSource {
     line_limit: usize,
     path: String,
+
+    /// the file size at the last load
+    #[serde(skip)]
+    size: Option<u64>,
 }
 
 impl FileDataSource {
@@ -106,6 +118,7 @@ impl FileDataSource {
         Self {
             path: path.into(),
             line_limit,
+            size: None,
         }
     }
 }
@@ -117,7 +130,13 @@ impl DataSource for FileDataSource {
         let file = File::open(Path::new(&self.path)).await?;
         let mut rev_reader = TailReader::new(file, self.line_limit);
 
-        Ok(rev_reader.read_lines().await?)
+        let lines = rev_reader.read_lines().await?;
+        self.size = Some(rev_reader.size());
+        Ok(lines)
+    }
+
+    fn size(&self) -> Option<u64> {
+        self.size
     }
 }
 
@@ -134,6 +153,7 @@ mod tests {
 
         let lines = rev_reader.read_lines().await.unwrap();
         assert_eq!(lines, "For\nUnit\nTests");
+        assert_eq!(rev_reader.size(), 34);
     }
 
     #[tokio::test]
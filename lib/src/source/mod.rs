@@ -3,9 +3,11 @@ mod directory;
 mod filesystem;
 mod http;
 mod inmemory;
+mod wasm;
 
 pub use self::base::*;
 pub use self::directory::*;
 pub use self::filesystem::*;
 pub use self::http::*;
 pub use self::inmemory::*;
+pub use self::wasm::*;
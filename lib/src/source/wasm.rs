@@ -0,0 +1,84 @@
+use crate::async_trait::async_trait;
+use crate::error::Error;
+use crate::plugin::{default_fuel, default_max_memory, WasmCache, WasmPlugin};
+use crate::serde::{Deserialize, Serialize};
+use crate::typetag;
+use crate::DataSource;
+
+/// A wasm data source calls a .wasm plugin (see WasmPlugin for the ABI).
+/// The plugin exports load() -> i64 that returns the packed (ptr, len)
+/// of the text in its memory.
+///
+/// Plugins run without host functions and can therefore
+/// only return data they compute themselves.
+/// The module is compiled once and again when the file is modified.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct WasmDataSource {
+    path: String,
+
+    #[serde(default = "default_fuel")]
+    fuel: u64,
+    #[serde(default = "default_max_memory")]
+    max_memory: usize,
+
+    #[serde(skip)]
+    module: WasmCache,
+}
+
+impl WasmDataSource {
+    pub fn new(path: &str) -> Self {
+        Self {
+            path: path.into(),
+            fuel: default_fuel(),
+            max_memory: default_max_memory(),
+            module: WasmCache::new(),
+        }
+    }
+
+    pub fn with_fuel(mut self, fuel: u64) -> Self {
+        self.fuel = fuel;
+        self
+    }
+
+    pub fn with_max_memory(mut self, max_memory: usize) -> Self {
+        self.max_memory = max_memory;
+        self
+    }
+}
+
+#[typetag::serde]
+#[async_trait]
+impl DataSource for WasmDataSource {
+    async fn load(&mut self) -> Result<String, Error> {
+        let mut plugin = self
+            .module
+            .module(&self.path)?
+            .instantiate(self.fuel, self.max_memory)?;
+        match WasmPlugin::unpack(plugin.call::<i64>("load")?) {
+            Some((ptr, len)) => plugin.read_str(ptr, len),
+            _ => Err(Error::PluginError("plugin returned no data".into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn it_should_load_from_plugins() {
+        let wasm = wat::parse_str(
+            r#"(module
+  (memory (export "memory") 1)
+  (data (i32.const 16) "hello from wasm")
+  (func (export "load") (result i64)
+    (i64.or (i64.shl (i64.const 16) (i64.const 32)) (i64.const 15))))"#,
+        )
+        .unwrap();
+        let path = std::env::temp_dir().join("minutecat_source_test.wasm");
+        std::fs::write(&path, wasm).unwrap();
+
+        let mut source = WasmDataSource::new(path.to_str().unwrap());
+        assert_eq!(source.load().await.unwrap(), "hello from wasm");
+    }
+}
@@ -0,0 +1,328 @@
+use super::ack::Ack;
+use super::error::Error;
+use super::extra::ExtraData;
+use super::serde::{Deserialize, Serialize};
+use super::serde_yaml;
+use super::silence::Silences;
+use super::status::LogStatus;
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File};
+use std::io::Read;
+use std::path::Path;
+use std::str;
+
+/// The runtime state of a single log
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+pub struct LogState {
+    /// trigger state and data of the event handlers
+    #[serde(default)]
+    pub extra: ExtraData,
+    /// the status after the last check
+    #[serde(default)]
+    pub status: LogStatus,
+    /// acks by trigger name
+    #[serde(default)]
+    pub acks: HashMap<String, Ack>,
+}
+
+/// The runtime state of a logset e.g. cooldowns, acks and silences.
+/// It is kept in a file next to the configuration,
+/// so the configuration is only written by the user and the command line.
+/// The logset reloads the file before every update cycle and
+/// merges its changes back afterwards, so other processes
+/// may change the state while minutecat is running
+#[derive(Debug, Default, PartialEq, Clone, Serialize, Deserialize)]
+pub struct State {
+    /// silences and maintenance windows added with the command line
+    #[serde(default)]
+    pub silences: Silences,
+    /// state by log name
+    #[serde(default)]
+    pub logs: BTreeMap<String, LogState>,
+}
+
+impl State {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// the state file of a configuration
+    /// e.g. config.yaml -> config.state.yaml
+    pub fn path(cfg_path: &str) -> String {
+        Path::new(cfg_path)
+            .with_extension("state.yaml")
+            .to_string_lossy()
+            .into()
+    }
+
+    /// reads a state file.
+    /// A missing file is an empty state
+    pub fn from_path(path: &str) -> Result<Self, Error> {
+        let mut file = match File::open(path) {
+            Ok(file) => file,
+            Err(_err) => return Ok(Self::new()),
+        };
+        let mut r = vec![];
+        file.read_to_end(&mut r)?;
+        Ok(serde_yaml::from_str(str::from_utf8(&r)?)?)
+    }
+
+    /// writes the state to a temporary file and moves it in place
+    /// so readers never see a partial file
+    pub fn to_file(&self, path: &str) -> Result<(), Error> {
+        let tmp = format!("{}.tmp", path);
+        fs::write(&tmp, serde_yaml::to_string(self)?)?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    /// merges the changes made since base into the state on disk.
+    /// Values that did not change since base keep the value on disk.
+    /// Logs that were removed since base are dropped
+    pub fn merge(&self, base: &State, mut disk: State) -> State {
+        if self.silences != base.silences {
+            disk.silences = self.silences.clone();
+        }
+        disk.logs
+            .retain(|name, _| self.logs.contains_key(name) || !base.logs.contains_key(name));
+        for (name, ours) in &self.logs {
+            let base = base.logs.get(name);
+            let theirs = disk.logs.entry(name.clone()).or_default();
+            if base.is_none_or(|b| b.extra != ours.extra) {
+                theirs.extra = ours.extra.clone();
+            }
+            if base.is_none_or(|b| b.status != ours.status) {
+                theirs.status = ours.status.clone();
+            }
+            // acks are merged one by one, e.g. the command line
+            // may ack one trigger while another one resolves
+            let mut triggers: Vec<&String> = ours.acks.keys().collect();
+            if let Some(base) = base {
+                triggers.extend(base.acks.keys());
+            }
+            for trigger in triggers {
+                let ack = ours.acks.get(trigger);
+                if base.is_some_and(|b| b.acks.get(trigger) == ack) {
+                    continue;
+                }
+                match ack {
+                    Some(ack) => theirs.acks.insert(trigger.clone(), ack.clone()),
+                    _ => theirs.acks.remove(trigger),
+                };
+            }
+        }
+        disk
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logfile::Logfile;
+    use crate::logfile::{Event, EventHandler};
+    use crate::logset::LogSet;
+    use crate::policy::TriggerState;
+    use crate::silence::{Silence, SilenceMatcher};
+    use crate::source::{DataSourceTypes, InMemoryDataSource};
+    use crate::task::{InMemoryTimeSource, Task, TimeMs, TimeSourceTypes};
+    use crate::{RegexTrigger, TriggerType, TriggerTypes};
+
+    fn path(name: &str) -> String {
+        let path = std::env::temp_dir().join(name);
+        let _ = fs::remove_file(&path);
+        path.to_string_lossy().into()
+    }
+
+    fn logset(state_path: &str) -> LogSet {
+        let mut lf = Logfile::new(
+            "api",
+            DataSourceTypes::InMemory(InMemoryDataSource::new(vec!["panic".into(); 2])),
+            Task::new(
+                true,
+                10,
+                TimeSourceTypes::InMemory(InMemoryTimeSource::new(vec![])),
+            ),
+        );
+        lf.push(TriggerTypes::Regex(RegexTrigger::new(
+            "panic",
+            "",
+            TriggerType::Error,
+            "panic",
+            false,
+        )));
+        let mut logset = LogSet::new();
+        logset.push(lf);
+        logset.state_path = Some(state_path.into());
+        logset
+    }
+
+    #[test]
+    fn it_should_name_the_state_file_after_the_config() {
+        assert_eq!(State::path("/a/config.yaml"), "/a/config.state.yaml");
+    }
+
+    #[tokio::test]
+    async fn it_should_keep_trigger_state_out_of_the_config() {
+        let state_path = path("minutecat_state_test.yaml");
+        let mut logset = logset(&state_path);
+        logset.force_update(&mut vec![]).await.unwrap();
+
+        // the config does not contain runtime state
+        let config = logset.serialize().unwrap();
+        assert!(!config.contains("extra"));
+        assert!(!config.contains("status"));
+
+        // a restarted logset reads it from the state file
+        let mut restarted = LogSet::deserialize(&config).unwrap();
+        restarted.state_path = Some(state_path.clone());
+        restarted.load_state().unwrap();
+        assert!(TriggerState::load(&mut restarted.logs[0].extra, "panic").active);
+        assert_eq!(restarted.logs[0].status.severity, TriggerType::Error);
+
+        let _ = fs::remove_file(&state_path);
+    }
+
+    struct SilencedHandler(Vec<bool>);
+    impl EventHandler for SilencedHandler {
+        fn on_event(&mut self, event: &Event) {
+            self.0.push(event.silenced);
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_reload_silences_before_every_update() {
+        let state_path = path("minutecat_state_silence_test.yaml");
+        let mut logset = logset(&state_path);
+        let mut handler = SilencedHandler(vec![]);
+        logset.force_update(&mut vec![&mut handler]).await.unwrap();
+
+        // e.g. the command line adds a silence while minutecat runs
+        let mut cli = self::logset(&state_path);
+        cli.load_state().unwrap();
+        cli.silences.silences.push(Silence::new(
+            SilenceMatcher::new().with_log("api"),
+            TimeMs::MAX,
+            "deploy",
+        ));
+        cli.save_state().unwrap();
+
+        logset.force_update(&mut vec![&mut handler]).await.unwrap();
+        assert_eq!(handler.0, vec![false, true]);
+        assert_eq!(logset.silences.silences.len(), 1);
+        // the state of the log was kept by the command line
+        assert!(TriggerState::load(&mut logset.logs[0].extra, "panic").active);
+
+        let _ = fs::remove_file(&state_path);
+    }
+
+    struct AckHandler(Vec<Option<String>>);
+    impl EventHandler for AckHandler {
+        fn on_event(&mut self, event: &Event) {
+            self.0.push(event.ack.as_ref().map(|a| a.by.clone()));
+        }
+    }
+
+    #[tokio::test]
+    async fn it_should_reload_acks_before_every_update() {
+        use crate::command::{AckCommand, Command};
+
+        let state_path = path("minutecat_state_ack_test.yaml");
+        let mut logset = logset(&state_path);
+        let mut handler = AckHandler(vec![]);
+        logset.force_update(&mut vec![&mut handler]).await.unwrap();
+
+        // e.g. the command line acks the trigger while minutecat runs
+        let mut cli = self::logset(&state_path);
+        cli.load_state().unwrap();
+        AckCommand::new("panic", Some(Ack::new("alice", 0)))
+            .execute(&mut cli.logs[0])
+            .unwrap();
+        assert!(AckCommand::new("other", Some(Ack::new("alice", 0)))
+            .execute(&mut cli.logs[0])
+            .is_err());
+        cli.save_state().unwrap();
+
+        logset.force_update(&mut vec![&mut handler]).await.unwrap();
+        assert_eq!(handler.0, vec![None, Some("alice".to_string())]);
+
+        let _ = fs::remove_file(&state_path);
+    }
+
+    #[tokio::test]
+    async fn it_should_save_the_state_if_a_log_fails() {
+        let state_path = path("minutecat_state_error_test.yaml");
+        let mut logset = logset(&state_path);
+        // a log without data fails to load
+        logset.logs.insert(
+            0,
+            Logfile::new(
+                "broken",
+                DataSourceTypes::InMemory(InMemoryDataSource::new(vec![])),
+                Task::new(
+                    true,
+                    10,
+                    TimeSourceTypes::InMemory(InMemoryTimeSource::new(vec![])),
+                ),
+            ),
+        );
+
+        let errors = match logset.force_update(&mut vec![]).await {
+            Err(crate::Error::UpdateErrors(errors)) => errors,
+            _ => panic!("expected update errors"),
+        };
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].0, "broken");
+
+        // the other log was updated and its state saved
+        let state = State::from_path(&state_path).unwrap();
+        assert_eq!(state.logs["api"].status.severity, TriggerType::Error);
+
+        let _ = fs::remove_file(&state_path);
+    }
+
+    #[test]
+    fn it_should_keep_changes_of_others() {
+        let value = |v: &str| {
+            let mut extra = ExtraData::new();
+            extra
+                .put("key", &v.to_string(), ExtraData::serialize)
+                .unwrap();
+            extra
+        };
+
+        let mut base = State::new();
+        for name in ["a", "b", "removed"] {
+            base.logs.insert(name.into(), LogState::default());
+        }
+        base.logs
+            .get_mut("a")
+            .unwrap()
+            .acks
+            .insert("old".into(), Ack::new("bob", 0));
+
+        // we changed b, removed a log and the ack of a
+        let mut ours = base.clone();
+        ours.logs.remove("removed");
+        ours.logs.get_mut("a").unwrap().acks.remove("old");
+        ours.logs.get_mut("b").unwrap().extra = value("ours");
+
+        // others changed a, added a log and acked another trigger
+        let mut disk = base.clone();
+        disk.logs.insert("other".into(), LogState::default());
+        let theirs = disk.logs.get_mut("a").unwrap();
+        theirs.extra = value("theirs");
+        theirs.acks.insert("new".into(), Ack::new("alice", 0));
+
+        let merged = ours.merge(&base, disk);
+        assert_eq!(
+            merged.logs.keys().collect::<Vec<_>>(),
+            vec!["a", "b", "other"]
+        );
+        assert_eq!(merged.logs["a"].extra, value("theirs"));
+        assert_eq!(merged.logs["b"].extra, value("ours"));
+        assert_eq!(
+            merged.logs["a"].acks.keys().collect::<Vec<_>>(),
+            vec!["new"]
+        );
+    }
+}
@@ -0,0 +1,96 @@
+use super::serde::{Deserialize, Serialize};
+use super::task::TimeMs;
+use super::trigger::TriggerType;
+use std::collections::BTreeMap;
+
+/// The aggregate status of a log or a set of logs
+/// computed from all triggers after each check
+#[derive(Debug, Default, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct LogStatus {
+    /// the highest severity among the active triggers
+    pub severity: TriggerType,
+    /// number of active triggers per severity
+    pub counts: BTreeMap<TriggerType, usize>,
+    /// time of the last change of severity or counts
+    pub last_change: Option<TimeMs>,
+}
+
+impl LogStatus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// computes the status from the severities of all active triggers.
+    /// returns true if the status changed
+    pub fn update(&mut self, active: &[TriggerType], now: TimeMs) -> bool {
+        let mut counts = BTreeMap::new();
+        for severity in active {
+            *counts.entry(*severity).or_insert(0) += 1;
+        }
+        let severity = active.iter().copied().max().unwrap_or_default();
+
+        if severity == self.severity && counts == self.counts {
+            return false;
+        }
+        self.severity = severity;
+        self.counts = counts;
+        self.last_change = Some(now);
+        true
+    }
+
+    /// combines two statuses e.g. of several logs
+    pub fn merge(&mut self, other: &LogStatus) {
+        self.severity = self.severity.max(other.severity);
+        for (severity, count) in &other.counts {
+            *self.counts.entry(*severity).or_insert(0) += count;
+        }
+        self.last_change = self.last_change.max(other.last_change);
+    }
+
+    /// number of active triggers with the given severity
+    pub fn count(&self, severity: TriggerType) -> usize {
+        self.counts.get(&severity).copied().unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_use_highest_severity() {
+        let mut status = LogStatus::new();
+        assert!(status.update(
+            &[TriggerType::Error, TriggerType::Success, TriggerType::Error],
+            100
+        ));
+        assert_eq!(status.severity, TriggerType::Error);
+        assert_eq!(status.count(TriggerType::Error), 2);
+        assert_eq!(status.count(TriggerType::Success), 1);
+        assert_eq!(status.last_change, Some(100));
+
+        // nothing changed
+        assert!(!status.update(
+            &[TriggerType::Success, TriggerType::Error, TriggerType::Error],
+            200
+        ));
+        assert_eq!(status.last_change, Some(100));
+
+        assert!(status.update(&[], 300));
+        assert_eq!(status.severity, TriggerType::NoEvent);
+        assert_eq!(status.last_change, Some(300));
+    }
+
+    #[test]
+    fn it_should_merge() {
+        let mut a = LogStatus::new();
+        a.update(&[TriggerType::Warning], 100);
+        let mut b = LogStatus::new();
+        b.update(&[TriggerType::Critical, TriggerType::Warning], 50);
+
+        a.merge(&b);
+        assert_eq!(a.severity, TriggerType::Critical);
+        assert_eq!(a.count(TriggerType::Warning), 2);
+        assert_eq!(a.last_change, Some(100));
+    }
+}
@@ -97,6 +97,9 @@ pub struct Task {
     delay: TimeMs,
     start: TimeMs,
     time_src: TimeSourceTypes,
+    /// the time of the last forced check
+    #[serde(skip)]
+    forced: Option<TimeMs>,
 }
 
 impl Task {
@@ -107,6 +110,7 @@ impl Task {
             delay,
             start: time_src.get_time_ms(),
             time_src,
+            forced: None,
         }
     }
 
@@ -190,6 +194,20 @@ impl Task {
         self.start + self.delay
     }
 
+    /// the time of the last check.
+    /// This is the time the task was last started
+    /// unless a forced check happened since
+    pub fn last_time(&self) -> TimeMs {
+        self.forced
+            .map_or(self.start, |forced| forced.max(self.start))
+    }
+
+    /// takes the current time as the time of a forced check.
+    /// The timer is not restarted
+    pub fn force(&mut self) {
+        self.forced = Some(self.time_src.get_time_ms());
+    }
+
     pub fn is_due(&mut self) -> bool {
         if !self.done && self.next_time() < self.time_src.get_time_ms() {
             self.done = !self.repeat; // if no repeate set to done
@@ -0,0 +1,348 @@
+use super::error::Error;
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+
+/// The message format notifiers use when no template is configured
+pub const DEFAULT_TEMPLATE: &str =
+    "[{{ severity }}] {{ name }}/{{ trigger.name }}: {{ trigger.description }} ({{ transition }})";
+
+/// The name of the machine minutecat runs on
+pub fn hostname() -> String {
+    if let Ok(host) = env::var("HOSTNAME") {
+        return host;
+    }
+    match fs::read_to_string("/etc/hostname") {
+        Ok(host) if !host.trim().is_empty() => host.trim().into(),
+        _ => "localhost".into(),
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum Filter {
+    Upper,
+    Lower,
+    Trim,
+    /// keeps n characters and appends ... if the value was longer
+    Truncate(usize),
+    /// escapes the value for use inside a JSON string
+    Json,
+}
+
+impl Filter {
+    fn parse(s: &str) -> Result<Self, Error> {
+        let s = s.trim();
+        let (name, arg) = match s.find('(') {
+            Some(start) if s.ends_with(')') => {
+                (&s[..start], Some(s[start + 1..s.len() - 1].trim()))
+            }
+            _ => (s, None),
+        };
+        match (name.trim(), arg) {
+            ("upper", None) => Ok(Self::Upper),
+            ("lower", None) => Ok(Self::Lower),
+            ("trim", None) => Ok(Self::Trim),
+            ("json", None) => Ok(Self::Json),
+            ("truncate", Some(n)) => Ok(Self::Truncate(n.parse().map_err(|_| {
+                Error::TemplateError(format!("truncate expects a length, got {}", n))
+            })?)),
+            _ => Err(Error::TemplateError(format!("unknown filter {}", s))),
+        }
+    }
+
+    fn apply(&self, value: String) -> String {
+        match self {
+            Self::Upper => value.to_uppercase(),
+            Self::Lower => value.to_lowercase(),
+            Self::Trim => value.trim().into(),
+            Self::Truncate(n) => {
+                if value.chars().count() > *n {
+                    format!("{}...", value.chars().take(*n).collect::<String>())
+                } else {
+                    value
+                }
+            }
+            Self::Json => {
+                let quoted = serde_json::Value::String(value).to_string();
+                quoted[1..quoted.len() - 1].into()
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum Condition {
+    /// the field exists and is not empty
+    Present(String),
+    Not(Box<Condition>),
+    Eq(String, String),
+    Ne(String, String),
+}
+
+impl Condition {
+    fn parse(s: &str) -> Result<Self, Error> {
+        let s = s.trim();
+        if let Some(rest) = s.strip_prefix("not ") {
+            return Ok(Self::Not(Box::new(Self::parse(rest)?)));
+        }
+        for (op, eq) in [("==", true), ("!=", false)] {
+            if let Some(pos) = s.find(op) {
+                let field = s[..pos].trim().to_string();
+                let value = Self::literal(&s[pos + op.len()..])?;
+                return Ok(if eq {
+                    Self::Eq(field, value)
+                } else {
+                    Self::Ne(field, value)
+                });
+            }
+        }
+        if s.is_empty() {
+            return Err(Error::TemplateError("if without condition".into()));
+        }
+        Ok(Self::Present(s.into()))
+    }
+
+    fn literal(s: &str) -> Result<String, Error> {
+        let s = s.trim();
+        if s.len() >= 2 && s.starts_with('"') && s.ends_with('"') {
+            Ok(s[1..s.len() - 1].into())
+        } else {
+            Err(Error::TemplateError(format!(
+                "expected a quoted value, got {}",
+                s
+            )))
+        }
+    }
+
+    fn eval(&self, fields: &HashMap<String, String>) -> bool {
+        let get = |field: &str| fields.get(field).map_or("", |v| v.as_str());
+        match self {
+            Self::Present(field) => !get(field).is_empty(),
+            Self::Not(cond) => !cond.eval(fields),
+            Self::Eq(field, value) => get(field) == value,
+            Self::Ne(field, value) => get(field) != value,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum Node {
+    Text(String),
+    Field(String, Vec<Filter>),
+    If(Condition, Vec<Node>, Vec<Node>),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+enum Token {
+    Text(String),
+    /// {{ ... }}
+    Expr(String),
+    /// {% ... %}
+    Tag(String),
+}
+
+/// A notification template.
+///
+/// Placeholders are written as {{ field }} where field is one of the
+/// event's fields e.g. name, trigger.name, trigger.description, severity,
+/// transition, slice, next_time, host or captures.<name>.
+/// Unknown fields are empty.
+/// Filters are applied from left to right: {{ slice | trim | truncate(80) }}.
+/// Available filters are upper, lower, trim, truncate(n) and json.
+///
+/// Conditionals are written as
+/// {% if captures.user %} ... {% else %} ... {% endif %}
+/// and support not, == "value" and != "value".
+/// A backslash keeps a literal {{ or {% e.g. \{{ is rendered as {{.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+impl Template {
+    pub fn parse(source: &str) -> Result<Self, Error> {
+        let mut tokens = Self::tokenize(source)?.into_iter();
+        let (nodes, end) = Self::parse_nodes(&mut tokens)?;
+        match end {
+            Some(tag) => Err(Error::TemplateError(format!("unexpected {}", tag))),
+            _ => Ok(Self { nodes }),
+        }
+    }
+
+    fn tokenize(source: &str) -> Result<Vec<Token>, Error> {
+        let mut tokens = vec![];
+        let mut rest = source;
+        loop {
+            let start = match (rest.find("{{"), rest.find("{%")) {
+                (Some(a), Some(b)) => a.min(b),
+                (Some(a), None) | (None, Some(a)) => a,
+                _ => break,
+            };
+            // \{{ and \{% are written as they are
+            if rest[..start].ends_with('\\') {
+                tokens.push(Token::Text(rest[..start - 1].into()));
+                tokens.push(Token::Text(rest[start..start + 2].into()));
+                rest = &rest[start + 2..];
+                continue;
+            }
+            if start > 0 {
+                tokens.push(Token::Text(rest[..start].into()));
+            }
+            let is_expr = rest[start..].starts_with("{{");
+            let close = if is_expr { "}}" } else { "%}" };
+            let inner = &rest[start + 2..];
+            let end = inner
+                .find(close)
+                .ok_or_else(|| Error::TemplateError(format!("missing {}", close)))?;
+            let content = inner[..end].trim().to_string();
+            tokens.push(if is_expr {
+                Token::Expr(content)
+            } else {
+                Token::Tag(content)
+            });
+            rest = &inner[end + 2..];
+        }
+        if !rest.is_empty() {
+            tokens.push(Token::Text(rest.into()));
+        }
+        Ok(tokens)
+    }
+
+    /// parses nodes until the end or until an else or endif tag
+    /// returns the nodes and the tag that ended them
+    fn parse_nodes(
+        tokens: &mut impl Iterator<Item = Token>,
+    ) -> Result<(Vec<Node>, Option<String>), Error> {
+        let mut nodes = vec![];
+        while let Some(token) = tokens.next() {
+            match token {
+                Token::Text(text) => nodes.push(Node::Text(text)),
+                Token::Expr(expr) => {
+                    let mut parts = expr.split('|');
+                    let field = parts.next().unwrap_or("").trim().to_string();
+                    if field.is_empty() {
+                        return Err(Error::TemplateError("empty placeholder".into()));
+                    }
+                    let filters = parts.map(Filter::parse).collect::<Result<_, _>>()?;
+                    nodes.push(Node::Field(field, filters));
+                }
+                Token::Tag(tag) if tag == "else" || tag == "endif" => {
+                    return Ok((nodes, Some(tag)));
+                }
+                Token::Tag(tag) => match tag.strip_prefix("if ") {
+                    Some(cond) => {
+                        let cond = Condition::parse(cond)?;
+                        let (then, end) = Self::parse_nodes(tokens)?;
+                        let otherwise = match end.as_deref() {
+                            Some("endif") => vec![],
+                            Some("else") => match Self::parse_nodes(tokens)? {
+                                (otherwise, Some(end)) if end == "endif" => otherwise,
+                                _ => return Err(Error::TemplateError("missing endif".into())),
+                            },
+                            _ => return Err(Error::TemplateError("missing endif".into())),
+                        };
+                        nodes.push(Node::If(cond, then, otherwise));
+                    }
+                    _ => return Err(Error::TemplateError(format!("unknown tag {}", tag))),
+                },
+            }
+        }
+        Ok((nodes, None))
+    }
+
+    pub fn render(&self, fields: &HashMap<String, String>) -> String {
+        let mut out = String::new();
+        Self::render_nodes(&self.nodes, fields, &mut out);
+        out
+    }
+
+    fn render_nodes(nodes: &[Node], fields: &HashMap<String, String>, out: &mut String) {
+        for node in nodes {
+            match node {
+                Node::Text(text) => out.push_str(text),
+                Node::Field(field, filters) => {
+                    let value = fields.get(field).cloned().unwrap_or_default();
+                    out.push_str(&filters.iter().fold(value, |v, f| f.apply(v)));
+                }
+                Node::If(cond, then, otherwise) => {
+                    if cond.eval(fields) {
+                        Self::render_nodes(then, fields, out);
+                    } else {
+                        Self::render_nodes(otherwise, fields, out);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields() -> HashMap<String, String> {
+        let mut fields = HashMap::new();
+        fields.insert("name".into(), "prod-api".into());
+        fields.insert("severity".into(), "Error".into());
+        fields.insert("slice".into(), "  login \"failed\"\n".into());
+        fields.insert("captures.user".into(), "bob".into());
+        fields
+    }
+
+    #[test]
+    fn it_should_render_fields() {
+        let t = Template::parse("{{ name }}: {{severity}} {{ missing }}!").unwrap();
+        assert_eq!(t.render(&fields()), "prod-api: Error !");
+    }
+
+    #[test]
+    fn it_should_apply_filters() {
+        let t = Template::parse(
+            "{{ name | upper }} {{ slice | trim | truncate(5) }} {{ slice | json }} {{ name | truncate(20) }}",
+        )
+        .unwrap();
+        assert_eq!(
+            t.render(&fields()),
+            "PROD-API login...   login \\\"failed\\\"\\n prod-api"
+        );
+    }
+
+    #[test]
+    fn it_should_escape_braces() {
+        let t = Template::parse(r"\{{ name }} {{ name }} \{% if %}").unwrap();
+        assert_eq!(t.render(&fields()), "{{ name }} prod-api {% if %}");
+    }
+
+    #[test]
+    fn it_should_render_conditionals() {
+        let t = Template::parse(
+            "{% if captures.user %}user {{ captures.user }}{% else %}nobody{% endif %}\
+             {% if not captures.host %}, no host{% endif %}\
+             {% if severity == \"Error\" %}, error{% if severity != \"Error\" %}never{% endif %}{% endif %}",
+        )
+        .unwrap();
+        assert_eq!(t.render(&fields()), "user bob, no host, error");
+        assert_eq!(t.render(&HashMap::new()), "nobody, no host");
+    }
+
+    #[test]
+    fn it_should_report_syntax_errors() {
+        for source in [
+            "{{ name",
+            "{% if name %}",
+            "{% if name %}{% else %}",
+            "{% endif %}",
+            "{% for x %}",
+            "{{ name | bold }}",
+            "{{ name | truncate(x) }}",
+            "{{ }}",
+            "{% if severity == Error %}{% endif %}",
+        ] {
+            assert!(
+                matches!(Template::parse(source), Err(Error::TemplateError(_))),
+                "{}",
+                source
+            );
+        }
+    }
+}
@@ -0,0 +1,361 @@
+use crate::error::Error;
+use crate::extra::ExtraData;
+use crate::regex::Regex;
+use crate::serde::{Deserialize, Serialize};
+use crate::task::{Task, TimeMs};
+use crate::typetag;
+use crate::{Trigger, TriggerContext, TriggerType};
+
+/// What an anomaly trigger measures
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize, Clone)]
+pub enum AnomalyMetric {
+    /// new lines per interval
+    Lines,
+    /// matches of the regex in new lines per interval
+    Matches(String),
+}
+
+/// The learned baseline of an anomaly trigger
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, Clone)]
+struct AnomalyState {
+    /// exponentially weighted mean of the rate
+    mean: f64,
+    /// exponentially weighted variance of the rate
+    variance: f64,
+    /// number of rates the baseline learned from
+    samples: usize,
+    /// the size of the source at the previous check
+    offset: Option<u64>,
+    /// the number of lines of the previous check
+    lines: usize,
+    /// the last line of the previous check
+    last_line: Option<String>,
+    /// the time of the previous check
+    last_time: Option<TimeMs>,
+    fired: bool,
+}
+
+fn default_alpha() -> f64 {
+    0.3
+}
+
+fn default_warmup() -> usize {
+    5
+}
+
+fn default_min_stddev() -> f64 {
+    1.0
+}
+
+/// An anomaly trigger learns a rolling baseline (EWMA and standard deviation)
+/// of the rate of new lines or matches and fires when the current rate
+/// deviates from the mean by more than k standard deviations in either direction.
+///
+/// New lines are the lines the source grew by since the previous check,
+/// so a text that is cut to a line limit still counts every new line.
+/// Sources that do not know their size (e.g. http) have to return a growing text,
+/// if it does not continue the text of the previous check every line is new.
+/// The rate is normalized to the interval (e.g. 1m) using the
+/// time between checks. The trigger does not fire before
+/// it learned from warmup rates.
+/// The standard deviation is at least min_stddev, so a constant
+/// baseline does not fire on every small change.
+///
+/// The baseline is stored in the logfile's extra data.
+/// Without a context (check) the trigger never fires.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct AnomalyTrigger {
+    name: String,
+    description: String,
+    trigger_type: TriggerType,
+    metric: AnomalyMetric,
+    interval: String,
+    k: f64,
+
+    /// weight of the newest rate
+    #[serde(default = "default_alpha")]
+    alpha: f64,
+    #[serde(default = "default_warmup")]
+    warmup: usize,
+    #[serde(default = "default_min_stddev")]
+    min_stddev: f64,
+}
+
+impl AnomalyTrigger {
+    pub fn new(
+        name: &str,
+        description: &str,
+        trigger_type: TriggerType,
+        metric: AnomalyMetric,
+        interval: &str,
+        k: f64,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            trigger_type,
+            metric,
+            interval: interval.into(),
+            k,
+            alpha: default_alpha(),
+            warmup: default_warmup(),
+            min_stddev: default_min_stddev(),
+        }
+    }
+
+    pub fn with_alpha(mut self, alpha: f64) -> Self {
+        self.alpha = alpha;
+        self
+    }
+
+    pub fn with_warmup(mut self, warmup: usize) -> Self {
+        self.warmup = warmup;
+        self
+    }
+
+    pub fn with_min_stddev(mut self, min_stddev: f64) -> Self {
+        self.min_stddev = min_stddev;
+        self
+    }
+
+    fn state_key(&self) -> String {
+        format!("anomaly.{}", self.name)
+    }
+
+    /// returns the lines that were added since the previous check
+    fn new_lines<'a>(text: &'a str, state: &AnomalyState, size: Option<u64>) -> Vec<&'a str> {
+        match (size, state.offset) {
+            // the source grew at the end of the text
+            (Some(size), Some(offset)) if size >= offset => {
+                let added = ((size - offset) as usize).min(text.len());
+                let mut start = text.len() - added;
+                while !text.is_char_boundary(start) {
+                    start += 1;
+                }
+                return text[start..].lines().collect();
+            }
+            // the source was truncated e.g. by a log rotation
+            (Some(_), Some(_)) => return text.lines().collect(),
+            _ => {}
+        }
+
+        let lines: Vec<&str> = text.lines().collect();
+        let continues = state.lines > 0
+            && state.lines <= lines.len()
+            && state.last_line.as_deref() == Some(lines[state.lines - 1]);
+        if continues {
+            lines[state.lines..].to_vec()
+        } else {
+            lines
+        }
+    }
+
+    /// counts the lines or matches in the new lines
+    fn count(&self, lines: &[&str]) -> Result<usize, Error> {
+        match &self.metric {
+            AnomalyMetric::Lines => Ok(lines.len()),
+            AnomalyMetric::Matches(re) => {
+                let re = Regex::new(re)?;
+                Ok(lines.iter().map(|l| re.find_iter(l).count()).sum())
+            }
+        }
+    }
+}
+
+#[typetag::serde]
+impl Trigger for AnomalyTrigger {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// an anomaly needs a baseline, without state it never fires
+    fn check(&self, _text: &str) -> Result<bool, Error> {
+        Ok(false)
+    }
+
+    fn check_ctx(&self, text: &str, ctx: &mut TriggerContext) -> Result<bool, Error> {
+        let interval = Task::scan(&self.interval)?;
+        let mut state = ctx
+            .extra
+            .get::<AnomalyState>(&self.state_key(), ExtraData::deserialize)
+            .unwrap_or_default();
+
+        let count = self.count(&Self::new_lines(text, &state, ctx.size))?;
+
+        let elapsed = state.last_time.map(|t| ctx.time.saturating_sub(t));
+        match elapsed {
+            // the first check only sets the starting point
+            None => {}
+            // no time passed, keep the previous result
+            Some(0) => return Ok(state.fired),
+            Some(elapsed) => {
+                let rate = count as f64 * interval as f64 / elapsed as f64;
+
+                state.fired = if state.samples >= self.warmup {
+                    let deviation = (rate - state.mean).abs();
+                    deviation > self.k * state.variance.sqrt().max(self.min_stddev)
+                } else {
+                    false
+                };
+
+                if state.samples == 0 {
+                    state.mean = rate;
+                    state.variance = 0.0;
+                } else {
+                    let diff = rate - state.mean;
+                    let incr = self.alpha * diff;
+                    state.mean += incr;
+                    state.variance = (1.0 - self.alpha) * (state.variance + diff * incr);
+                }
+                state.samples += 1;
+            }
+        }
+
+        state.offset = ctx.size;
+        state.lines = text.lines().count();
+        state.last_line = text.lines().last().map(|l| l.to_string());
+        state.last_time = Some(ctx.time);
+
+        ctx.extra
+            .put(&self.state_key(), &state, ExtraData::serialize)?;
+        Ok(state.fired)
+    }
+
+    /// returns the last match for the matches metric
+    /// and an empty slice for the lines metric
+    fn slice<'a>(&self, text: &'a str) -> Result<&'a str, Error> {
+        match &self.metric {
+            AnomalyMetric::Matches(re) => match Regex::new(re)?.find_iter(text).last() {
+                Some(m) => Ok(m.as_str()),
+                _ => Ok(&text[0..0]),
+            },
+            AnomalyMetric::Lines => Ok(&text[0..0]),
+        }
+    }
+
+    fn get_type(&self) -> TriggerType {
+        self.trigger_type
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// appends lines to a growing log and checks once a minute
+    fn run(trigger: &AnomalyTrigger, lines_per_check: Vec<usize>) -> Vec<bool> {
+        let mut extra = ExtraData::new();
+        let mut text = String::new();
+        let mut line = 0;
+        let mut result = vec![];
+        for (i, count) in lines_per_check.into_iter().enumerate() {
+            for _ in 0..count {
+                line += 1;
+                let level = if line % 2 == 0 { "ERROR" } else { "INFO" };
+                text.push_str(&format!("{} line {}\n", level, line));
+            }
+            let mut ctx = TriggerContext::new(i as TimeMs * 60000, &mut extra);
+            result.push(trigger.check_ctx(&text, &mut ctx).unwrap());
+        }
+        result
+    }
+
+    fn trigger(metric: AnomalyMetric) -> AnomalyTrigger {
+        AnomalyTrigger::new("rate", "desc", TriggerType::Warning, metric, "1m", 3.0).with_warmup(4)
+    }
+
+    #[test]
+    fn it_should_fire_on_spikes() {
+        let fired = run(
+            &trigger(AnomalyMetric::Lines),
+            vec![10, 10, 12, 9, 11, 10, 100, 10],
+        );
+        assert_eq!(
+            fired,
+            vec![false, false, false, false, false, false, true, false]
+        );
+    }
+
+    #[test]
+    fn it_should_fire_on_drops() {
+        let fired = run(
+            &trigger(AnomalyMetric::Lines),
+            vec![100, 100, 104, 98, 102, 100, 0],
+        );
+        assert_eq!(fired, vec![false, false, false, false, false, false, true]);
+    }
+
+    #[test]
+    fn it_should_count_matches() {
+        let fired = run(
+            &trigger(AnomalyMetric::Matches("ERROR".into())),
+            vec![10, 10, 12, 8, 10, 10, 60],
+        );
+        assert_eq!(fired, vec![false, false, false, false, false, false, true]);
+    }
+
+    #[test]
+    fn it_should_persist_the_baseline() {
+        let t = trigger(AnomalyMetric::Lines);
+        let mut extra = ExtraData::new();
+        let text = "a\nb\n";
+        let mut ctx = TriggerContext::new(0, &mut extra);
+        t.check_ctx(text, &mut ctx).unwrap();
+        ctx.time = 60000;
+        t.check_ctx("a\nb\nc\n", &mut ctx).unwrap();
+
+        let state = extra
+            .get::<AnomalyState>("anomaly.rate", ExtraData::deserialize)
+            .unwrap();
+        assert_eq!(state.samples, 1);
+        assert_eq!(state.mean, 1.0);
+        assert_eq!(state.last_line, Some("c".into()));
+        assert!(!t.check(text).unwrap());
+    }
+
+    /// the mean rate after two checks a minute apart
+    fn learned(first: &str, second: &str, sizes: [Option<u64>; 2]) -> f64 {
+        let t = trigger(AnomalyMetric::Lines);
+        let mut extra = ExtraData::new();
+        for (i, text) in [first, second].iter().enumerate() {
+            let mut ctx = TriggerContext::new(i as TimeMs * 60000, &mut extra).with_size(sizes[i]);
+            t.check_ctx(text, &mut ctx).unwrap();
+        }
+        extra
+            .get::<AnomalyState>("anomaly.rate", ExtraData::deserialize)
+            .unwrap()
+            .mean
+    }
+
+    #[test]
+    fn it_should_count_repeated_lines() {
+        // a growing text
+        assert_eq!(
+            learned("ok\nok\nok\n", "ok\nok\nok\nok\nok\n", [None; 2]),
+            2.0
+        );
+        // a text cut to a line limit of 3
+        assert_eq!(
+            learned("ok\nok\nok\n", "ok\nok\nok\n", [Some(9), Some(15)]),
+            2.0
+        );
+        // a rotated source
+        assert_eq!(learned("ok\nok\nok\n", "ok\n", [Some(9), Some(3)]), 1.0);
+        // a text that does not continue the previous one
+        assert_eq!(learned("a\nb\n", "c\n", [None; 2]), 1.0);
+    }
+
+    #[test]
+    fn it_should_use_the_minimum_standard_deviation() {
+        let t = trigger(AnomalyMetric::Lines);
+        assert_eq!(
+            run(&t, vec![10, 10, 10, 10, 10, 10, 12, 10, 20]),
+            vec![false, false, false, false, false, false, false, false, true]
+        );
+        assert!(run(&t.with_min_stddev(0.0), vec![10, 10, 10, 10, 10, 10, 12])[6]);
+    }
+}
@@ -0,0 +1,513 @@
+use crate::error::Error;
+use crate::extra::ExtraData;
+use crate::serde::{Deserialize, Serialize};
+use crate::task::TimeMs;
+use crate::typetag;
+use crate::AnomalyTrigger;
+use crate::CompositeTrigger;
+use crate::JsonTrigger;
+use crate::LogfmtTrigger;
+use crate::NumericTrigger;
+use crate::RegexTrigger;
+use crate::ScriptTrigger;
+use crate::SequenceTrigger;
+use crate::WasmTrigger;
+use std::collections::HashMap;
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub enum TriggerTypes {
+    Regex(RegexTrigger),
+    Composite(CompositeTrigger),
+    Numeric(NumericTrigger),
+    Json(JsonTrigger),
+    Logfmt(LogfmtTrigger),
+    Sequence(SequenceTrigger),
+    Anomaly(AnomalyTrigger),
+    Script(ScriptTrigger),
+    Wasm(WasmTrigger),
+    Generic(Box<dyn Trigger>),
+}
+
+#[typetag::serde]
+impl Trigger for TriggerTypes {
+    fn name(&self) -> &str {
+        match self {
+            Self::Regex(t) => t.name(),
+            Self::Composite(t) => t.name(),
+            Self::Numeric(t) => t.name(),
+            Self::Json(t) => t.name(),
+            Self::Logfmt(t) => t.name(),
+            Self::Sequence(t) => t.name(),
+            Self::Anomaly(t) => t.name(),
+            Self::Script(t) => t.name(),
+            Self::Wasm(t) => t.name(),
+            Self::Generic(t) => t.name(),
+        }
+    }
+
+    fn description(&self) -> &str {
+        match self {
+            Self::Regex(t) => t.description(),
+            Self::Composite(t) => t.description(),
+            Self::Numeric(t) => t.description(),
+            Self::Json(t) => t.description(),
+            Self::Logfmt(t) => t.description(),
+            Self::Sequence(t) => t.description(),
+            Self::Anomaly(t) => t.description(),
+            Self::Script(t) => t.description(),
+            Self::Wasm(t) => t.description(),
+            Self::Generic(t) => t.description(),
+        }
+    }
+
+    fn check(&self, text: &str) -> Result<bool, Error> {
+        match self {
+            Self::Regex(t) => t.check(text),
+            Self::Composite(t) => t.check(text),
+            Self::Numeric(t) => t.check(text),
+            Self::Json(t) => t.check(text),
+            Self::Logfmt(t) => t.check(text),
+            Self::Sequence(t) => t.check(text),
+            Self::Anomaly(t) => t.check(text),
+            Self::Script(t) => t.check(text),
+            Self::Wasm(t) => t.check(text),
+            Self::Generic(t) => t.check(text),
+        }
+    }
+
+    /// returns the slice that fired the trigger
+    fn slice<'a>(&self, text: &'a str) -> Result<&'a str, Error> {
+        match self {
+            Self::Regex(t) => t.slice(text),
+            Self::Composite(t) => t.slice(text),
+            Self::Numeric(t) => t.slice(text),
+            Self::Json(t) => t.slice(text),
+            Self::Logfmt(t) => t.slice(text),
+            Self::Sequence(t) => t.slice(text),
+            Self::Anomaly(t) => t.slice(text),
+            Self::Script(t) => t.slice(text),
+            Self::Wasm(t) => t.slice(text),
+            Self::Generic(t) => t.slice(text),
+        }
+    }
+
+    fn get_type(&self) -> TriggerType {
+        match self {
+            Self::Regex(t) => t.get_type(),
+            Self::Composite(t) => t.get_type(),
+            Self::Numeric(t) => t.get_type(),
+            Self::Json(t) => t.get_type(),
+            Self::Logfmt(t) => t.get_type(),
+            Self::Sequence(t) => t.get_type(),
+            Self::Anomaly(t) => t.get_type(),
+            Self::Script(t) => t.get_type(),
+            Self::Wasm(t) => t.get_type(),
+            Self::Generic(t) => t.get_type(),
+        }
+    }
+
+    fn get_type_for(&self, text: &str) -> Result<TriggerType, Error> {
+        match self {
+            Self::Regex(t) => t.get_type_for(text),
+            Self::Composite(t) => t.get_type_for(text),
+            Self::Numeric(t) => t.get_type_for(text),
+            Self::Json(t) => t.get_type_for(text),
+            Self::Logfmt(t) => t.get_type_for(text),
+            Self::Sequence(t) => t.get_type_for(text),
+            Self::Anomaly(t) => t.get_type_for(text),
+            Self::Script(t) => t.get_type_for(text),
+            Self::Wasm(t) => t.get_type_for(text),
+            Self::Generic(t) => t.get_type_for(text),
+        }
+    }
+
+    fn check_ctx(&self, text: &str, ctx: &mut TriggerContext) -> Result<bool, Error> {
+        match self {
+            Self::Regex(t) => t.check_ctx(text, ctx),
+            Self::Composite(t) => t.check_ctx(text, ctx),
+            Self::Numeric(t) => t.check_ctx(text, ctx),
+            Self::Json(t) => t.check_ctx(text, ctx),
+            Self::Logfmt(t) => t.check_ctx(text, ctx),
+            Self::Sequence(t) => t.check_ctx(text, ctx),
+            Self::Anomaly(t) => t.check_ctx(text, ctx),
+            Self::Script(t) => t.check_ctx(text, ctx),
+            Self::Wasm(t) => t.check_ctx(text, ctx),
+            Self::Generic(t) => t.check_ctx(text, ctx),
+        }
+    }
+
+    fn slice_ctx<'a>(&self, text: &'a str, ctx: &TriggerContext) -> Result<&'a str, Error> {
+        match self {
+            Self::Regex(t) => t.slice_ctx(text, ctx),
+            Self::Composite(t) => t.slice_ctx(text, ctx),
+            Self::Numeric(t) => t.slice_ctx(text, ctx),
+            Self::Json(t) => t.slice_ctx(text, ctx),
+            Self::Logfmt(t) => t.slice_ctx(text, ctx),
+            Self::Sequence(t) => t.slice_ctx(text, ctx),
+            Self::Anomaly(t) => t.slice_ctx(text, ctx),
+            Self::Script(t) => t.slice_ctx(text, ctx),
+            Self::Wasm(t) => t.slice_ctx(text, ctx),
+            Self::Generic(t) => t.slice_ctx(text, ctx),
+        }
+    }
+
+    fn get_type_for_ctx(&self, text: &str, ctx: &TriggerContext) -> Result<TriggerType, Error> {
+        match self {
+            Self::Regex(t) => t.get_type_for_ctx(text, ctx),
+            Self::Composite(t) => t.get_type_for_ctx(text, ctx),
+            Self::Numeric(t) => t.get_type_for_ctx(text, ctx),
+            Self::Json(t) => t.get_type_for_ctx(text, ctx),
+            Self::Logfmt(t) => t.get_type_for_ctx(text, ctx),
+            Self::Sequence(t) => t.get_type_for_ctx(text, ctx),
+            Self::Anomaly(t) => t.get_type_for_ctx(text, ctx),
+            Self::Script(t) => t.get_type_for_ctx(text, ctx),
+            Self::Wasm(t) => t.get_type_for_ctx(text, ctx),
+            Self::Generic(t) => t.get_type_for_ctx(text, ctx),
+        }
+    }
+
+    fn matches_ctx(&self, text: &str, ctx: &TriggerContext) -> Result<Vec<TriggerMatch>, Error> {
+        match self {
+            Self::Regex(t) => t.matches_ctx(text, ctx),
+            Self::Composite(t) => t.matches_ctx(text, ctx),
+            Self::Numeric(t) => t.matches_ctx(text, ctx),
+            Self::Json(t) => t.matches_ctx(text, ctx),
+            Self::Logfmt(t) => t.matches_ctx(text, ctx),
+            Self::Sequence(t) => t.matches_ctx(text, ctx),
+            Self::Anomaly(t) => t.matches_ctx(text, ctx),
+            Self::Script(t) => t.matches_ctx(text, ctx),
+            Self::Wasm(t) => t.matches_ctx(text, ctx),
+            Self::Generic(t) => t.matches_ctx(text, ctx),
+        }
+    }
+
+    fn matches(&self, text: &str) -> Result<Vec<TriggerMatch>, Error> {
+        match self {
+            Self::Regex(t) => t.matches(text),
+            Self::Composite(t) => t.matches(text),
+            Self::Numeric(t) => t.matches(text),
+            Self::Json(t) => t.matches(text),
+            Self::Logfmt(t) => t.matches(text),
+            Self::Sequence(t) => t.matches(text),
+            Self::Anomaly(t) => t.matches(text),
+            Self::Script(t) => t.matches(text),
+            Self::Wasm(t) => t.matches(text),
+            Self::Generic(t) => t.matches(text),
+        }
+    }
+
+    fn nested(&self) -> &[TriggerTypes] {
+        match self {
+            Self::Regex(t) => t.nested(),
+            Self::Composite(t) => t.nested(),
+            Self::Numeric(t) => t.nested(),
+            Self::Json(t) => t.nested(),
+            Self::Logfmt(t) => t.nested(),
+            Self::Sequence(t) => t.nested(),
+            Self::Anomaly(t) => t.nested(),
+            Self::Script(t) => t.nested(),
+            Self::Wasm(t) => t.nested(),
+            Self::Generic(t) => t.nested(),
+        }
+    }
+}
+
+pub trait TriggerClone {
+    fn box_clone(&self) -> Box<dyn Trigger>;
+}
+
+impl<T> TriggerClone for T
+where
+    T: 'static + Trigger + Clone,
+{
+    fn box_clone(&self) -> Box<dyn Trigger> {
+        Box::new(self.clone())
+    }
+}
+
+/// TriggerType describes the severity of a trigger.
+/// Severities are ordered from NoEvent to Fatal,
+/// the status of a log is the highest severity
+/// among its active triggers
+#[derive(
+    Debug, Default, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize, Copy, Clone,
+)]
+pub enum TriggerType {
+    #[default]
+    #[serde(alias = "noevent")]
+    NoEvent,
+    #[serde(alias = "debug")]
+    Debug,
+    #[serde(alias = "success")]
+    Success,
+    #[serde(alias = "info")]
+    Info,
+    #[serde(alias = "notice")]
+    Notice,
+    #[serde(alias = "warning")]
+    Warning,
+    #[serde(alias = "error")]
+    Error,
+    #[serde(alias = "critical")]
+    Critical,
+    #[serde(alias = "fatal")]
+    Fatal,
+}
+
+impl fmt::Display for TriggerType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl FromStr for TriggerType {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "debug" => Ok(Self::Debug),
+            "success" => Ok(Self::Success),
+            "info" => Ok(Self::Info),
+            "notice" => Ok(Self::Notice),
+            "warning" | "warn" => Ok(Self::Warning),
+            "error" => Ok(Self::Error),
+            "critical" => Ok(Self::Critical),
+            "fatal" => Ok(Self::Fatal),
+            _ => Err(Error::FromStringError),
+        }
+    }
+}
+
+/// A trigger is anything that can
+/// cause a logfile notification to appear
+/// e.g. regex match, time since last change
+#[typetag::serde(tag = "type")]
+pub trait Trigger: TriggerClone + Send {
+    fn name(&self) -> &str;
+    fn description(&self) -> &str;
+    fn check(&self, text: &str) -> Result<bool, Error>;
+
+    /// returns the slice that fired the trigger
+    fn slice<'a>(&self, text: &'a str) -> Result<&'a str, Error>;
+    fn get_type(&self) -> TriggerType;
+
+    /// returns the trigger type for a text that fired the trigger.
+    /// Triggers that can report different types depending on
+    /// the input should overwrite this
+    fn get_type_for(&self, _text: &str) -> Result<TriggerType, Error> {
+        Ok(self.get_type())
+    }
+
+    /// checks the trigger with access to the state of the logfile.
+    /// Triggers that need to keep state between checks
+    /// should overwrite this
+    fn check_ctx(&self, text: &str, _ctx: &mut TriggerContext) -> Result<bool, Error> {
+        self.check(text)
+    }
+
+    /// returns every match in a text that fired the trigger.
+    /// By default the slice is the only match
+    fn matches(&self, text: &str) -> Result<Vec<TriggerMatch>, Error> {
+        Ok(TriggerMatch::from_slice(text, self.slice(text)?)
+            .into_iter()
+            .collect())
+    }
+
+    /// returns the slice after a check with the context.
+    /// Triggers that keep results of the check in the context
+    /// should overwrite this and the other _ctx methods
+    fn slice_ctx<'a>(&self, text: &'a str, _ctx: &TriggerContext) -> Result<&'a str, Error> {
+        self.slice(text)
+    }
+
+    fn get_type_for_ctx(&self, text: &str, _ctx: &TriggerContext) -> Result<TriggerType, Error> {
+        self.get_type_for(text)
+    }
+
+    fn matches_ctx(&self, text: &str, _ctx: &TriggerContext) -> Result<Vec<TriggerMatch>, Error> {
+        self.matches(text)
+    }
+
+    /// the triggers nested in this trigger e.g. of a composite trigger
+    fn nested(&self) -> &[TriggerTypes] {
+        &[]
+    }
+}
+
+/// A single match of a trigger in a text
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone)]
+pub struct TriggerMatch {
+    /// line number of the start of the match starting at 1
+    pub line: usize,
+    /// byte offset of the start of the match in the text
+    pub start: usize,
+    /// byte offset of the end of the match in the text
+    pub end: usize,
+    /// the matched text
+    pub text: String,
+    /// capture groups of the match, group 0 is not included
+    #[serde(default)]
+    pub captures: Vec<Option<String>>,
+    /// named capture groups of the match e.g. (?P<user>\w+)
+    #[serde(default)]
+    pub named: HashMap<String, String>,
+}
+
+impl TriggerMatch {
+    pub fn new(text: &str, start: usize, end: usize, captures: Vec<Option<String>>) -> Self {
+        Self {
+            line: Self::line_of(text, start),
+            start,
+            end,
+            text: text[start..end].into(),
+            captures,
+            named: HashMap::new(),
+        }
+    }
+
+    pub fn with_named(mut self, named: HashMap<String, String>) -> Self {
+        self.named = named;
+        self
+    }
+
+    /// creates a match from a slice of text.
+    /// Returns None for empty slices and slices
+    /// that are not part of the text
+    pub fn from_slice(text: &str, slice: &str) -> Option<Self> {
+        let base = text.as_ptr() as usize;
+        let ptr = slice.as_ptr() as usize;
+        if slice.is_empty() || ptr < base || ptr + slice.len() > base + text.len() {
+            return None;
+        }
+        let start = ptr - base;
+        Some(Self::new(text, start, start + slice.len(), vec![]))
+    }
+
+    /// returns the line number of a byte offset starting at 1
+    pub fn line_of(text: &str, offset: usize) -> usize {
+        text[..offset].matches('\n').count() + 1
+    }
+}
+
+/// The result of a single check of a trigger
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct CheckResult {
+    pub fired: bool,
+    /// the type reported for the text e.g. by a script
+    pub trigger_type: Option<TriggerType>,
+    /// the byte range of the slice in the text
+    pub slice: Option<Range<usize>>,
+}
+
+/// The context of a single check.
+/// It allows triggers to keep state between checks
+/// and to keep results for the slice and matches of the same check
+pub struct TriggerContext<'a> {
+    /// the time of the check
+    pub time: TimeMs,
+    /// the logfile's extra data, triggers may store their state here
+    pub extra: &'a mut ExtraData,
+    /// the size of the whole source at the end of the text
+    /// e.g. the file size, None if the source does not know it
+    pub size: Option<u64>,
+    /// results of this check by trigger name.
+    /// Trigger names are unique within a logfile including nested triggers
+    pub results: HashMap<String, CheckResult>,
+    /// the byte ranges of the records if the logfile groups
+    /// lines into records. Line based triggers treat every record as a line
+    pub records: Option<&'a [Range<usize>]>,
+}
+
+impl<'a> TriggerContext<'a> {
+    pub fn new(time: TimeMs, extra: &'a mut ExtraData) -> Self {
+        Self {
+            time,
+            extra,
+            size: None,
+            results: HashMap::new(),
+            records: None,
+        }
+    }
+
+    pub fn with_size(mut self, size: Option<u64>) -> Self {
+        self.size = size;
+        self
+    }
+
+    pub fn with_records(mut self, records: Option<&'a [Range<usize>]>) -> Self {
+        self.records = records;
+        self
+    }
+
+    /// the result of a trigger in this check
+    pub fn result(&self, name: &str) -> Option<&CheckResult> {
+        self.results.get(name)
+    }
+}
+
+impl Clone for Box<dyn Trigger> {
+    fn clone(&self) -> Box<dyn Trigger> {
+        self.box_clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_order_severities() {
+        assert!(TriggerType::NoEvent < TriggerType::Debug);
+        assert!(TriggerType::Debug < TriggerType::Success);
+        assert!(TriggerType::Success < TriggerType::Info);
+        assert!(TriggerType::Info < TriggerType::Notice);
+        assert!(TriggerType::Notice < TriggerType::Warning);
+        assert!(TriggerType::Warning < TriggerType::Error);
+        assert!(TriggerType::Error < TriggerType::Critical);
+        assert!(TriggerType::Critical < TriggerType::Fatal);
+        assert_eq!(
+            vec![TriggerType::Warning, TriggerType::Fatal, TriggerType::Info]
+                .into_iter()
+                .max(),
+            Some(TriggerType::Fatal)
+        );
+    }
+
+    #[test]
+    fn it_should_parse_severities() {
+        assert_eq!("error".parse::<TriggerType>().unwrap(), TriggerType::Error);
+        assert_eq!("Error".parse::<TriggerType>().unwrap(), TriggerType::Error);
+        assert_eq!(
+            "CRITICAL".parse::<TriggerType>().unwrap(),
+            TriggerType::Critical
+        );
+        assert_eq!("warn".parse::<TriggerType>().unwrap(), TriggerType::Warning);
+        assert_eq!(
+            "notice".parse::<TriggerType>().unwrap(),
+            TriggerType::Notice
+        );
+        assert!("noevent".parse::<TriggerType>().is_err());
+        assert!("bad".parse::<TriggerType>().is_err());
+    }
+
+    #[test]
+    fn it_should_read_existing_configs() {
+        let types: Vec<TriggerType> =
+            serde_yaml::from_str("[NoEvent, Success, Warning, Error, fatal, info]").unwrap();
+        assert_eq!(
+            types,
+            vec![
+                TriggerType::NoEvent,
+                TriggerType::Success,
+                TriggerType::Warning,
+                TriggerType::Error,
+                TriggerType::Fatal,
+                TriggerType::Info
+            ]
+        );
+        let ser = serde_yaml::to_string(&TriggerType::Critical).unwrap();
+        assert!(ser.ends_with("Critical\n") || ser.ends_with("Critical"));
+    }
+}
@@ -0,0 +1,391 @@
+use crate::error::Error;
+use crate::serde::{Deserialize, Serialize};
+use crate::typetag;
+use crate::{Trigger, TriggerContext, TriggerMatch, TriggerType, TriggerTypes};
+
+/// CompositeMode decides how many of
+/// the nested triggers have to fire
+/// for the composite trigger to fire
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize, Copy, Clone)]
+pub enum CompositeMode {
+    /// every nested trigger has to fire (AND)
+    All,
+    /// at least one nested trigger has to fire (OR)
+    Any,
+    /// no nested trigger may fire (NOT)
+    None,
+    /// at least n nested triggers have to fire
+    AtLeast(usize),
+}
+
+/// A composite trigger nests other triggers
+/// and combines their results based on the mode.
+/// An empty composite trigger never fires.
+///
+/// The reported slice is the slice of the first nested trigger
+/// (in config order) that fired and returned a non-empty slice.
+/// If no such trigger exists (e.g. in None mode) the slice is empty.
+/// After a check with a context, slices and matches use the nested triggers
+/// that fired in the check, so stateful nested triggers are not checked again.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CompositeTrigger {
+    name: String,
+    description: String,
+    trigger_type: TriggerType,
+    mode: CompositeMode,
+    triggers: Vec<TriggerTypes>,
+}
+
+impl CompositeTrigger {
+    pub fn new(
+        name: &str,
+        description: &str,
+        trigger_type: TriggerType,
+        mode: CompositeMode,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            trigger_type,
+            mode,
+            triggers: vec![],
+        }
+    }
+
+    pub fn push(&mut self, trigger: TriggerTypes) {
+        self.triggers.push(trigger);
+    }
+
+    pub fn len(&self) -> usize {
+        self.triggers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// the nested triggers that fired in the check of the context.
+    /// Triggers without a result are checked without a context
+    fn fired(&self, text: &str, ctx: Option<&TriggerContext>) -> Result<Vec<&TriggerTypes>, Error> {
+        let mut fired = vec![];
+        for trigger in &self.triggers[..] {
+            let result = match ctx.and_then(|ctx| ctx.result(trigger.name())) {
+                Some(result) => result.fired,
+                _ => trigger.check(text)?,
+            };
+            if result {
+                fired.push(trigger);
+            }
+        }
+        Ok(fired)
+    }
+
+    fn slice_in<'a>(&self, text: &'a str, ctx: Option<&TriggerContext>) -> Result<&'a str, Error> {
+        for trigger in self.fired(text, ctx)? {
+            let slice = match ctx {
+                Some(ctx) => trigger.slice_ctx(text, ctx)?,
+                _ => trigger.slice(text)?,
+            };
+            if !slice.is_empty() {
+                return Ok(slice);
+            }
+        }
+        Ok(&text[0..0])
+    }
+
+    /// the matches of all nested triggers that fired
+    /// ordered by their position in the text
+    fn matches_in(
+        &self,
+        text: &str,
+        ctx: Option<&TriggerContext>,
+    ) -> Result<Vec<TriggerMatch>, Error> {
+        let mut result: Vec<TriggerMatch> = vec![];
+        for trigger in self.fired(text, ctx)? {
+            let matches = match ctx {
+                Some(ctx) => trigger.matches_ctx(text, ctx)?,
+                _ => trigger.matches(text)?,
+            };
+            for m in matches {
+                if !result.contains(&m) {
+                    result.push(m);
+                }
+            }
+        }
+        result.sort_by_key(|m| (m.start, m.end));
+        Ok(result)
+    }
+
+    fn combine(&self, fired: usize) -> bool {
+        if self.triggers.is_empty() {
+            return false;
+        }
+
+        match self.mode {
+            CompositeMode::All => fired == self.triggers.len(),
+            CompositeMode::Any => fired > 0,
+            CompositeMode::None => fired == 0,
+            CompositeMode::AtLeast(n) => fired >= n,
+        }
+    }
+}
+
+#[typetag::serde]
+impl Trigger for CompositeTrigger {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn check(&self, text: &str) -> Result<bool, Error> {
+        let mut fired = 0;
+        for trigger in &self.triggers[..] {
+            if trigger.check(text)? {
+                fired += 1;
+            }
+        }
+        Ok(self.combine(fired))
+    }
+
+    /// checks every nested trigger and keeps
+    /// which of them fired in the context
+    fn check_ctx(&self, text: &str, ctx: &mut TriggerContext) -> Result<bool, Error> {
+        let mut fired = 0;
+        for trigger in &self.triggers[..] {
+            let result = trigger.check_ctx(text, ctx)?;
+            ctx.results.entry(trigger.name().into()).or_default().fired = result;
+            if result {
+                fired += 1;
+            }
+        }
+        Ok(self.combine(fired))
+    }
+
+    fn slice<'a>(&self, text: &'a str) -> Result<&'a str, Error> {
+        self.slice_in(text, None)
+    }
+
+    fn slice_ctx<'a>(&self, text: &'a str, ctx: &TriggerContext) -> Result<&'a str, Error> {
+        self.slice_in(text, Some(ctx))
+    }
+
+    fn matches(&self, text: &str) -> Result<Vec<TriggerMatch>, Error> {
+        self.matches_in(text, None)
+    }
+
+    fn matches_ctx(&self, text: &str, ctx: &TriggerContext) -> Result<Vec<TriggerMatch>, Error> {
+        self.matches_in(text, Some(ctx))
+    }
+
+    fn nested(&self) -> &[TriggerTypes] {
+        &self.triggers
+    }
+
+    fn get_type(&self) -> TriggerType {
+        self.trigger_type
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RegexTrigger;
+
+    fn re(name: &str, re: &str) -> TriggerTypes {
+        TriggerTypes::Regex(RegexTrigger::new(
+            name,
+            "desc",
+            TriggerType::Error,
+            re,
+            false,
+        ))
+    }
+
+    #[test]
+    fn it_should_match_all() {
+        let mut c = CompositeTrigger::new("name", "desc", TriggerType::Error, CompositeMode::All);
+        c.push(re("disk", "disk"));
+        c.push(re("full", "full"));
+
+        assert!(c.check("the disk is full").unwrap());
+        assert_eq!(c.slice("the disk is full").unwrap(), "disk");
+        assert!(!c.check("the disk is empty").unwrap());
+    }
+
+    #[test]
+    fn it_should_match_any() {
+        let mut c = CompositeTrigger::new("name", "desc", TriggerType::Error, CompositeMode::Any);
+        c.push(re("disk", "disk"));
+        c.push(re("full", "full"));
+
+        assert!(c.check("the tank is full").unwrap());
+        assert_eq!(c.slice("the tank is full").unwrap(), "full");
+        assert!(!c.check("the tank is empty").unwrap());
+    }
+
+    #[test]
+    fn it_should_match_none() {
+        let mut c = CompositeTrigger::new("name", "desc", TriggerType::Error, CompositeMode::None);
+        c.push(re("healthcheck", "healthcheck"));
+
+        assert!(c.check("ERROR in request").unwrap());
+        assert_eq!(c.slice("ERROR in request").unwrap(), "");
+        assert!(!c.check("ERROR in healthcheck").unwrap());
+    }
+
+    #[test]
+    fn it_should_match_at_least() {
+        let mut c = CompositeTrigger::new(
+            "name",
+            "desc",
+            TriggerType::Error,
+            CompositeMode::AtLeast(2),
+        );
+        c.push(re("a", "a"));
+        c.push(re("b", "b"));
+        c.push(re("c", "c"));
+
+        assert!(c.check("a c").unwrap());
+        assert_eq!(c.slice("a c").unwrap(), "a");
+        assert!(!c.check("b").unwrap());
+
+        let matches = c.matches("c b c").unwrap();
+        let texts: Vec<&str> = matches.iter().map(|m| m.text.as_str()).collect();
+        assert_eq!(texts, vec!["c", "b", "c"]);
+    }
+
+    #[test]
+    fn it_should_not_match_empty() {
+        let c = CompositeTrigger::new("name", "desc", TriggerType::Error, CompositeMode::All);
+        assert!(!c.check("anything").unwrap());
+    }
+
+    #[test]
+    fn it_should_report_nested_slices() {
+        // ERROR but not healthcheck
+        let mut not = CompositeTrigger::new("not", "desc", TriggerType::Error, CompositeMode::None);
+        not.push(re("healthcheck", "healthcheck"));
+
+        let mut c = CompositeTrigger::new("name", "desc", TriggerType::Error, CompositeMode::All);
+        c.push(TriggerTypes::Composite(not));
+        c.push(re("error", "ERROR"));
+
+        assert!(c.check("ERROR in request").unwrap());
+        assert_eq!(c.slice("ERROR in request").unwrap(), "ERROR");
+        assert!(!c.check("ERROR in healthcheck").unwrap());
+    }
+
+    /// fires only with a context e.g. like a stateful trigger
+    #[derive(Clone, Serialize, Deserialize)]
+    struct ContextOnly;
+
+    #[typetag::serde]
+    impl Trigger for ContextOnly {
+        fn name(&self) -> &str {
+            "context"
+        }
+
+        fn description(&self) -> &str {
+            ""
+        }
+
+        fn check(&self, _text: &str) -> Result<bool, Error> {
+            Ok(false)
+        }
+
+        fn check_ctx(&self, _text: &str, _ctx: &mut TriggerContext) -> Result<bool, Error> {
+            Ok(true)
+        }
+
+        fn slice<'a>(&self, text: &'a str) -> Result<&'a str, Error> {
+            Ok(&text[4..9])
+        }
+
+        fn get_type(&self) -> TriggerType {
+            TriggerType::Error
+        }
+    }
+
+    #[test]
+    fn it_should_report_what_fired_in_the_check() {
+        let mut c = CompositeTrigger::new("name", "desc", TriggerType::Error, CompositeMode::Any);
+        c.push(TriggerTypes::Generic(Box::new(ContextOnly)));
+        c.push(re("late", "late"));
+
+        let text = "the check was late";
+        let mut extra = crate::ExtraData::new();
+        let mut ctx = TriggerContext::new(0, &mut extra);
+        assert!(c.check_ctx(text, &mut ctx).unwrap());
+        assert_eq!(c.slice_ctx(text, &ctx).unwrap(), "check");
+        let texts: Vec<String> = c
+            .matches_ctx(text, &ctx)
+            .unwrap()
+            .into_iter()
+            .map(|m| m.text)
+            .collect();
+        assert_eq!(texts, vec!["check", "late"]);
+
+        // without a context the nested triggers are checked without one
+        assert_eq!(c.slice(text).unwrap(), "late");
+    }
+
+    #[test]
+    fn it_should_round_trip_nested_yaml() {
+        let yaml = r#"---
+Composite:
+  name: error-not-healthcheck
+  description: errors outside of healthchecks
+  trigger_type: Error
+  mode: All
+  triggers:
+    - Regex:
+        name: error
+        description: any error
+        trigger_type: Error
+        re: ERROR
+    - Composite:
+        name: not-healthcheck
+        description: no healthcheck
+        trigger_type: Error
+        mode: None
+        triggers:
+          - Regex:
+              name: healthcheck
+              description: healthcheck requests
+              trigger_type: Error
+              re: healthcheck
+    - Composite:
+        name: two-of-three
+        description: at least two
+        trigger_type: Warning
+        mode:
+          AtLeast: 2
+        triggers:
+          - Regex:
+              name: a
+              description: a
+              trigger_type: Warning
+              re: a
+          - Regex:
+              name: b
+              description: b
+              trigger_type: Warning
+              re: b
+"#;
+        let trigger: TriggerTypes = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(trigger.name(), "error-not-healthcheck");
+        assert!(trigger.check("ERROR a b").unwrap());
+        assert!(!trigger.check("ERROR a").unwrap());
+        assert!(!trigger.check("ERROR a b healthcheck").unwrap());
+        assert_eq!(trigger.slice("ERROR a b").unwrap(), "ERROR");
+
+        let ser = serde_yaml::to_string(&trigger).unwrap();
+        let trigger: TriggerTypes = serde_yaml::from_str(&ser).unwrap();
+        assert_eq!(trigger.name(), "error-not-healthcheck");
+        assert!(trigger.check("ERROR a b").unwrap());
+        assert!(!trigger.check("ERROR a b healthcheck").unwrap());
+    }
+}
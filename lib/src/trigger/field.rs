@@ -0,0 +1,162 @@
+use crate::error::Error;
+use crate::regex::Regex;
+use crate::serde::{Deserialize, Serialize};
+use crate::Comparison;
+use std::convert::TryFrom;
+
+/// A regex of a field test.
+/// It is compiled once when the test is built or read
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct FieldRegex(Regex);
+
+impl FieldRegex {
+    pub fn new(re: &str) -> Result<Self, Error> {
+        Ok(Self(Regex::new(re)?))
+    }
+
+    pub fn is_match(&self, value: &str) -> bool {
+        self.0.is_match(value)
+    }
+}
+
+impl PartialEq for FieldRegex {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.as_str() == other.0.as_str()
+    }
+}
+
+impl TryFrom<String> for FieldRegex {
+    type Error = Error;
+
+    fn try_from(re: String) -> Result<Self, Self::Error> {
+        Self::new(&re)
+    }
+}
+
+impl From<FieldRegex> for String {
+    fn from(re: FieldRegex) -> Self {
+        re.0.as_str().into()
+    }
+}
+
+/// FieldTest is the test applied to the value
+/// of a single field in a structured log line
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub enum FieldTest {
+    /// the field is present
+    Exists,
+    /// the field is not present
+    Missing,
+    /// the field equals the value
+    Equals(String),
+    /// the field matches the regex
+    Regex(FieldRegex),
+    /// the field is a number and compares to the value
+    Compare { op: Comparison, value: f64 },
+}
+
+/// A predicate on a field of a structured log line.
+/// Nested fields are addressed with dotted paths
+/// e.g. http.status
+/// This predicate language is shared by all
+/// triggers that work on structured fields
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+pub struct FieldPredicate {
+    pub field: String,
+    pub test: FieldTest,
+}
+
+impl FieldPredicate {
+    pub fn new(field: &str, test: FieldTest) -> Self {
+        Self {
+            field: field.into(),
+            test,
+        }
+    }
+
+    /// tests the value of the field
+    /// value is None if the field does not exist
+    pub fn matches(&self, value: Option<&str>) -> Result<bool, Error> {
+        Ok(match (&self.test, value) {
+            (FieldTest::Exists, value) => value.is_some(),
+            (FieldTest::Missing, value) => value.is_none(),
+            (FieldTest::Equals(expected), Some(value)) => expected == value,
+            (FieldTest::Regex(re), Some(value)) => re.is_match(value),
+            (
+                FieldTest::Compare {
+                    op,
+                    value: threshold,
+                },
+                Some(value),
+            ) => match value.trim().parse::<f64>() {
+                Ok(value) => op.compare(value, *threshold),
+                _ => false,
+            },
+            (_, None) => false,
+        })
+    }
+
+    /// returns true if all predicates match.
+    /// lookup returns the value of a field
+    pub fn all<F>(predicates: &[FieldPredicate], lookup: F) -> Result<bool, Error>
+    where
+        F: Fn(&str) -> Option<String>,
+    {
+        for predicate in predicates {
+            if !predicate.matches(lookup(&predicate.field).as_deref())? {
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_test_existence() {
+        assert!(FieldPredicate::new("a", FieldTest::Exists)
+            .matches(Some(""))
+            .unwrap());
+        assert!(!FieldPredicate::new("a", FieldTest::Exists)
+            .matches(None)
+            .unwrap());
+        assert!(FieldPredicate::new("a", FieldTest::Missing)
+            .matches(None)
+            .unwrap());
+    }
+
+    #[test]
+    fn it_should_test_values() {
+        let eq = FieldPredicate::new("level", FieldTest::Equals("error".into()));
+        assert!(eq.matches(Some("error")).unwrap());
+        assert!(!eq.matches(Some("info")).unwrap());
+        assert!(!eq.matches(None).unwrap());
+
+        let re = FieldPredicate::new("msg", FieldTest::Regex(FieldRegex::new("^db").unwrap()));
+        assert!(re.matches(Some("db down")).unwrap());
+        assert!(!re.matches(Some("disk full")).unwrap());
+
+        let cmp = FieldPredicate::new(
+            "http.status",
+            FieldTest::Compare {
+                op: Comparison::Ge,
+                value: 500.0,
+            },
+        );
+        assert!(cmp.matches(Some("503")).unwrap());
+        assert!(!cmp.matches(Some("200")).unwrap());
+        assert!(!cmp.matches(Some("n/a")).unwrap());
+    }
+
+    #[test]
+    fn it_should_compile_regexes_when_read() {
+        let test: FieldTest = serde_yaml::from_str("Regex: ^db").unwrap();
+        assert_eq!(test, FieldTest::Regex(FieldRegex::new("^db").unwrap()));
+        assert_eq!(serde_yaml::to_string(&test).unwrap(), "---\nRegex: ^db\n");
+        assert!(serde_yaml::from_str::<FieldTest>("Regex: (").is_err());
+    }
+}
@@ -0,0 +1,459 @@
+use crate::error::Error;
+use crate::record::RecordMode;
+use crate::serde::{de, Deserialize, Serialize};
+use crate::serde_json::{self, Value};
+use crate::typetag;
+use crate::{FieldPredicate, Trigger, TriggerContext, TriggerType};
+use std::ops::Range;
+
+/// NonJsonPolicy decides what happens to
+/// lines that can not be parsed as json objects
+/// e.g. plain text or json arrays
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize, Copy, Clone, Default)]
+pub enum NonJsonPolicy {
+    /// skip the line
+    #[default]
+    Ignore,
+    /// treat the line as a match
+    Match,
+    /// fail the check with an error
+    Fail,
+}
+
+/// A json trigger parses every line as a json object
+/// and fires if all predicates match for at least one line.
+/// The slice is the first matching line, or the value of slice_field
+/// in that line if it is set. String values are reported without quotes.
+/// If the logfile groups lines into records every record is parsed
+/// as a single value e.g. pretty printed json.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct JsonTrigger {
+    name: String,
+    description: String,
+    trigger_type: TriggerType,
+    predicates: Vec<FieldPredicate>,
+
+    #[serde(default)]
+    slice_field: Option<String>,
+    #[serde(default)]
+    non_json: NonJsonPolicy,
+}
+
+impl JsonTrigger {
+    pub fn new(
+        name: &str,
+        description: &str,
+        trigger_type: TriggerType,
+        predicates: Vec<FieldPredicate>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            trigger_type,
+            predicates,
+            slice_field: None,
+            non_json: NonJsonPolicy::Ignore,
+        }
+    }
+
+    pub fn with_slice_field(mut self, field: &str) -> Self {
+        self.slice_field = Some(field.into());
+        self
+    }
+
+    pub fn with_non_json(mut self, non_json: NonJsonPolicy) -> Self {
+        self.non_json = non_json;
+        self
+    }
+
+    /// looks up a dotted path e.g. http.status
+    /// array elements can be addressed by index e.g. items.0
+    pub fn lookup(value: &Value, path: &str) -> Option<String> {
+        // keys may contain the special characters of json pointers
+        let pointer: String = path
+            .split('.')
+            .map(|key| format!("/{}", key.replace('~', "~0").replace('/', "~1")))
+            .collect();
+        match value.pointer(&pointer)? {
+            Value::String(s) => Some(s.clone()),
+            other => Some(other.to_string()),
+        }
+    }
+
+    fn parse(&self, line: &str) -> Result<Option<Value>, Error> {
+        let err = match serde_json::from_str::<Value>(line) {
+            Ok(value) if value.is_object() => return Ok(Some(value)),
+            Ok(_) => de::Error::custom(format!("expected a json object: {}", line)),
+            Err(err) => err,
+        };
+        match self.non_json {
+            NonJsonPolicy::Fail => Err(Error::SerdeJsonError(err)),
+            _ => Ok(None),
+        }
+    }
+
+    /// returns the byte span of the value of a dotted path in a json line.
+    /// Strings are returned without quotes.
+    /// Like the parsed value, the last of duplicate keys is used
+    pub fn span(line: &str, path: &str) -> Option<(usize, usize)> {
+        let path: Vec<&str> = path.split('.').collect();
+        Self::value_span(line.as_bytes(), 0, &path)
+    }
+
+    fn skip_ws(b: &[u8], mut i: usize) -> usize {
+        while i < b.len() && b[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        i
+    }
+
+    /// the end of the string starting at i
+    fn string_end(b: &[u8], mut i: usize) -> Option<usize> {
+        if b.get(i)? != &b'"' {
+            return None;
+        }
+        i += 1;
+        while i < b.len() {
+            match b[i] {
+                b'\\' => i += 2,
+                b'"' => return Some(i + 1),
+                _ => i += 1,
+            }
+        }
+        None
+    }
+
+    /// the end of the value starting at i
+    fn value_end(b: &[u8], i: usize) -> Option<usize> {
+        match b.get(i)? {
+            b'"' => Self::string_end(b, i),
+            b'{' | b'[' => {
+                let mut depth = 0;
+                let mut j = i;
+                while j < b.len() {
+                    match b[j] {
+                        b'"' => {
+                            j = Self::string_end(b, j)?;
+                            continue;
+                        }
+                        b'{' | b'[' => depth += 1,
+                        b'}' | b']' => {
+                            depth -= 1;
+                            if depth == 0 {
+                                return Some(j + 1);
+                            }
+                        }
+                        _ => {}
+                    }
+                    j += 1;
+                }
+                None
+            }
+            _ => {
+                let mut j = i;
+                while j < b.len()
+                    && !matches!(b[j], b',' | b'}' | b']')
+                    && !b[j].is_ascii_whitespace()
+                {
+                    j += 1;
+                }
+                Some(j)
+            }
+        }
+    }
+
+    fn value_span(b: &[u8], i: usize, path: &[&str]) -> Option<(usize, usize)> {
+        let i = Self::skip_ws(b, i);
+        let (field, rest) = match path.split_first() {
+            Some(split) => split,
+            _ => {
+                let end = Self::value_end(b, i)?;
+                return match b[i] {
+                    b'"' => Some((i + 1, end - 1)),
+                    _ => Some((i, end)),
+                };
+            }
+        };
+
+        let (close, index) = match b.get(i)? {
+            b'{' => (b'}', None),
+            b'[' => (b']', Some(field.parse::<usize>().ok()?)),
+            _ => return None,
+        };
+        let mut j = Self::skip_ws(b, i + 1);
+        let mut n = 0;
+        // the start of the value of the last matching key
+        let mut found = None;
+        loop {
+            if b.get(j)? == &close {
+                return Self::value_span(b, found?, rest);
+            }
+            let start = match index {
+                Some(_) => j,
+                _ => {
+                    let key_end = Self::string_end(b, j)?;
+                    // keys may contain escapes
+                    let key: String = serde_json::from_slice(&b[j..key_end]).ok()?;
+                    let colon = Self::skip_ws(b, key_end);
+                    if b.get(colon)? != &b':' {
+                        return None;
+                    }
+                    let start = Self::skip_ws(b, colon + 1);
+                    if key == *field {
+                        found = Some(start);
+                    }
+                    start
+                }
+            };
+            if index == Some(n) {
+                return Self::value_span(b, start, rest);
+            }
+            j = Self::skip_ws(b, Self::value_end(b, start)?);
+            match b.get(j)? {
+                b',' => j = Self::skip_ws(b, j + 1),
+                c if *c == close => {}
+                _ => return None,
+            }
+            n += 1;
+        }
+    }
+
+    /// returns the first matching line
+    /// and its parsed value (None for non-json matches)
+    fn find<'a>(
+        &self,
+        text: &'a str,
+        records: Option<&[Range<usize>]>,
+    ) -> Result<Option<(&'a str, Option<Value>)>, Error> {
+        for record in RecordMode::records(records, text) {
+            let line = &text[record];
+            if line.trim().is_empty() {
+                continue;
+            }
+            match self.parse(line)? {
+                Some(value) => {
+                    if FieldPredicate::all(&self.predicates, |path| Self::lookup(&value, path))? {
+                        return Ok(Some((line, Some(value))));
+                    }
+                }
+                None => {
+                    if self.non_json == NonJsonPolicy::Match {
+                        return Ok(Some((line, None)));
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    /// returns the matching line or the span of slice_field in it
+    fn slice_in<'a>(
+        &self,
+        text: &'a str,
+        records: Option<&[Range<usize>]>,
+    ) -> Result<&'a str, Error> {
+        let (line, value) = match self.find(text, records)? {
+            Some(found) => found,
+            _ => return Ok(&text[0..0]),
+        };
+
+        if let (Some(field), Some(_)) = (&self.slice_field, value) {
+            if let Some((start, end)) = Self::span(line, field) {
+                return Ok(&line[start..end]);
+            }
+        }
+        Ok(line)
+    }
+}
+
+#[typetag::serde]
+impl Trigger for JsonTrigger {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn check(&self, text: &str) -> Result<bool, Error> {
+        Ok(self.find(text, None)?.is_some())
+    }
+
+    fn check_ctx(&self, text: &str, ctx: &mut TriggerContext) -> Result<bool, Error> {
+        Ok(self.find(text, ctx.records)?.is_some())
+    }
+
+    fn slice<'a>(&self, text: &'a str) -> Result<&'a str, Error> {
+        self.slice_in(text, None)
+    }
+
+    fn slice_ctx<'a>(&self, text: &'a str, ctx: &TriggerContext) -> Result<&'a str, Error> {
+        self.slice_in(text, ctx.records)
+    }
+
+    fn get_type(&self) -> TriggerType {
+        self.trigger_type
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Comparison, FieldTest};
+
+    const TEXT: &str = r#"{"level":"info","msg":"ok","http":{"status":200}}
+not json at all
+{"level":"error","msg":"db down","http":{"status":503},"error":{"kind":"timeout"}}
+"#;
+
+    fn trigger() -> JsonTrigger {
+        JsonTrigger::new(
+            "server-error",
+            "5xx responses",
+            TriggerType::Error,
+            vec![
+                FieldPredicate::new("level", FieldTest::Equals("error".into())),
+                FieldPredicate::new(
+                    "http.status",
+                    FieldTest::Compare {
+                        op: Comparison::Ge,
+                        value: 500.0,
+                    },
+                ),
+                FieldPredicate::new("error.kind", FieldTest::Exists),
+            ],
+        )
+    }
+
+    #[test]
+    fn it_should_match_fields() {
+        let t = trigger();
+        assert!(t.check(TEXT).unwrap());
+        assert_eq!(t.slice(TEXT).unwrap(), TEXT.lines().nth(2).unwrap());
+    }
+
+    #[test]
+    fn it_should_not_match_fields() {
+        let t = trigger();
+        let text = TEXT.lines().next().unwrap();
+        assert!(!t.check(text).unwrap());
+        assert_eq!(t.slice(text).unwrap(), "");
+    }
+
+    #[test]
+    fn it_should_slice_field() {
+        let t = trigger().with_slice_field("msg");
+        assert_eq!(t.slice(TEXT).unwrap(), "db down");
+
+        // numbers are found as well
+        let t = trigger().with_slice_field("http.status");
+        assert_eq!(t.slice(TEXT).unwrap(), "503");
+
+        // missing fields fall back to the line
+        let t = trigger().with_slice_field("missing");
+        assert_eq!(t.slice(TEXT).unwrap(), TEXT.lines().nth(2).unwrap());
+    }
+
+    #[test]
+    fn it_should_slice_the_value_of_the_field() {
+        let line = r#"{"msg":"error","code":15033,"level":"error","http":{"status":503}}"#;
+        let t = JsonTrigger::new("any", "desc", TriggerType::Error, vec![]);
+
+        let (start, end) = JsonTrigger::span(line, "level").unwrap();
+        assert_eq!(&line[start..end], "error");
+        assert_eq!(start, line.find(r#""level":"#).unwrap() + 9);
+        assert_eq!(
+            t.clone().with_slice_field("level").slice(line).unwrap(),
+            "error"
+        );
+
+        let slice = t.with_slice_field("http.status").slice(line).unwrap();
+        assert_eq!(slice, "503");
+        assert_eq!(slice.as_ptr(), line[line.find("503}").unwrap()..].as_ptr());
+
+        let line = r#"{"a": [1, {"b\"c": "x,]}"}, {"d": true}], "e": null}"#;
+        let span = |path: &str| JsonTrigger::span(line, path).map(|(s, e)| &line[s..e]);
+        assert_eq!(span("a.1"), Some(r#"{"b\"c": "x,]}"}"#));
+        assert_eq!(span("a.2.d"), Some("true"));
+        assert_eq!(span("e"), Some("null"));
+        assert_eq!(span("a.3"), None);
+        assert_eq!(span("x"), None);
+        assert_eq!(span("a.1.b\"c"), Some("x,]}"));
+    }
+
+    #[test]
+    fn it_should_use_the_same_value_as_the_parser() {
+        // the last of duplicate keys wins
+        let line = r#"{"level":"info","level":"error"}"#;
+        let t = JsonTrigger::new(
+            "error",
+            "desc",
+            TriggerType::Error,
+            vec![FieldPredicate::new(
+                "level",
+                FieldTest::Equals("error".into()),
+            )],
+        )
+        .with_slice_field("level");
+        assert!(t.check(line).unwrap());
+        assert_eq!(t.slice(line).unwrap(), "error");
+        assert_eq!(
+            t.slice(line).unwrap().as_ptr(),
+            line[line.rfind("error").unwrap()..].as_ptr()
+        );
+
+        // keys with pointer characters
+        let value: Value = serde_json::from_str(r#"{"a/b":{"c~d":1}}"#).unwrap();
+        assert_eq!(JsonTrigger::lookup(&value, "a/b.c~d"), Some("1".into()));
+    }
+
+    #[test]
+    fn it_should_apply_non_json_policy() {
+        let t = JsonTrigger::new("any", "desc", TriggerType::Warning, vec![]);
+        assert!(!t.check("not json").unwrap());
+
+        let t = t.with_non_json(NonJsonPolicy::Match);
+        assert!(t.check("not json").unwrap());
+        assert_eq!(t.slice("{}\nnot json").unwrap(), "{}");
+
+        let t = trigger().with_non_json(NonJsonPolicy::Fail);
+        assert!(t.check(TEXT).is_err());
+
+        // valid json that is not an object
+        assert!(t.check("[1, 2]").is_err());
+        let t = trigger().with_non_json(NonJsonPolicy::Match);
+        assert_eq!(t.slice("\"text\"").unwrap(), "\"text\"");
+    }
+
+    #[test]
+    fn it_should_lookup_paths() {
+        let value: Value = serde_json::from_str(r#"{"a":{"b":[1,{"c":"d"}]}}"#).unwrap();
+        assert_eq!(JsonTrigger::lookup(&value, "a.b.1.c"), Some("d".into()));
+        assert_eq!(JsonTrigger::lookup(&value, "a.b.0"), Some("1".into()));
+        assert_eq!(JsonTrigger::lookup(&value, "a.x"), None);
+    }
+
+    #[test]
+    fn it_should_deserialize_yaml() {
+        let yaml = r#"---
+Json:
+  name: server-error
+  description: 5xx responses
+  trigger_type: Error
+  slice_field: msg
+  predicates:
+    - field: level
+      test:
+        Regex: "^(error|fatal)$"
+    - field: http.status
+      test:
+        Compare:
+          op: ">="
+          value: 500
+"#;
+        let t: crate::TriggerTypes = serde_yaml::from_str(yaml).unwrap();
+        assert!(t.check(TEXT).unwrap());
+        assert_eq!(t.slice(TEXT).unwrap(), "db down");
+    }
+}
@@ -0,0 +1,242 @@
+use crate::error::Error;
+use crate::record::RecordMode;
+use crate::serde::{Deserialize, Serialize};
+use crate::typetag;
+use crate::{FieldPredicate, Trigger, TriggerContext, TriggerType};
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// A logfmt trigger parses every line as logfmt
+/// key/value pairs e.g. level=error msg="db down" dur=3.2s
+/// and fires if all predicates match for at least one line.
+/// The slice is the first matching line.
+/// If the logfile groups lines into records every record is a line.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct LogfmtTrigger {
+    name: String,
+    description: String,
+    trigger_type: TriggerType,
+    predicates: Vec<FieldPredicate>,
+}
+
+impl LogfmtTrigger {
+    pub fn new(
+        name: &str,
+        description: &str,
+        trigger_type: TriggerType,
+        predicates: Vec<FieldPredicate>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            trigger_type,
+            predicates,
+        }
+    }
+
+    /// parses a single logfmt line
+    /// values may be quoted with double quotes and contain
+    /// the escapes \" \\ \n \t and \r.
+    /// Keys without a value (e.g. "debug") are parsed as empty strings.
+    /// Garbage between pairs is skipped.
+    pub fn parse(line: &str) -> HashMap<String, String> {
+        let mut result = HashMap::new();
+        let mut chars = line.chars().peekable();
+
+        loop {
+            // skip whitespace
+            while chars.peek().is_some_and(|c| c.is_whitespace()) {
+                chars.next();
+            }
+            if chars.peek().is_none() {
+                break;
+            }
+
+            let mut key = String::new();
+            while let Some(&c) = chars.peek() {
+                if c == '=' || c.is_whitespace() {
+                    break;
+                }
+                key.push(c);
+                chars.next();
+            }
+
+            let mut value = String::new();
+            if chars.peek() == Some(&'=') {
+                chars.next();
+                if chars.peek() == Some(&'"') {
+                    chars.next();
+                    while let Some(c) = chars.next() {
+                        match c {
+                            '"' => break,
+                            '\\' => match chars.next() {
+                                Some('n') => value.push('\n'),
+                                Some('t') => value.push('\t'),
+                                Some('r') => value.push('\r'),
+                                Some(c) => value.push(c),
+                                None => value.push('\\'),
+                            },
+                            c => value.push(c),
+                        }
+                    }
+                } else {
+                    while let Some(&c) = chars.peek() {
+                        if c.is_whitespace() {
+                            break;
+                        }
+                        value.push(c);
+                        chars.next();
+                    }
+                }
+            }
+
+            if !key.is_empty() {
+                result.insert(key, value);
+            }
+        }
+
+        result
+    }
+
+    fn find<'a>(
+        &self,
+        text: &'a str,
+        records: Option<&[Range<usize>]>,
+    ) -> Result<Option<&'a str>, Error> {
+        for record in RecordMode::records(records, text) {
+            let line = &text[record];
+            let fields = Self::parse(line);
+            if fields.is_empty() {
+                continue;
+            }
+            if FieldPredicate::all(&self.predicates, |key| fields.get(key).cloned())? {
+                return Ok(Some(line));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[typetag::serde]
+impl Trigger for LogfmtTrigger {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn check(&self, text: &str) -> Result<bool, Error> {
+        Ok(self.find(text, None)?.is_some())
+    }
+
+    fn check_ctx(&self, text: &str, ctx: &mut TriggerContext) -> Result<bool, Error> {
+        Ok(self.find(text, ctx.records)?.is_some())
+    }
+
+    fn slice<'a>(&self, text: &'a str) -> Result<&'a str, Error> {
+        Ok(self.find(text, None)?.unwrap_or(&text[0..0]))
+    }
+
+    fn slice_ctx<'a>(&self, text: &'a str, ctx: &TriggerContext) -> Result<&'a str, Error> {
+        Ok(self.find(text, ctx.records)?.unwrap_or(&text[0..0]))
+    }
+
+    fn get_type(&self) -> TriggerType {
+        self.trigger_type
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Comparison, FieldRegex, FieldTest};
+
+    fn parse(line: &str) -> Vec<(String, String)> {
+        let mut fields: Vec<(String, String)> = LogfmtTrigger::parse(line).into_iter().collect();
+        fields.sort();
+        fields
+    }
+
+    fn pair(key: &str, value: &str) -> (String, String) {
+        (key.into(), value.into())
+    }
+
+    #[test]
+    fn it_should_parse_simple_pairs() {
+        assert_eq!(
+            parse("level=error msg=down dur=3.2s"),
+            vec![
+                pair("dur", "3.2s"),
+                pair("level", "error"),
+                pair("msg", "down")
+            ]
+        );
+    }
+
+    #[test]
+    fn it_should_parse_quoted_values() {
+        assert_eq!(
+            parse(r#"level=error msg="db down" empty="""#),
+            vec![
+                pair("empty", ""),
+                pair("level", "error"),
+                pair("msg", "db down")
+            ]
+        );
+    }
+
+    #[test]
+    fn it_should_parse_escapes() {
+        assert_eq!(
+            parse(r#"msg="say \"hi\" \\ bye\n" path=C:\tmp"#),
+            vec![pair("msg", "say \"hi\" \\ bye\n"), pair("path", "C:\\tmp")]
+        );
+    }
+
+    #[test]
+    fn it_should_parse_tricky_quoting() {
+        // = inside quotes, unterminated quote, bare keys and extra whitespace
+        assert_eq!(
+            parse(r#"  q="a=b c"   debug  u="unterminated x=1"#),
+            vec![
+                pair("debug", ""),
+                pair("q", "a=b c"),
+                pair("u", "unterminated x=1")
+            ]
+        );
+        assert_eq!(parse(r#"k="trailing\"#), vec![pair("k", "trailing\\")]);
+        assert_eq!(parse(""), vec![]);
+    }
+
+    #[test]
+    fn it_should_match_predicates() {
+        let t = LogfmtTrigger::new(
+            "slow-error",
+            "slow errors",
+            TriggerType::Error,
+            vec![
+                FieldPredicate::new("level", FieldTest::Equals("error".into())),
+                FieldPredicate::new("msg", FieldTest::Regex(FieldRegex::new("^db").unwrap())),
+                FieldPredicate::new(
+                    "status",
+                    FieldTest::Compare {
+                        op: Comparison::Ge,
+                        value: 500.0,
+                    },
+                ),
+            ],
+        );
+        let text = "level=info msg=ok status=200\nlevel=error msg=\"db down\" status=503\n";
+        assert!(t.check(text).unwrap());
+        assert_eq!(
+            t.slice(text).unwrap(),
+            "level=error msg=\"db down\" status=503"
+        );
+
+        let text = "level=error msg=\"disk full\" status=503\n";
+        assert!(!t.check(text).unwrap());
+        assert_eq!(t.slice(text).unwrap(), "");
+    }
+}
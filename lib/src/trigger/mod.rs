@@ -0,0 +1,23 @@
+mod anomaly;
+mod base;
+mod composite;
+mod field;
+mod json;
+mod logfmt;
+mod numeric;
+mod regex;
+mod script;
+mod sequence;
+mod wasm;
+
+pub use self::anomaly::*;
+pub use self::base::*;
+pub use self::composite::*;
+pub use self::field::*;
+pub use self::json::*;
+pub use self::logfmt::*;
+pub use self::numeric::*;
+pub use self::regex::*;
+pub use self::script::*;
+pub use self::sequence::*;
+pub use self::wasm::*;
@@ -0,0 +1,318 @@
+use crate::error::Error;
+use crate::regex::{Captures, Regex};
+use crate::serde::{Deserialize, Serialize};
+use crate::typetag;
+use crate::{Trigger, TriggerType};
+use std::fmt;
+use std::str::FromStr;
+
+/// Comparison operators used to compare
+/// an extracted value against a threshold
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize, Copy, Clone)]
+pub enum Comparison {
+    #[serde(rename = ">")]
+    Gt,
+    #[serde(rename = ">=")]
+    Ge,
+    #[serde(rename = "<")]
+    Lt,
+    #[serde(rename = "<=")]
+    Le,
+    #[serde(rename = "==")]
+    Eq,
+    #[serde(rename = "!=")]
+    Ne,
+}
+
+impl Comparison {
+    pub fn compare(&self, value: f64, threshold: f64) -> bool {
+        match self {
+            Self::Gt => value > threshold,
+            Self::Ge => value >= threshold,
+            Self::Lt => value < threshold,
+            Self::Le => value <= threshold,
+            Self::Eq => (value - threshold).abs() < f64::EPSILON,
+            Self::Ne => (value - threshold).abs() >= f64::EPSILON,
+        }
+    }
+}
+
+impl fmt::Display for Comparison {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = match self {
+            Self::Gt => ">",
+            Self::Ge => ">=",
+            Self::Lt => "<",
+            Self::Le => "<=",
+            Self::Eq => "==",
+            Self::Ne => "!=",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+impl FromStr for Comparison {
+    type Err = Error;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            ">" => Ok(Self::Gt),
+            ">=" => Ok(Self::Ge),
+            "<" => Ok(Self::Lt),
+            "<=" => Ok(Self::Le),
+            "==" => Ok(Self::Eq),
+            "!=" => Ok(Self::Ne),
+            _ => Err(Error::FromStringError),
+        }
+    }
+}
+
+/// Aggregate describes how the values of
+/// all occurrences in the text are combined
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize, Copy, Clone, Default)]
+pub enum Aggregate {
+    #[default]
+    Last,
+    Max,
+    Min,
+    Avg,
+}
+
+/// A numeric trigger extracts a number from the text
+/// and compares it against a warning and an error threshold.
+/// The number is taken from the capture group named value,
+/// or the first capture group if there is no such group.
+/// The trigger type depends on which threshold is crossed,
+/// error takes precedence over warning.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct NumericTrigger {
+    name: String,
+    description: String,
+    re: String,
+    op: Comparison,
+
+    #[serde(default)]
+    aggregate: Aggregate,
+    #[serde(default)]
+    warning: Option<f64>,
+    #[serde(default)]
+    error: Option<f64>,
+}
+
+impl NumericTrigger {
+    pub fn new(
+        name: &str,
+        description: &str,
+        re: &str,
+        op: Comparison,
+        aggregate: Aggregate,
+        warning: Option<f64>,
+        error: Option<f64>,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            re: re.into(),
+            op,
+            aggregate,
+            warning,
+            error,
+        }
+    }
+
+    fn parse_value(captures: &Captures) -> Option<f64> {
+        let m = captures
+            .name("value")
+            .or_else(|| captures.get(1))
+            .or_else(|| captures.get(0))?;
+        m.as_str().trim().parse::<f64>().ok()
+    }
+
+    /// returns the aggregated value
+    /// and the match that should be reported as the slice
+    fn extract<'a>(&self, text: &'a str) -> Result<Option<(f64, &'a str)>, Error> {
+        let re = Regex::new(&self.re)?;
+
+        let mut result: Option<(f64, &'a str)> = None;
+        let mut sum = 0.0;
+        let mut count = 0;
+        for captures in re.captures_iter(text) {
+            let value = match Self::parse_value(&captures) {
+                Some(value) => value,
+                _ => continue,
+            };
+            let slice = &text[captures.get(0).map_or(0..0, |m| m.range())];
+            sum += value;
+            count += 1;
+
+            result = match (self.aggregate, result) {
+                (Aggregate::Max, Some((prev, _))) if prev >= value => result,
+                (Aggregate::Min, Some((prev, _))) if prev <= value => result,
+                _ => Some((value, slice)),
+            };
+        }
+
+        if self.aggregate == Aggregate::Avg {
+            result = result.map(|(_, slice)| (sum / count as f64, slice));
+        }
+        Ok(result)
+    }
+
+    fn crossed(&self, threshold: Option<f64>, value: f64) -> bool {
+        match threshold {
+            Some(threshold) => self.op.compare(value, threshold),
+            _ => false,
+        }
+    }
+}
+
+#[typetag::serde]
+impl Trigger for NumericTrigger {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn check(&self, text: &str) -> Result<bool, Error> {
+        Ok(self.get_type_for(text)? != TriggerType::NoEvent)
+    }
+
+    fn slice<'a>(&self, text: &'a str) -> Result<&'a str, Error> {
+        match self.extract(text)? {
+            Some((_, slice)) => Ok(slice),
+            _ => Ok(&text[0..0]),
+        }
+    }
+
+    /// the most severe type this trigger can report
+    fn get_type(&self) -> TriggerType {
+        if self.error.is_some() {
+            TriggerType::Error
+        } else if self.warning.is_some() {
+            TriggerType::Warning
+        } else {
+            TriggerType::NoEvent
+        }
+    }
+
+    fn get_type_for(&self, text: &str) -> Result<TriggerType, Error> {
+        let value = match self.extract(text)? {
+            Some((value, _)) => value,
+            _ => return Ok(TriggerType::NoEvent),
+        };
+
+        if self.crossed(self.error, value) {
+            Ok(TriggerType::Error)
+        } else if self.crossed(self.warning, value) {
+            Ok(TriggerType::Warning)
+        } else {
+            Ok(TriggerType::NoEvent)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEXT: &str = "queue_depth=10\nqueue_depth=842\nqueue_depth=120\n";
+
+    fn trigger(aggregate: Aggregate) -> NumericTrigger {
+        NumericTrigger::new(
+            "queue",
+            "queue depth",
+            r"queue_depth=(\d+)",
+            Comparison::Ge,
+            aggregate,
+            Some(100.0),
+            Some(500.0),
+        )
+    }
+
+    #[test]
+    fn it_should_use_last_value() {
+        let t = trigger(Aggregate::Last);
+        assert!(t.check(TEXT).unwrap());
+        assert_eq!(t.get_type_for(TEXT).unwrap(), TriggerType::Warning);
+        assert_eq!(t.slice(TEXT).unwrap(), "queue_depth=120");
+    }
+
+    #[test]
+    fn it_should_use_max_value() {
+        let t = trigger(Aggregate::Max);
+        assert_eq!(t.get_type_for(TEXT).unwrap(), TriggerType::Error);
+        assert_eq!(t.slice(TEXT).unwrap(), "queue_depth=842");
+    }
+
+    #[test]
+    fn it_should_use_min_value() {
+        let t = trigger(Aggregate::Min);
+        assert!(!t.check(TEXT).unwrap());
+        assert_eq!(t.get_type_for(TEXT).unwrap(), TriggerType::NoEvent);
+        assert_eq!(t.slice(TEXT).unwrap(), "queue_depth=10");
+    }
+
+    #[test]
+    fn it_should_use_avg_value() {
+        // (10 + 842 + 120) / 3 = 324
+        let t = trigger(Aggregate::Avg);
+        assert_eq!(t.get_type_for(TEXT).unwrap(), TriggerType::Warning);
+    }
+
+    #[test]
+    fn it_should_prefer_named_group() {
+        let t = NumericTrigger::new(
+            "latency",
+            "latency",
+            r"(latency)_ms=(?P<value>\d+\.?\d*)",
+            Comparison::Gt,
+            Aggregate::Last,
+            None,
+            Some(1000.0),
+        );
+        assert_eq!(t.get_type(), TriggerType::Error);
+        assert!(t.check("latency_ms=1203.5").unwrap());
+        assert!(!t.check("latency_ms=999").unwrap());
+    }
+
+    #[test]
+    fn it_should_not_trigger_without_value() {
+        let t = trigger(Aggregate::Last);
+        assert!(!t.check("nothing to see here").unwrap());
+        assert_eq!(t.slice("nothing to see here").unwrap(), "");
+    }
+
+    #[test]
+    fn it_should_parse_comparison() {
+        assert_eq!(Comparison::from_str(">=").unwrap(), Comparison::Ge);
+        assert_eq!(Comparison::from_str("!=").unwrap(), Comparison::Ne);
+        assert_eq!(Comparison::from_str("=>"), Err(Error::FromStringError));
+        assert!(Comparison::Lt.compare(1.0, 2.0));
+        assert!(Comparison::Eq.compare(2.0, 2.0));
+    }
+
+    #[test]
+    fn it_should_deserialize_yaml() {
+        let yaml = r#"---
+Numeric:
+  name: latency
+  description: request latency
+  re: latency_ms=(\d+)
+  op: ">"
+  aggregate: Max
+  warning: 500
+  error: 1000
+"#;
+        let t: crate::TriggerTypes = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(
+            t.get_type_for("latency_ms=600").unwrap(),
+            TriggerType::Warning
+        );
+        assert_eq!(
+            t.get_type_for("latency_ms=1203").unwrap(),
+            TriggerType::Error
+        );
+    }
+}
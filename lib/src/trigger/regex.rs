@@ -0,0 +1,292 @@
+use crate::error::Error;
+use crate::record::RecordMode;
+use crate::regex::Regex;
+use crate::serde::{Deserialize, Serialize};
+use crate::typetag;
+use crate::{Trigger, TriggerContext, TriggerMatch, TriggerType};
+use std::collections::HashMap;
+use std::ops::Range;
+
+/// A regex trigger fires if the regex matches the text.
+/// If per_line is set the regex is applied to every line on its own,
+/// so ^ and $ match at line boundaries and matches never span lines.
+/// If the logfile groups lines into records every record is a line.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct RegexTrigger {
+    name: String,
+    description: String,
+    trigger_type: TriggerType,
+    re: String,
+
+    #[serde(default)]
+    invert: bool,
+    #[serde(default)]
+    per_line: bool,
+}
+
+impl RegexTrigger {
+    pub fn new(
+        name: &str,
+        description: &str,
+        trigger_type: TriggerType,
+        re: &str,
+        invert: bool,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            trigger_type,
+            re: re.into(),
+            invert,
+            per_line: false,
+        }
+    }
+
+    pub fn with_per_line(mut self, per_line: bool) -> Self {
+        self.per_line = per_line;
+        self
+    }
+
+    /// returns the byte ranges of the parts
+    /// of the text the regex is applied to
+    fn scopes(&self, text: &str, records: Option<&[Range<usize>]>) -> Vec<Range<usize>> {
+        if !self.per_line {
+            return vec![Range {
+                start: 0,
+                end: text.len(),
+            }];
+        }
+        RecordMode::records(records, text)
+    }
+
+    fn check_in(&self, text: &str, records: Option<&[Range<usize>]>) -> Result<bool, Error> {
+        let re = Regex::new(&self.re)?;
+        let is_match = self
+            .scopes(text, records)
+            .into_iter()
+            .any(|scope| re.is_match(&text[scope]));
+        Ok(is_match ^ self.invert)
+    }
+
+    fn slice_in<'a>(
+        &self,
+        text: &'a str,
+        records: Option<&[Range<usize>]>,
+    ) -> Result<&'a str, Error> {
+        let re = Regex::new(&self.re)?;
+        for scope in self.scopes(text, records) {
+            if let Some(ma) = re.find(&text[scope.clone()]) {
+                return Ok(&text[scope.start + ma.start()..scope.start + ma.end()]);
+            }
+        }
+        Ok(&text[0..0])
+    }
+
+    fn matches_in(
+        &self,
+        text: &str,
+        records: Option<&[Range<usize>]>,
+    ) -> Result<Vec<TriggerMatch>, Error> {
+        // an inverted trigger fires because nothing matched
+        if self.invert {
+            return Ok(vec![]);
+        }
+
+        let re = Regex::new(&self.re)?;
+        let mut result = vec![];
+        for scope in self.scopes(text, records) {
+            for captures in re.captures_iter(&text[scope.clone()]) {
+                let m = match captures.get(0) {
+                    Some(m) => m,
+                    _ => continue,
+                };
+                let groups = captures
+                    .iter()
+                    .skip(1)
+                    .map(|c| c.map(|c| c.as_str().to_string()))
+                    .collect();
+                let named: HashMap<String, String> = re
+                    .capture_names()
+                    .flatten()
+                    .filter_map(|name| {
+                        captures
+                            .name(name)
+                            .map(|c| (name.to_string(), c.as_str().to_string()))
+                    })
+                    .collect();
+                result.push(
+                    TriggerMatch::new(text, scope.start + m.start(), scope.start + m.end(), groups)
+                        .with_named(named),
+                );
+            }
+        }
+        Ok(result)
+    }
+}
+
+#[typetag::serde]
+impl Trigger for RegexTrigger {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn check(&self, text: &str) -> Result<bool, Error> {
+        self.check_in(text, None)
+    }
+
+    fn check_ctx(&self, text: &str, ctx: &mut TriggerContext) -> Result<bool, Error> {
+        self.check_in(text, ctx.records)
+    }
+
+    fn slice<'a>(&self, text: &'a str) -> Result<&'a str, Error> {
+        self.slice_in(text, None)
+    }
+
+    fn slice_ctx<'a>(&self, text: &'a str, ctx: &TriggerContext) -> Result<&'a str, Error> {
+        self.slice_in(text, ctx.records)
+    }
+
+    fn matches(&self, text: &str) -> Result<Vec<TriggerMatch>, Error> {
+        self.matches_in(text, None)
+    }
+
+    fn matches_ctx(&self, text: &str, ctx: &TriggerContext) -> Result<Vec<TriggerMatch>, Error> {
+        self.matches_in(text, ctx.records)
+    }
+
+    fn get_type(&self) -> TriggerType {
+        self.trigger_type
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_match_trigger() {
+        let r = RegexTrigger::new("name", "desc", TriggerType::Success, "test", false);
+
+        assert!(r.check("This is a test string").unwrap());
+        assert_eq!(r.slice("This is a test string").unwrap(), "test");
+    }
+
+    #[test]
+    fn it_should_not_match() {
+        let r = RegexTrigger::new("name", "desc", TriggerType::Success, "foo", false);
+
+        assert!(!r.check("This is a test string").unwrap());
+        assert_eq!(r.slice("This is a test string").unwrap(), "");
+    }
+
+    #[test]
+    fn it_should_match_inverted_trigger() {
+        let r = RegexTrigger::new("name", "desc", TriggerType::Success, "foo", true);
+
+        assert!(r.check("This is a test string").unwrap());
+        assert_eq!(r.slice("This is a test string").unwrap(), "");
+    }
+
+    #[test]
+    fn it_should_not_match_inverted() {
+        let r = RegexTrigger::new("name", "desc", TriggerType::Success, "test", true);
+
+        assert!(!r.check("This is a test string").unwrap());
+        assert_eq!(r.slice("This is a test string").unwrap(), "test");
+    }
+
+    #[test]
+    fn it_should_return_every_match() {
+        let r = RegexTrigger::new(
+            "name",
+            "desc",
+            TriggerType::Error,
+            r"ERROR (\d+)( fatal)?",
+            false,
+        );
+
+        let text = "INFO ok\nERROR 1\nINFO ok\nERROR 2 fatal\n";
+        let matches = r.matches(text).unwrap();
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[0].line, 2);
+        assert_eq!(matches[0].text, "ERROR 1");
+        assert_eq!(&text[matches[0].start..matches[0].end], "ERROR 1");
+        assert_eq!(matches[0].captures, vec![Some("1".into()), None]);
+        assert_eq!(matches[1].line, 4);
+        assert_eq!(matches[1].text, "ERROR 2 fatal");
+        assert_eq!(
+            matches[1].captures,
+            vec![Some("2".into()), Some(" fatal".into())]
+        );
+    }
+
+    #[test]
+    fn it_should_match_per_line() {
+        let text = "INFO ok\nERROR 1\r\nERROR 2";
+        let r = RegexTrigger::new("name", "desc", TriggerType::Error, r"^ERROR \d$", false);
+        assert!(!r.check(text).unwrap());
+        assert!(r.matches(text).unwrap().is_empty());
+
+        let r = r.with_per_line(true);
+        assert!(r.check(text).unwrap());
+        assert_eq!(r.slice(text).unwrap(), "ERROR 1");
+        let matches = r.matches(text).unwrap();
+        assert_eq!(matches.len(), 2);
+        assert_eq!((matches[0].line, matches[1].line), (2, 3));
+        assert_eq!(&text[matches[1].start..matches[1].end], "ERROR 2");
+    }
+
+    #[test]
+    fn it_should_not_match_across_lines() {
+        let text = "ERROR\nfailed";
+        let r = RegexTrigger::new("name", "desc", TriggerType::Error, r"ERROR\s+failed", false);
+        assert!(r.check(text).unwrap());
+        assert!(!r.with_per_line(true).check(text).unwrap());
+    }
+
+    #[test]
+    fn it_should_not_return_inverted_matches() {
+        let r = RegexTrigger::new("name", "desc", TriggerType::Success, "foo", true);
+        assert!(r.matches("This is a test string").unwrap().is_empty());
+    }
+
+    #[test]
+    fn it_should_return_named_captures() {
+        let r = RegexTrigger::new(
+            "name",
+            "desc",
+            TriggerType::Error,
+            r"login failed for (?P<user>\w+)( code (?P<code>\d{3}))?",
+            false,
+        );
+
+        let matches = r
+            .matches("login failed for bob code 403\nlogin failed for alice")
+            .unwrap();
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[0].named.get("user"), Some(&"bob".to_string()));
+        assert_eq!(matches[0].named.get("code"), Some(&"403".to_string()));
+        assert_eq!(matches[1].named.get("user"), Some(&"alice".to_string()));
+        assert_eq!(matches[1].named.get("code"), None);
+    }
+
+    #[test]
+    fn it_should_match_records_of_the_context() {
+        use crate::extra::ExtraData;
+
+        let r = RegexTrigger::new("name", "desc", TriggerType::Error, "a\nb$", false)
+            .with_per_line(true);
+        let text = "a\nb\nc";
+        let records = [0..3, 4..5];
+        let mut extra = ExtraData::new();
+        let mut ctx = TriggerContext::new(0, &mut extra).with_records(Some(&records));
+        assert!(!r.check(text).unwrap());
+        assert!(r.check_ctx(text, &mut ctx).unwrap());
+        assert_eq!(r.slice_ctx(text, &ctx).unwrap(), "a\nb");
+        assert_eq!(r.matches_ctx(text, &ctx).unwrap()[0].end, 3);
+    }
+}
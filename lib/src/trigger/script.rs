@@ -0,0 +1,532 @@
+use crate::error::Error;
+use crate::extra::ExtraData;
+use crate::record::RecordMode;
+use crate::rhai::module_resolvers::DummyModuleResolver;
+use crate::rhai::{Array, Dynamic, Engine, Map, Scope};
+use crate::serde::{Deserialize, Serialize};
+use crate::serde_json::Value;
+use crate::task::Task;
+use crate::typetag;
+use crate::{CheckResult, Trigger, TriggerContext, TriggerMatch, TriggerType};
+use std::convert::TryFrom;
+use std::ops::Range;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+fn default_max_operations() -> u64 {
+    100_000
+}
+
+fn default_timeout() -> String {
+    "1s".into()
+}
+
+fn default_max_string_size() -> usize {
+    1024 * 1024
+}
+
+fn default_max_array_size() -> usize {
+    10_000
+}
+
+fn default_max_map_size() -> usize {
+    10_000
+}
+
+/// The result of a single script run
+#[derive(Debug, PartialEq, Clone)]
+struct ScriptResult {
+    fired: bool,
+    severity: Option<TriggerType>,
+    slice: String,
+    line: Option<i64>,
+}
+
+/// A script trigger evaluates a sandboxed Rhai script.
+///
+/// The script sees the variables
+/// text (the whole text), lines (an array of lines or records
+/// if the logfile groups lines into records)
+/// and state (a map that is kept between checks).
+/// It returns either a bool or a map of the form
+/// #{ fired: true, severity: "error", line: 3, slice: "..." }
+/// where severity, line and slice are optional.
+/// line is an index into lines (negative indices count from the end)
+/// and reports that line, or the first occurrence of slice in it.
+/// Without line the slice is the first occurrence of slice in the text.
+/// A slice that is not part of the text is empty.
+///
+/// Scripts can not import modules and print nothing.
+/// They are stopped after max_operations operations
+/// or when they run longer than the timeout (e.g. 500ms).
+/// Strings (bytes), arrays and maps (elements) can not grow larger than
+/// max_string_size, max_array_size and max_map_size.
+/// The limits are raised to fit text and lines.
+///
+/// The state is stored in the logfile's extra data.
+/// Without a context (check) every run starts with an empty state.
+/// After a check with a context the slice and type are taken from
+/// the result of that run instead of running the script again.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ScriptTrigger {
+    name: String,
+    description: String,
+    trigger_type: TriggerType,
+    script: String,
+
+    #[serde(default = "default_max_operations")]
+    max_operations: u64,
+    #[serde(default = "default_timeout")]
+    timeout: String,
+    #[serde(default = "default_max_string_size")]
+    max_string_size: usize,
+    #[serde(default = "default_max_array_size")]
+    max_array_size: usize,
+    #[serde(default = "default_max_map_size")]
+    max_map_size: usize,
+}
+
+impl ScriptTrigger {
+    pub fn new(name: &str, description: &str, trigger_type: TriggerType, script: &str) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            trigger_type,
+            script: script.into(),
+            max_operations: default_max_operations(),
+            timeout: default_timeout(),
+            max_string_size: default_max_string_size(),
+            max_array_size: default_max_array_size(),
+            max_map_size: default_max_map_size(),
+        }
+    }
+
+    pub fn with_max_operations(mut self, max_operations: u64) -> Self {
+        self.max_operations = max_operations;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: &str) -> Self {
+        self.timeout = timeout.into();
+        self
+    }
+
+    pub fn with_max_sizes(mut self, string: usize, array: usize, map: usize) -> Self {
+        self.max_string_size = string;
+        self.max_array_size = array;
+        self.max_map_size = map;
+        self
+    }
+
+    fn state_key(&self) -> String {
+        format!("script.{}", self.name)
+    }
+
+    /// an engine for a text of text_size bytes and lines lines
+    fn engine(&self, text_size: usize, lines: usize) -> Result<Engine, Error> {
+        let timeout = Duration::from_millis(Task::scan(&self.timeout)? as u64);
+        let start = Instant::now();
+
+        let mut engine = Engine::new();
+        engine
+            .set_max_operations(self.max_operations)
+            .set_max_string_size(self.max_string_size.max(text_size))
+            .set_max_array_size(self.max_array_size.max(lines))
+            .set_max_map_size(self.max_map_size)
+            .set_module_resolver(DummyModuleResolver::new())
+            .on_print(|_| {})
+            .on_debug(|_, _, _| {})
+            .on_progress(move |_| {
+                if start.elapsed() > timeout {
+                    Some("timeout".into())
+                } else {
+                    None
+                }
+            });
+        Ok(engine)
+    }
+
+    /// runs the script and returns the result and the new state
+    fn run(
+        &self,
+        text: &str,
+        records: Option<&[Range<usize>]>,
+        state: Value,
+    ) -> Result<(ScriptResult, Value), Error> {
+        let lines: Array = RecordMode::records(records, text)
+            .into_iter()
+            .map(|r| Dynamic::from(text[r].to_string()))
+            .collect();
+        let engine = self.engine(text.len(), lines.len())?;
+        let state: Dynamic =
+            crate::rhai::serde::to_dynamic(state).map_err(|e| Error::ScriptError(e.to_string()))?;
+
+        let mut scope = Scope::new();
+        scope.push("text", text.to_string());
+        scope.push("lines", lines);
+        scope.push_dynamic("state", state);
+
+        let result = engine
+            .eval_with_scope::<Dynamic>(&mut scope, &self.script)
+            .map_err(|e| Error::ScriptError(e.to_string()))?;
+
+        let state = match scope.get_value::<Dynamic>("state") {
+            Some(state) => crate::rhai::serde::from_dynamic::<Value>(&state)
+                .map_err(|e| Error::ScriptError(e.to_string()))?,
+            _ => Value::Null,
+        };
+        // indexing does not check the size, the state is kept between runs
+        if state
+            .as_object()
+            .is_some_and(|s| s.len() > self.max_map_size)
+        {
+            return Err(Error::ScriptError("Size of the state map too large".into()));
+        }
+
+        Ok((Self::result(result)?, state))
+    }
+
+    fn result(result: Dynamic) -> Result<ScriptResult, Error> {
+        if let Some(fired) = result.clone().try_cast::<bool>() {
+            return Ok(ScriptResult {
+                fired,
+                severity: None,
+                slice: "".into(),
+                line: None,
+            });
+        }
+
+        let map = match result.try_cast::<Map>() {
+            Some(map) => map,
+            _ => {
+                return Err(Error::ScriptError(
+                    "script has to return a bool or a map".into(),
+                ))
+            }
+        };
+
+        let fired = match map.get("fired") {
+            Some(fired) => fired
+                .as_bool()
+                .map_err(|_| Error::ScriptError("fired has to be a bool".into()))?,
+            _ => false,
+        };
+        let severity = match map.get("severity") {
+            Some(severity) => Some(
+                TriggerType::from_str(&severity.to_string())
+                    .map_err(|_| Error::ScriptError(format!("unknown severity {}", severity)))?,
+            ),
+            _ => None,
+        };
+        let slice = map.get("slice").map_or("".into(), |s| s.to_string());
+        let line = match map.get("line") {
+            Some(line) => Some(
+                line.as_int()
+                    .map_err(|_| Error::ScriptError("line has to be an integer".into()))?,
+            ),
+            _ => None,
+        };
+
+        Ok(ScriptResult {
+            fired,
+            severity,
+            slice,
+            line,
+        })
+    }
+
+    /// the byte range of the slice of a result in the text
+    fn range(text: &str, records: Option<&[Range<usize>]>, result: &ScriptResult) -> Range<usize> {
+        let scope = match result.line {
+            Some(line) => {
+                let lines = RecordMode::records(records, text);
+                let index = if line < 0 {
+                    lines.len() as i64 + line
+                } else {
+                    line
+                };
+                match usize::try_from(index).ok().and_then(|i| lines.get(i)) {
+                    Some(scope) => scope.clone(),
+                    _ => return 0..0,
+                }
+            }
+            _ => 0..text.len(),
+        };
+
+        if result.slice.is_empty() {
+            return match result.line {
+                Some(_) => scope,
+                _ => 0..0,
+            };
+        }
+        match text[scope.clone()].find(&result.slice) {
+            Some(start) => scope.start + start..scope.start + start + result.slice.len(),
+            _ => 0..0,
+        }
+    }
+
+    /// runs the script without a context and state
+    fn check_result(&self, text: &str) -> Result<CheckResult, Error> {
+        let (result, _) = self.run(text, None, Value::Null)?;
+        Ok(CheckResult {
+            fired: result.fired,
+            trigger_type: result.severity,
+            slice: Some(Self::range(text, None, &result)),
+        })
+    }
+}
+
+#[typetag::serde]
+impl Trigger for ScriptTrigger {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn check(&self, text: &str) -> Result<bool, Error> {
+        Ok(self.check_result(text)?.fired)
+    }
+
+    fn check_ctx(&self, text: &str, ctx: &mut TriggerContext) -> Result<bool, Error> {
+        let state = ctx
+            .extra
+            .get::<Value>(&self.state_key(), ExtraData::deserialize)
+            .unwrap_or(Value::Null);
+
+        let (result, state) = self.run(text, ctx.records, state)?;
+        ctx.extra
+            .put(&self.state_key(), &state, ExtraData::serialize)?;
+        ctx.results.insert(
+            self.name.clone(),
+            CheckResult {
+                fired: result.fired,
+                trigger_type: result.severity,
+                slice: Some(Self::range(text, ctx.records, &result)),
+            },
+        );
+        Ok(result.fired)
+    }
+
+    fn slice<'a>(&self, text: &'a str) -> Result<&'a str, Error> {
+        let range = self.check_result(text)?.slice.unwrap_or(0..0);
+        Ok(&text[range])
+    }
+
+    fn slice_ctx<'a>(&self, text: &'a str, ctx: &TriggerContext) -> Result<&'a str, Error> {
+        match ctx.result(&self.name).and_then(|r| r.slice.clone()) {
+            Some(range) => Ok(&text[range]),
+            _ => self.slice(text),
+        }
+    }
+
+    fn matches_ctx(&self, text: &str, ctx: &TriggerContext) -> Result<Vec<TriggerMatch>, Error> {
+        Ok(TriggerMatch::from_slice(text, self.slice_ctx(text, ctx)?)
+            .into_iter()
+            .collect())
+    }
+
+    fn get_type(&self) -> TriggerType {
+        self.trigger_type
+    }
+
+    fn get_type_for(&self, text: &str) -> Result<TriggerType, Error> {
+        Ok(self
+            .check_result(text)?
+            .trigger_type
+            .unwrap_or(self.trigger_type))
+    }
+
+    fn get_type_for_ctx(&self, text: &str, ctx: &TriggerContext) -> Result<TriggerType, Error> {
+        match ctx.result(&self.name) {
+            Some(result) => Ok(result.trigger_type.unwrap_or(self.trigger_type)),
+            _ => self.get_type_for(text),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn it_should_return_bools() {
+        let t = ScriptTrigger::new(
+            "name",
+            "desc",
+            TriggerType::Error,
+            r#"lines.len() > 2 && text.contains("error")"#,
+        );
+        assert!(t.check("a\nb\nerror").unwrap());
+        assert!(!t.check("a\nerror").unwrap());
+        assert_eq!(t.slice("a\nb\nerror").unwrap(), "");
+    }
+
+    #[test]
+    fn it_should_return_maps() {
+        let t = ScriptTrigger::new(
+            "name",
+            "desc",
+            TriggerType::Warning,
+            r#"
+            let failed = lines.filter(|l| l.starts_with("FAIL"));
+            if failed.len() >= 2 {
+                #{ fired: true, severity: "critical", slice: failed[-1] }
+            } else {
+                #{ fired: failed.len() > 0, slice: "missing" }
+            }
+            "#,
+        );
+        let text = "FAIL a\nok\nFAIL b";
+        assert!(t.check(text).unwrap());
+        assert_eq!(t.get_type_for(text).unwrap(), TriggerType::Critical);
+        assert_eq!(t.slice(text).unwrap(), "FAIL b");
+
+        let text = "FAIL a\nok";
+        assert!(t.check(text).unwrap());
+        assert_eq!(t.get_type_for(text).unwrap(), TriggerType::Warning);
+        assert_eq!(t.slice(text).unwrap(), "");
+    }
+
+    #[test]
+    fn it_should_keep_state() {
+        let t = ScriptTrigger::new(
+            "counter",
+            "desc",
+            TriggerType::Error,
+            r#"
+            if state == () { state = #{ runs: 0 }; }
+            state.runs += 1;
+            state.runs >= 3
+            "#,
+        );
+        let mut extra = ExtraData::new();
+        let mut ctx = TriggerContext::new(0, &mut extra);
+        assert!(!t.check_ctx("", &mut ctx).unwrap());
+        assert!(!t.check_ctx("", &mut ctx).unwrap());
+        assert!(t.check_ctx("", &mut ctx).unwrap());
+
+        // without context the state is empty
+        assert!(!t.check("").unwrap());
+    }
+
+    #[test]
+    fn it_should_slice_lines() {
+        let t = ScriptTrigger::new(
+            "name",
+            "desc",
+            TriggerType::Error,
+            r#"#{ fired: true, line: -1, slice: "FAIL" }"#,
+        );
+        let text = "FAIL a\nok\nFAIL a";
+        let slice = t.slice(text).unwrap();
+        assert_eq!(slice, "FAIL");
+        // the last line, not the first occurrence in the text
+        assert_eq!(TriggerMatch::from_slice(text, slice).unwrap().line, 3);
+
+        let t = ScriptTrigger::new(
+            "name",
+            "desc",
+            TriggerType::Error,
+            "#{ fired: true, line: 1 }",
+        );
+        assert_eq!(t.slice(text).unwrap(), "ok");
+        let t = ScriptTrigger::new(
+            "name",
+            "desc",
+            TriggerType::Error,
+            "#{ fired: true, line: 5 }",
+        );
+        assert_eq!(t.slice(text).unwrap(), "");
+    }
+
+    #[test]
+    fn it_should_report_the_result_of_the_check() {
+        // every run reports a different line
+        let t = ScriptTrigger::new(
+            "counter",
+            "desc",
+            TriggerType::Warning,
+            r#"
+            if state == () { state = 0; }
+            state += 1;
+            #{ fired: true, severity: if state > 1 { "critical" } else { "info" }, line: state }
+            "#,
+        );
+        let text = "a\nb\nc";
+        let mut extra = ExtraData::new();
+        let mut ctx = TriggerContext::new(0, &mut extra);
+        assert!(t.check_ctx(text, &mut ctx).unwrap());
+        assert!(t.check_ctx(text, &mut ctx).unwrap());
+        assert_eq!(t.slice_ctx(text, &ctx).unwrap(), "c");
+        assert_eq!(t.matches_ctx(text, &ctx).unwrap()[0].line, 3);
+        assert_eq!(
+            t.get_type_for_ctx(text, &ctx).unwrap(),
+            TriggerType::Critical
+        );
+
+        // without a context the script runs with an empty state
+        assert_eq!(t.slice(text).unwrap(), "b");
+        assert_eq!(t.get_type_for(text).unwrap(), TriggerType::Info);
+    }
+
+    #[test]
+    fn it_should_limit_operations() {
+        let t = ScriptTrigger::new("name", "desc", TriggerType::Error, "loop {}")
+            .with_max_operations(1000);
+        assert!(matches!(t.check(""), Err(Error::ScriptError(_))));
+
+        let t = ScriptTrigger::new("name", "desc", TriggerType::Error, "loop {}")
+            .with_max_operations(0)
+            .with_timeout("50ms");
+        assert!(matches!(t.check(""), Err(Error::ScriptError(_))));
+    }
+
+    #[test]
+    fn it_should_limit_sizes() {
+        let grow = |script: &str| {
+            ScriptTrigger::new("name", "desc", TriggerType::Error, script)
+                .with_max_sizes(1000, 100, 100)
+        };
+        let string = grow(r#"let s = "x"; for i in 0..30 { s += s; } true"#);
+        assert!(matches!(string.check(""), Err(Error::ScriptError(_))));
+        let array = grow("let a = [1]; for i in 0..30 { a += a; } true");
+        assert!(matches!(array.check(""), Err(Error::ScriptError(_))));
+        let map = grow("let m = #{}; for i in 0..200 { m[`k${i}`] = i; } m.len() > 0");
+        assert!(matches!(map.check(""), Err(Error::ScriptError(_))));
+        let state = grow("for i in 0..200 { state[`k${i}`] = i; } true");
+        assert!(matches!(state.check(""), Err(Error::ScriptError(_))));
+
+        // the inputs always fit
+        let text = "a\n".repeat(1000);
+        let t = grow("text.len() == 2000 && lines.len() == 1000");
+        assert!(t.check(&text).unwrap());
+    }
+
+    #[test]
+    fn it_should_report_script_errors() {
+        let t = ScriptTrigger::new("name", "desc", TriggerType::Error, "let x = ;");
+        assert!(matches!(t.check(""), Err(Error::ScriptError(_))));
+
+        let t = ScriptTrigger::new("name", "desc", TriggerType::Error, "42");
+        assert!(matches!(t.check(""), Err(Error::ScriptError(_))));
+
+        let t = ScriptTrigger::new(
+            "name",
+            "desc",
+            TriggerType::Error,
+            r#"import "fs" as fs; true"#,
+        );
+        assert!(matches!(t.check(""), Err(Error::ScriptError(_))));
+
+        let t = ScriptTrigger::new(
+            "name",
+            "desc",
+            TriggerType::Error,
+            r#"#{ fired: true, severity: "bad" }"#,
+        );
+        assert!(matches!(t.check(""), Err(Error::ScriptError(_))));
+    }
+}
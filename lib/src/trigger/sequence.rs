@@ -0,0 +1,399 @@
+use crate::error::Error;
+use crate::extra::ExtraData;
+use crate::regex::Regex;
+use crate::serde::{Deserialize, Serialize};
+use crate::task::{Task, TimeMs};
+use crate::typetag;
+use crate::{Trigger, TriggerContext, TriggerType};
+use std::collections::HashMap;
+
+/// SequenceMode decides if the trigger fires when
+/// the second pattern follows the first one in time,
+/// or when it does not
+#[derive(Debug, Eq, PartialEq, Serialize, Deserialize, Copy, Clone)]
+pub enum SequenceMode {
+    FollowedBy,
+    NotFollowedBy,
+}
+
+/// The state of a single correlation key
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, Clone)]
+struct SequenceEntry {
+    /// when the first pattern was seen
+    started: TimeMs,
+    /// when the second pattern was seen after the first one
+    finished: Option<TimeMs>,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize, Clone)]
+struct SequenceState {
+    entries: HashMap<String, SequenceEntry>,
+}
+
+/// A sequence trigger correlates two patterns over time.
+/// In FollowedBy mode it fires when the second pattern
+/// is seen within the time span after the first one.
+/// In NotFollowedBy mode it fires when the second pattern
+/// was not seen within the time span after the first one.
+///
+/// If key is set it names a capture group that has to be present
+/// in both patterns (e.g. a job id). Only lines with
+/// the same key are correlated.
+///
+/// Times are taken from the check context and state is stored
+/// in the logfile's extra data. A sequence is tracked
+/// for as long as its first line is part of the text.
+/// Without a context (check) the trigger only looks at the text.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SequenceTrigger {
+    name: String,
+    description: String,
+    trigger_type: TriggerType,
+    first: String,
+    second: String,
+    within: String,
+    mode: SequenceMode,
+
+    #[serde(default)]
+    key: Option<String>,
+}
+
+impl SequenceTrigger {
+    pub fn new(
+        name: &str,
+        description: &str,
+        trigger_type: TriggerType,
+        first: &str,
+        second: &str,
+        within: &str,
+        mode: SequenceMode,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            trigger_type,
+            first: first.into(),
+            second: second.into(),
+            within: within.into(),
+            mode,
+            key: None,
+        }
+    }
+
+    pub fn with_key(mut self, key: &str) -> Self {
+        self.key = Some(key.into());
+        self
+    }
+
+    fn state_key(&self) -> String {
+        format!("sequence.{}", self.name)
+    }
+
+    /// returns all matches of a pattern
+    /// as (key, start, end)
+    fn find(&self, re: &str, text: &str) -> Result<Vec<(String, usize, usize)>, Error> {
+        let re = Regex::new(re)?;
+        let mut result = vec![];
+        for captures in re.captures_iter(text) {
+            let m = match captures.get(0) {
+                Some(m) => m,
+                _ => continue,
+            };
+            let key = match &self.key {
+                Some(key) => captures.name(key).map_or("", |k| k.as_str()),
+                _ => "",
+            };
+            result.push((key.to_string(), m.start(), m.end()));
+        }
+        Ok(result)
+    }
+
+    /// returns the last start of the first pattern for every key
+    /// and if the second pattern followed it in the text
+    fn scan(&self, text: &str) -> Result<HashMap<String, (usize, usize, bool)>, Error> {
+        let mut result = HashMap::new();
+        for (key, start, end) in self.find(&self.first, text)? {
+            result.insert(key, (start, end, false));
+        }
+        for (key, start, _) in self.find(&self.second, text)? {
+            if let Some(entry) = result.get_mut(&key) {
+                if start >= entry.1 {
+                    entry.2 = true;
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    fn fired(&self, entry: &SequenceEntry, now: TimeMs, within: TimeMs) -> bool {
+        match (self.mode, entry.finished) {
+            (SequenceMode::FollowedBy, Some(finished)) => {
+                finished.saturating_sub(entry.started) <= within
+            }
+            (SequenceMode::FollowedBy, None) => false,
+            (SequenceMode::NotFollowedBy, Some(_)) => false,
+            (SequenceMode::NotFollowedBy, None) => now.saturating_sub(entry.started) > within,
+        }
+    }
+}
+
+#[typetag::serde]
+impl Trigger for SequenceTrigger {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    /// checks the text without any state
+    fn check(&self, text: &str) -> Result<bool, Error> {
+        self.check_ctx(text, &mut TriggerContext::new(0, &mut ExtraData::new()))
+    }
+
+    fn check_ctx(&self, text: &str, ctx: &mut TriggerContext) -> Result<bool, Error> {
+        let within = Task::scan(&self.within)?;
+        let mut state = ctx
+            .extra
+            .get::<SequenceState>(&self.state_key(), ExtraData::deserialize)
+            .unwrap_or_default();
+
+        let scanned = self.scan(text)?;
+
+        // forget sequences that are no longer in the text
+        state.entries.retain(|key, _| scanned.contains_key(key));
+
+        let mut fired = false;
+        for (key, (_, _, followed)) in scanned {
+            let entry = state.entries.entry(key).or_insert(SequenceEntry {
+                started: ctx.time,
+                finished: None,
+            });
+
+            match (followed, entry.finished) {
+                (true, None) => entry.finished = Some(ctx.time),
+                // the first pattern occurred again, start over
+                (false, Some(_)) => {
+                    entry.started = ctx.time;
+                    entry.finished = None;
+                }
+                _ => {}
+            }
+
+            fired |= self.fired(entry, ctx.time, within);
+        }
+
+        ctx.extra
+            .put(&self.state_key(), &state, ExtraData::serialize)?;
+        Ok(fired)
+    }
+
+    /// returns the last match of the second pattern in FollowedBy mode
+    /// and the last unfollowed match of the first pattern in NotFollowedBy mode
+    fn slice<'a>(&self, text: &'a str) -> Result<&'a str, Error> {
+        let scanned = self.scan(text)?;
+        let found = match self.mode {
+            SequenceMode::FollowedBy => self
+                .find(&self.second, text)?
+                .into_iter()
+                .filter(|(key, start, _)| {
+                    scanned
+                        .get(key)
+                        .is_some_and(|(_, end, followed)| *followed && start >= end)
+                })
+                .map(|(_, start, end)| (start, end))
+                .next_back(),
+            SequenceMode::NotFollowedBy => scanned
+                .values()
+                .filter(|(_, _, followed)| !followed)
+                .map(|(start, end, _)| (*start, *end))
+                .max(),
+        };
+        match found {
+            Some((start, end)) => Ok(&text[start..end]),
+            _ => Ok(&text[0..0]),
+        }
+    }
+
+    fn get_type(&self) -> TriggerType {
+        self.trigger_type
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::logfile::{Event, EventHandler, Logfile};
+    use crate::source::{DataSourceTypes, InMemoryDataSource};
+    use crate::task::{InMemoryTimeSource, TimeSourceTypes};
+    use crate::TriggerTypes;
+
+    struct TestHandler(Vec<bool>);
+    impl EventHandler for TestHandler {
+        fn on_event(&mut self, event: &Event) {
+            self.0.push(event.did_trigger);
+        }
+    }
+
+    /// runs every text through a logfile
+    /// checks happen at the given times
+    async fn run(trigger: SequenceTrigger, texts: Vec<&str>, times: Vec<TimeMs>) -> Vec<bool> {
+        // data and time sources pop from the back
+        let mut data: Vec<String> = texts.iter().map(|t| t.to_string()).collect();
+        data.reverse();
+        // every update reads the time twice and the task is started at 0
+        let mut time_values = vec![0];
+        for t in &times {
+            time_values.push(*t);
+            time_values.push(*t);
+        }
+        time_values.reverse();
+
+        let mut lf = Logfile::new(
+            "test",
+            DataSourceTypes::InMemory(InMemoryDataSource::new(data)),
+            Task::new(
+                true,
+                0,
+                TimeSourceTypes::InMemory(InMemoryTimeSource::new(time_values)),
+            ),
+        );
+        lf.push(TriggerTypes::Sequence(trigger));
+
+        let mut handler = TestHandler(vec![]);
+        for _ in &times {
+            lf.update(&mut vec![&mut handler]).await.unwrap();
+        }
+        handler.0
+    }
+
+    #[tokio::test]
+    async fn it_should_fire_when_not_followed() {
+        let t = SequenceTrigger::new(
+            "backup",
+            "backup did not finish",
+            TriggerType::Error,
+            "backup started",
+            "backup finished",
+            "2h",
+            SequenceMode::NotFollowedBy,
+        );
+
+        let fired = run(
+            t,
+            vec![
+                "backup started",
+                "backup started\nworking",
+                "backup started\nworking",
+                "backup started\nworking\nbackup finished",
+            ],
+            vec![1000, 3600000, 7201001, 7300000],
+        )
+        .await;
+        assert_eq!(fired, vec![false, false, true, false]);
+    }
+
+    #[tokio::test]
+    async fn it_should_fire_when_followed() {
+        let t = SequenceTrigger::new(
+            "lockout",
+            "login failure caused a lockout",
+            TriggerType::Warning,
+            "login failed",
+            "account locked",
+            "1m",
+            SequenceMode::FollowedBy,
+        );
+
+        let fired = run(
+            t.clone(),
+            vec!["login failed", "login failed\naccount locked"],
+            vec![1000, 30000],
+        )
+        .await;
+        assert_eq!(fired, vec![false, true]);
+
+        // too late
+        let fired = run(
+            t,
+            vec!["login failed", "login failed\naccount locked"],
+            vec![1000, 120000],
+        )
+        .await;
+        assert_eq!(fired, vec![false, false]);
+    }
+
+    #[tokio::test]
+    async fn it_should_correlate_keys() {
+        let t = SequenceTrigger::new(
+            "job",
+            "job did not finish",
+            TriggerType::Error,
+            r"job (?P<id>\d+) started",
+            r"job (?P<id>\d+) finished",
+            "10s",
+            SequenceMode::NotFollowedBy,
+        )
+        .with_key("id");
+
+        let fired = run(
+            t.clone(),
+            vec![
+                "job 1 started\njob 2 started",
+                "job 1 started\njob 2 started\njob 2 finished",
+                "job 1 started\njob 2 started\njob 2 finished\njob 1 finished",
+            ],
+            vec![1000, 20000, 30000],
+        )
+        .await;
+        assert_eq!(fired, vec![false, true, false]);
+
+        let text = "job 1 started\njob 2 started\njob 2 finished";
+        assert_eq!(t.slice(text).unwrap(), "job 1 started");
+    }
+
+    #[tokio::test]
+    async fn it_should_restart_sequences() {
+        let t = SequenceTrigger::new(
+            "backup",
+            "backup did not finish",
+            TriggerType::Error,
+            "backup started",
+            "backup finished",
+            "10s",
+            SequenceMode::NotFollowedBy,
+        );
+
+        let fired = run(
+            t,
+            vec![
+                "backup started\nbackup finished",
+                "backup started\nbackup finished\nbackup started",
+                "backup started\nbackup finished\nbackup started",
+            ],
+            vec![1000, 5000, 16000],
+        )
+        .await;
+        assert_eq!(fired, vec![false, false, true]);
+    }
+
+    #[test]
+    fn it_should_check_without_context() {
+        let t = SequenceTrigger::new(
+            "lockout",
+            "desc",
+            TriggerType::Warning,
+            "login failed",
+            "account locked",
+            "1m",
+            SequenceMode::FollowedBy,
+        );
+        assert!(t.check("login failed\naccount locked").unwrap());
+        assert!(!t.check("account locked\nlogin failed").unwrap());
+        assert_eq!(
+            t.slice("login failed\naccount locked").unwrap(),
+            "account locked"
+        );
+    }
+}
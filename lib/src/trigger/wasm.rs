@@ -0,0 +1,167 @@
+use crate::error::Error;
+use crate::plugin::{default_fuel, default_max_memory, WasmCache, WasmPlugin};
+use crate::serde::{Deserialize, Serialize};
+use crate::typetag;
+use crate::{Trigger, TriggerType};
+
+/// A wasm trigger calls a .wasm plugin (see WasmPlugin for the ABI).
+/// The plugin exports
+///   - check(ptr: i32, len: i32) -> i32, non-zero if the trigger fired
+///   - slice(ptr: i32, len: i32) -> i64 (optional), the packed (offset, len)
+///     of the slice in the text
+///
+/// The module is compiled once and again when the file is modified.
+/// Every call runs in a new instance that may use fuel
+/// and grow its memory up to max_memory bytes.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct WasmTrigger {
+    name: String,
+    description: String,
+    trigger_type: TriggerType,
+    path: String,
+
+    #[serde(default = "default_fuel")]
+    fuel: u64,
+    #[serde(default = "default_max_memory")]
+    max_memory: usize,
+
+    #[serde(skip)]
+    module: WasmCache,
+}
+
+impl WasmTrigger {
+    pub fn new(name: &str, description: &str, trigger_type: TriggerType, path: &str) -> Self {
+        Self {
+            name: name.into(),
+            description: description.into(),
+            trigger_type,
+            path: path.into(),
+            fuel: default_fuel(),
+            max_memory: default_max_memory(),
+            module: WasmCache::new(),
+        }
+    }
+
+    pub fn with_fuel(mut self, fuel: u64) -> Self {
+        self.fuel = fuel;
+        self
+    }
+
+    pub fn with_max_memory(mut self, max_memory: usize) -> Self {
+        self.max_memory = max_memory;
+        self
+    }
+
+    fn plugin(&self) -> Result<WasmPlugin, Error> {
+        self.module
+            .module(&self.path)?
+            .instantiate(self.fuel, self.max_memory)
+    }
+}
+
+#[typetag::serde]
+impl Trigger for WasmTrigger {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> &str {
+        &self.description
+    }
+
+    fn check(&self, text: &str) -> Result<bool, Error> {
+        let mut plugin = self.plugin()?;
+        Ok(plugin.call_str::<i32>("check", text)? != 0)
+    }
+
+    fn slice<'a>(&self, text: &'a str) -> Result<&'a str, Error> {
+        let mut plugin = self.plugin()?;
+        if !plugin.exports("slice") {
+            return Ok(&text[0..0]);
+        }
+
+        match WasmPlugin::unpack(plugin.call_str::<i64>("slice", text)?) {
+            Some((start, len)) => match text.get(start..start + len) {
+                Some(slice) => Ok(slice),
+                _ => Err(Error::PluginError("slice is out of bounds".into())),
+            },
+            _ => Ok(&text[0..0]),
+        }
+    }
+
+    fn get_type(&self) -> TriggerType {
+        self.trigger_type
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// fires on the first '!' and reports it as the slice
+    const PLUGIN: &str = r#"(module
+  (memory (export "memory") 1)
+  (global $heap (mut i32) (i32.const 1024))
+  (func (export "alloc") (param $len i32) (result i32)
+    (local $ptr i32)
+    (local.set $ptr (global.get $heap))
+    (global.set $heap (i32.add (global.get $heap) (local.get $len)))
+    (local.get $ptr))
+  (func $find (param $ptr i32) (param $len i32) (result i32)
+    (local $i i32)
+    (block $done
+      (loop $next
+        (br_if $done (i32.ge_u (local.get $i) (local.get $len)))
+        (if (i32.eq (i32.load8_u (i32.add (local.get $ptr) (local.get $i))) (i32.const 33))
+          (then (return (local.get $i))))
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br $next)))
+    (i32.const -1))
+  (func (export "check") (param i32 i32) (result i32)
+    (i32.ge_s (call $find (local.get 0) (local.get 1)) (i32.const 0)))
+  (func (export "slice") (param i32 i32) (result i64)
+    (local $i i32)
+    (local.set $i (call $find (local.get 0) (local.get 1)))
+    (if (result i64) (i32.lt_s (local.get $i) (i32.const 0))
+      (then (i64.const -1))
+      (else (i64.or
+        (i64.shl (i64.extend_i32_u (local.get $i)) (i64.const 32))
+        (i64.const 1))))))"#;
+
+    const LOOP: &str = r#"(module
+  (memory (export "memory") 1)
+  (func (export "alloc") (param i32) (result i32) (i32.const 0))
+  (func (export "check") (param i32 i32) (result i32)
+    (loop $forever (br $forever))
+    (i32.const 0)))"#;
+
+    fn write(name: &str, wat: &str) -> String {
+        let path = std::env::temp_dir().join(name);
+        std::fs::write(&path, wat::parse_str(wat).unwrap()).unwrap();
+        path.to_str().unwrap().into()
+    }
+
+    #[test]
+    fn it_should_call_plugins() {
+        let path = write("minutecat_trigger_test.wasm", PLUGIN);
+        let t = WasmTrigger::new("name", "desc", TriggerType::Error, &path);
+
+        assert!(t.check("something failed!").unwrap());
+        assert_eq!(t.slice("something failed!").unwrap(), "!");
+        assert!(!t.check("all good").unwrap());
+        assert_eq!(t.slice("all good").unwrap(), "");
+    }
+
+    #[test]
+    fn it_should_run_out_of_fuel() {
+        let path = write("minutecat_trigger_loop.wasm", LOOP);
+        let t = WasmTrigger::new("name", "desc", TriggerType::Error, &path).with_fuel(1000);
+        assert!(matches!(t.check("text"), Err(Error::PluginError(_))));
+    }
+
+    #[test]
+    fn it_should_fail_on_missing_modules() {
+        let t = WasmTrigger::new("name", "desc", TriggerType::Error, "/does/not/exist.wasm");
+        assert!(t.check("text").is_err());
+    }
+}